@@ -0,0 +1,205 @@
+//! Timings for large-file mode on generated input: opening a file, scrolling through
+//! it, drawing it with Find & Replace open and Replace All. Run with `cargo bench --bench large_file [MEGABYTES]`.
+
+use dnotepadx::document::{DocumentDefaults, Documents, Selection};
+use dnotepadx::file_operations::FileOperations;
+use dnotepadx::search::SearchState;
+use dnotepadx::text_format::{Indentation, LineEnding};
+use dnotepadx::undo::Edit;
+use dnotepadx::viewport::{self, ViewportStyle};
+use dnotepadx::{gutter, indent};
+use eframe::egui::{self, Color32, Event, FontId, MouseWheelUnit, RawInput};
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+fn main() {
+    let megabytes: usize = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(200);
+
+    let mut text = String::with_capacity(megabytes << 20);
+    let mut line = 0;
+    while text.len() < megabytes << 20 {
+        line += 1;
+        let _ = writeln!(
+            text,
+            "2024-05-{:02} 12:{:02}:{:02} INFO worker-{} request {} served in {} ms",
+            line % 28 + 1,
+            line % 60,
+            line % 59,
+            line % 16,
+            line,
+            line % 997
+        );
+    }
+    let path = std::env::temp_dir().join(format!("dnotepadx-bench-{}.log", std::process::id()));
+    std::fs::write(&path, &text).expect("writing the input file");
+    println!("input: {} MB, {} lines", megabytes, line);
+
+    let mut documents = Documents::new(defaults());
+    let started = Instant::now();
+    let file = FileOperations::read_file(&path).expect("reading the input file");
+    let read = started.elapsed();
+    documents.open(path.clone(), file);
+    report("load: read and decode", read);
+    report("load: total until shown", started.elapsed());
+    let _ = std::fs::remove_file(&path);
+
+    let screen = Screen::new();
+    scroll(&screen, &mut documents);
+    find_open(&screen, &mut documents, "request");
+    replace_all(&mut documents, "request", "req");
+}
+
+/// An egui context drawing the large-file viewport the way the editor does.
+struct Screen {
+    ctx: egui::Context,
+    rect: egui::Rect,
+    style: ViewportStyle,
+    indent: indent::IndentOptions,
+}
+
+impl Screen {
+    fn new() -> Self {
+        let screen = Self {
+            ctx: egui::Context::default(),
+            rect: egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(1280.0, 800.0)),
+            style: ViewportStyle {
+                font_id: FontId::monospace(14.0),
+                text_color: Color32::WHITE,
+                selection: Color32::BLUE,
+                match_color: Color32::DARK_BLUE,
+                scrollbar: Color32::GRAY,
+                ruler: Color32::DARK_GRAY,
+                gutter: Some(gutter::GutterStyle {
+                    font_id: FontId::monospace(14.0),
+                    number_color: Color32::GRAY,
+                    current_color: Color32::WHITE,
+                    background: Color32::BLACK,
+                    relative: false,
+                }),
+            },
+            indent: indent::IndentOptions::new(Indentation::spaces(4), true, true, "Plain Text"),
+        };
+        // Warm up the font atlas
+        screen.frame(Vec::new(), &mut Documents::new(defaults()), None);
+        screen
+    }
+
+    /// One frame, laid out and tessellated. With `search`, the Find & Replace window is
+    /// open: the matches are refreshed and highlighted as in the editor.
+    fn frame(&self, events: Vec<Event>, documents: &mut Documents, search: Option<&mut SearchState>) {
+        let input = RawInput {
+            screen_rect: Some(self.rect),
+            events,
+            ..Default::default()
+        };
+        let mut search = search;
+        let output = self.ctx.run(input, |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                let matches = match &mut search {
+                    Some(search) => {
+                        search.refresh_document(documents.active());
+                        search.matches()
+                    }
+                    None => &[],
+                };
+                viewport::show(ui, documents.active_mut(), &self.style, &self.indent, matches);
+            });
+        });
+        self.ctx.tessellate(output.shapes, output.pixels_per_point);
+    }
+}
+
+fn defaults() -> DocumentDefaults {
+    DocumentDefaults {
+        undo_depth: 1000,
+        indentation: Indentation::spaces(4),
+        line_ending: LineEnding::Lf,
+        large_file_bytes: 8 << 20,
+    }
+}
+
+/// Frames of wheel scrolling and of jumps across the file.
+fn scroll(screen: &Screen, documents: &mut Documents) {
+    let center = Event::PointerMoved(screen.rect.center());
+    screen.frame(vec![center.clone()], documents, None);
+
+    let frames = 500;
+    let started = Instant::now();
+    for _ in 0..frames {
+        let wheel = Event::MouseWheel {
+            unit: MouseWheelUnit::Line,
+            delta: egui::vec2(0.0, -3.0),
+            modifiers: Default::default(),
+        };
+        screen.frame(vec![center.clone(), wheel], documents, None);
+    }
+    report("scroll: wheel, per frame", started.elapsed() / frames);
+
+    let lines = documents.active().line_count();
+    let started = Instant::now();
+    for i in 0..frames as usize {
+        documents.active_mut().go_to(i * 7919 % lines + 1, 1);
+        screen.frame(Vec::new(), documents, None);
+    }
+    report("scroll: jump, per frame", started.elapsed() / frames);
+}
+
+/// Frames with the Find & Replace window open: the first one searches, later ones
+/// reuse the matches until an edit makes them stale.
+fn find_open(screen: &Screen, documents: &mut Documents, find: &str) {
+    let mut search = SearchState::default();
+    search.find_text = find.to_string();
+    let started = Instant::now();
+    screen.frame(Vec::new(), documents, Some(&mut search));
+    report("find open: first frame", started.elapsed());
+    println!("{:<40} {:>10}", "find open: matches", search.matches().len());
+
+    let frames = 500;
+    let started = Instant::now();
+    for _ in 0..frames {
+        screen.frame(Vec::new(), documents, Some(&mut search));
+    }
+    report("find open: per frame", started.elapsed() / frames);
+
+    let edits = 5;
+    let started = Instant::now();
+    for _ in 0..edits {
+        let doc = documents.active_mut();
+        let at = doc.cursor.head;
+        doc.edit(vec![Edit::new(at, "", "x")], Selection::caret(at + 1));
+        screen.frame(Vec::new(), documents, Some(&mut search));
+    }
+    report("find open: frame after an edit", started.elapsed() / edits);
+    for _ in 0..edits {
+        documents.active_mut().undo();
+    }
+}
+
+/// Replace All as the Find & Replace window does it, then undo it.
+fn replace_all(documents: &mut Documents, find: &str, replace: &str) {
+    let mut search = SearchState::default();
+    search.find_text = find.to_string();
+    search.replace_text = replace.to_string();
+    let started = Instant::now();
+    search.refresh_document(documents.active());
+    report("replace all: search", started.elapsed());
+
+    let started = Instant::now();
+    let query = search.query().expect("a valid pattern");
+    let doc = documents.active_mut();
+    let (edits, _) = query.replace_all_edits(&doc.text(), search.matches(), &search.replace_text);
+    let replacements = edits.len();
+    doc.edit(edits, Selection::default());
+    report(&format!("replace all: {} replacements", replacements), started.elapsed());
+
+    let started = Instant::now();
+    doc.undo();
+    report("replace all: undo", started.elapsed());
+}
+
+fn report(what: &str, time: Duration) {
+    println!("{:<40} {:>10.2?}", what, time);
+}
@@ -1,47 +1,212 @@
-use crate::file_operations::FileOperations;
-use crate::settings::Settings;
-use crate::theme::{create_font_id, get_available_fonts, Theme};
-use eframe::egui::{self, Margin, Rounding, Stroke, TextEdit, Ui};
+use dnotepadx::cli::FileArg;
+use dnotepadx::document::{Document, DocumentDefaults, Documents, ExternalChange, LineTarget, Selection, TextStats};
+use dnotepadx::editorconfig::EditorConfig;
+use dnotepadx::file_operations::{BackupPolicy, CloseChoice, FileOperations, LoadedFile, Opened};
+use dnotepadx::fonts::{self, FontLibrary};
+use dnotepadx::gutter::{self, GutterStyle};
+use dnotepadx::hex::{self, HexStyle};
+use dnotepadx::highlight::{self, Mark};
+use dnotepadx::indent::{self, IndentOptions};
+use dnotepadx::instance::{Listener, Waiter};
+use dnotepadx::keybindings::{Action, KeyChord};
+use dnotepadx::mapped::{MappedFile, SearchStatus};
+use dnotepadx::pager::{self, PagerStyle};
+use dnotepadx::recovery::{self, Journal, RecoveredBuffer};
+use dnotepadx::search::{byte_regex, match_ordinal, SearchState};
+use dnotepadx::session::{Session, SessionDocument};
+use dnotepadx::settings::Settings;
+use dnotepadx::syntax::{Highlighter, TokenKind};
+use dnotepadx::text_format::{Encoding, FileFormat, Indentation, LineEnding};
+use dnotepadx::theme::{Theme, AA_CONTRAST};
+use dnotepadx::theme_files::{self, ThemeFile};
+use dnotepadx::undo::{Edit, RecordingBuffer};
+use dnotepadx::viewport::{self, ViewportStyle};
+use dnotepadx::watcher::FileWatcher;
+use eframe::egui::text::{CCursor, CCursorRange};
+use eframe::egui::{self, Color32, FontId, Key, Margin, Modifiers, RichText, Rounding, Stroke, TextEdit, TextFormat, Ui};
+use similar::{ChangeTag, TextDiff};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 pub struct DNotepadX {
     settings: Settings,
-    content: String,
-    current_file: Option<PathBuf>,
-    is_modified: bool,
+    documents: Documents,
     show_settings: bool,
     show_find_replace: bool,
-    find_text: String,
-    replace_text: String,
+    search: SearchState,
     temp_theme: Theme,
     temp_font_family: String,
     temp_font_size: f32,
     temp_word_wrap: bool,
-    cursor_position: Option<usize>,
+    temp_undo_depth: usize,
+    temp_large_file_threshold: u64,
+    temp_viewer_threshold: u64,
+    temp_keybindings: BTreeMap<Action, String>,
+    temp_auto_save: bool,
+    temp_auto_save_interval: u64,
+    temp_auto_save_idle: u64,
+    temp_backup_policy: BackupPolicy,
+    temp_default_line_ending: LineEnding,
+    temp_restore_session: bool,
+    temp_tab_size: usize,
+    temp_insert_spaces: bool,
+    temp_auto_indent: bool,
+    temp_smart_indent: bool,
+    journal: Journal,
+    recovered: Vec<RecoveredBuffer>,
+    highlighter: Highlighter,
+    /// The fonts offered in the settings.
+    fonts: FontLibrary,
+    /// The themes in the themes directory, listed after the built-in ones.
+    user_themes: Vec<ThemeFile>,
+    /// Receives files from later `dnotepadx` invocations.
+    listener: Option<Listener>,
+    /// Groups of documents opened with `--wait`.
+    waits: Vec<Wait>,
+    /// Created on the first frame, once there is a context to wake up.
+    watcher: Option<FileWatcher>,
+    /// Diff shown by "Compare" after the file changed on disk.
+    comparison: Option<Comparison>,
+    /// The Go to Line prompt, while it is open.
+    go_to: Option<GoToPrompt>,
+    /// Totals for the status bar, computed for a document id and revision.
+    text_stats: Option<((u64, u64), TextStats)>,
+    /// Whether the window is maximized, remembered with the session.
+    maximized: bool,
+    /// Set once the window is closing, when the session is saved for the last time.
+    closing: bool,
+    /// Set when the window closes with unsaved documents the user chose not to save.
+    discard_unsaved: bool,
 }
 
 impl DNotepadX {
-    pub fn new() -> Self {
-        let settings = Settings::load();
-        Self {
+    pub fn new(cc: &eframe::CreationContext, settings: Settings) -> Self {
+        let journal = Journal::new();
+        let fonts = FontLibrary::scan(&settings.font_files);
+        fonts.install(&cc.egui_ctx, &settings.font_family);
+        let mut app = Self {
             temp_theme: settings.theme.clone(),
             temp_font_family: settings.font_family.clone(),
             temp_font_size: settings.font_size,
             temp_word_wrap: settings.word_wrap,
+            temp_undo_depth: settings.undo_depth,
+            temp_large_file_threshold: settings.large_file_threshold_mb,
+            temp_viewer_threshold: settings.viewer_threshold_mb,
+            temp_keybindings: Self::keybinding_texts(&settings),
+            temp_auto_save: settings.auto_save,
+            temp_auto_save_interval: settings.auto_save_interval_secs,
+            temp_auto_save_idle: settings.auto_save_idle_secs,
+            temp_backup_policy: settings.backup_policy,
+            temp_default_line_ending: settings.default_line_ending,
+            temp_restore_session: settings.restore_session,
+            temp_tab_size: settings.tab_size,
+            temp_insert_spaces: settings.insert_spaces,
+            temp_auto_indent: settings.auto_indent,
+            temp_smart_indent: settings.smart_indent,
+            documents: Documents::new(DocumentDefaults {
+                undo_depth: settings.undo_depth,
+                indentation: settings.indentation(),
+                line_ending: settings.default_line_ending,
+                large_file_bytes: megabytes(settings.large_file_threshold_mb),
+            }),
             settings,
-            content: String::new(),
-            current_file: None,
-            is_modified: false,
             show_settings: false,
             show_find_replace: false,
-            find_text: String::new(),
-            replace_text: String::new(),
-            cursor_position: None,
+            search: SearchState::default(),
+            recovered: journal.orphaned(),
+            journal,
+            highlighter: Highlighter::new(),
+            fonts,
+            user_themes: theme_files::load_all(),
+            watcher: None,
+            comparison: None,
+            go_to: None,
+            text_stats: None,
+            listener: None,
+            waits: Vec::new(),
+            maximized: false,
+            closing: false,
+            discard_unsaved: false,
+        };
+        if app.settings.restore_session {
+            if let Some(session) = cc.storage.and_then(Session::load) {
+                if session.maximized {
+                    cc.egui_ctx.send_viewport_cmd(egui::ViewportCommand::Maximized(true));
+                }
+                app.restore_session(session);
+            }
+        }
+        app
+    }
+
+    /// Reopens the tabs of the last run. After a crash the recovery journal has newer
+    /// unsaved text than the session, so only the files are reopened then.
+    fn restore_session(&mut self, session: Session) {
+        let crashed = !self.recovered.is_empty();
+        let mut active = None;
+        for (index, mut saved) in session.documents.into_iter().enumerate() {
+            if crashed {
+                saved.unsaved = None;
+            }
+            if self.reopen(saved) && index == session.active {
+                active = Some(self.documents.active_index());
+            }
+        }
+        if let Some(index) = active {
+            self.documents.set_active(index);
+        }
+    }
+
+    /// Opens one document of a session, returning false when there was nothing to open.
+    fn reopen(&mut self, saved: SessionDocument) -> bool {
+        let unsaved = saved.unsaved;
+        match &saved.path {
+            Some(path) if path.exists() && saved.hex => {
+                let Ok((bytes, disk)) = FileOperations::read_bytes(path) else {
+                    return false;
+                };
+                self.documents.open(path.clone(), LoadedFile::default());
+                self.documents.active_mut().load_bytes(bytes, disk);
+            }
+            Some(path) if path.exists() => match FileOperations::open_path(path, self.viewer_bytes()) {
+                Ok(Opened::Text(file)) => {
+                    self.documents.open(path.clone(), file);
+                }
+                Ok(Opened::Mapped(file)) => {
+                    self.documents.open_mapped(path.clone(), file);
+                }
+                Err(_) => return false,
+            },
+            // The file is gone or was never written, the unsaved text is all there is
+            Some(path) if unsaved.is_some() => {
+                self.documents.open(path.clone(), LoadedFile::default());
+            }
+            None if unsaved.is_some() => {
+                if !self.documents.active().is_blank() {
+                    self.documents.new_untitled();
+                }
+            }
+            _ => return false,
+        }
+
+        let large_file_bytes = megabytes(self.settings.large_file_threshold_mb);
+        let doc = self.documents.active_mut();
+        if saved.hex && doc.set_hex(true, large_file_bytes).is_err() {
+            return true;
+        }
+        if let Some(unsaved) = unsaved {
+            doc.restore_snapshot(unsaved);
         }
+        match &mut doc.mapped {
+            Some(file) => file.top = file.row_start(saved.viewer_top),
+            None => doc.restore_view(saved.selection, saved.scroll.into()),
+        }
+        true
     }
 
     fn apply_theme(&self, ctx: &egui::Context) {
-        let mut visuals = egui::Visuals::default();
+        let mut visuals = egui::Visuals::dark();
         
         // Set overall theme colors
         visuals.window_fill = self.settings.theme.background();
@@ -82,28 +247,155 @@ impl DNotepadX {
         ctx.set_visuals(visuals);
     }
 
+    fn set_theme(&mut self, theme: Theme) {
+        self.settings.theme = theme;
+        self.temp_theme = self.settings.theme.clone();
+        self.settings.save();
+    }
+
+    /// Saves the current theme as a file, by default in the themes directory so it shows up in the Themes menu.
+    fn save_theme(&mut self) {
+        let Some(dir) = theme_files::themes_dir() else {
+            return;
+        };
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            FileOperations::show_save_error(&e);
+            return;
+        }
+        let Some(path) = FileOperations::save_theme_dialog(&dir) else {
+            return;
+        };
+        match theme_files::write(&path, &self.settings.theme) {
+            Ok(()) => self.user_themes = theme_files::load_all(),
+            Err(e) => FileOperations::show_save_error(&e),
+        }
+    }
+
+    /// Converts a theme picked by the user into the themes directory and switches to it.
+    fn import_theme(&mut self) {
+        let Some(source) = FileOperations::pick_theme_file() else {
+            return;
+        };
+        let (name, theme) = match theme_files::import(&source) {
+            Ok(imported) => imported,
+            Err(e) => {
+                FileOperations::show_open_error(&e);
+                return;
+            }
+        };
+        // Only a theme that was saved is switched to, so it is still listed on the next start
+        let Some(path) = theme_files::new_theme_path(&name) else {
+            FileOperations::show_save_error(&"there is no configuration directory to keep themes in");
+            return;
+        };
+        let saved = std::fs::create_dir_all(path.parent().unwrap_or(&path))
+            .and_then(|()| theme_files::write(&path, &theme));
+        if let Err(e) = saved {
+            FileOperations::show_save_error(&e);
+            return;
+        }
+        self.user_themes = theme_files::load_all();
+        self.set_theme(theme);
+    }
+
+    /// Adds a font file picked by the user to the font list and selects its family in the settings.
+    fn load_font_file(&mut self) {
+        let Some(path) = FileOperations::pick_font_file() else {
+            return;
+        };
+        match self.fonts.add(&path, false) {
+            Ok(families) => {
+                self.temp_font_family = families[0].clone();
+                if !self.settings.font_files.contains(&path) {
+                    self.settings.font_files.push(path);
+                    self.settings.save();
+                }
+            }
+            Err(e) => FileOperations::show_open_error(&e),
+        }
+    }
+
+    fn keybinding_texts(settings: &Settings) -> BTreeMap<Action, String> {
+        Action::ALL
+            .iter()
+            .map(|&action| {
+                let chords: Vec<String> = settings.keybindings.chords(action)
+                    .iter()
+                    .map(KeyChord::to_string)
+                    .collect();
+                (action, chords.join(", "))
+            })
+            .collect()
+    }
+
+    /// Menu entry for `action` showing its key chord; returns true when clicked.
+    fn action_button(&self, ui: &mut Ui, text: &str, action: Action, enabled: bool) -> bool {
+        let shortcut = self.settings.keybindings.shortcut_text(ui.ctx(), action);
+        ui.add_enabled(enabled, egui::Button::new(text).shortcut_text(shortcut))
+            .clicked()
+    }
+
+    fn run_action(&mut self, action: Action) {
+        match action {
+            Action::NewFile => self.new_file(),
+            Action::OpenFile => self.open_file(),
+            Action::Save => self.save_file(),
+            Action::SaveAs => self.save_as_file(),
+            Action::SaveAsCopy => self.save_as_copy(),
+            Action::CloseTab => self.close_document(self.documents.active_index()),
+            Action::Undo => self.documents.active_mut().undo(),
+            Action::Redo => self.documents.active_mut().redo(),
+            Action::FindReplace => self.show_find_replace = !self.show_find_replace,
+            Action::FindNext => self.find_next(),
+            Action::FindPrevious => self.find_previous(),
+            Action::GoToLine => self.open_go_to(),
+            Action::ToggleHex => self.toggle_hex(),
+        }
+    }
+
     fn menu_bar(&mut self, ui: &mut Ui) {
         egui::menu::bar(ui, |ui| {
             ui.menu_button("File", |ui| {
-                if ui.button("📄 New").clicked() {
-                    self.new_file();
-                    ui.close_menu();
-                }
-                if ui.button("📂 Open").clicked() {
-                    self.open_file();
-                    ui.close_menu();
+                for (text, action) in [
+                    ("📄 New", Action::NewFile),
+                    ("📂 Open", Action::OpenFile),
+                ] {
+                    if self.action_button(ui, text, action, true) {
+                        self.run_action(action);
+                        ui.close_menu();
+                    }
                 }
                 ui.separator();
-                if ui.button("💾 Save").clicked() {
-                    self.save_file();
-                    ui.close_menu();
-                }
-                if ui.button("💾 Save As").clicked() {
-                    self.save_as_file();
-                    ui.close_menu();
+                let doc = self.documents.active();
+                let (writable, has_path, current) = (!doc.is_read_only(), doc.path.is_some(), doc.format().encoding);
+                let text = writable && !doc.is_hex();
+                for (text, action) in [
+                    ("💾 Save", Action::Save),
+                    ("💾 Save As", Action::SaveAs),
+                    ("📋 Save As Copy", Action::SaveAsCopy),
+                ] {
+                    if self.action_button(ui, text, action, writable) {
+                        self.run_action(action);
+                        ui.close_menu();
+                    }
                 }
-                if ui.button("📋 Save As Copy").clicked() {
-                    self.save_as_copy();
+                ui.add_enabled_ui(text && has_path, |ui| {
+                    ui.menu_button("🔤 Reopen with Encoding", |ui| {
+                        if let Some(encoding) = encoding_menu(ui, current) {
+                            self.reopen_with_encoding(encoding);
+                        }
+                    });
+                });
+                ui.add_enabled_ui(text, |ui| {
+                    ui.menu_button("🔤 Save with Encoding", |ui| {
+                        if let Some(encoding) = encoding_menu(ui, current) {
+                            self.save_with_encoding(encoding);
+                        }
+                    });
+                });
+                ui.separator();
+                if self.action_button(ui, "✖ Close Tab", Action::CloseTab, true) {
+                    self.run_action(Action::CloseTab);
                     ui.close_menu();
                 }
                 ui.separator();
@@ -128,10 +420,40 @@ impl DNotepadX {
             });
 
             ui.menu_button("Edit", |ui| {
-                if ui.button("🔍 Find & Replace").clicked() {
-                    self.show_find_replace = !self.show_find_replace;
+                let doc = self.documents.active();
+                let (can_undo, can_redo) = (doc.history.can_undo(), doc.history.can_redo());
+                if self.action_button(ui, "↶ Undo", Action::Undo, can_undo) {
+                    self.run_action(Action::Undo);
+                    ui.close_menu();
+                }
+                if self.action_button(ui, "↷ Redo", Action::Redo, can_redo) {
+                    self.run_action(Action::Redo);
+                    ui.close_menu();
+                }
+                ui.separator();
+                for (text, action) in [
+                    ("🔍 Find & Replace", Action::FindReplace),
+                    ("⏷ Find Next", Action::FindNext),
+                    ("⏶ Find Previous", Action::FindPrevious),
+                ] {
+                    if self.action_button(ui, text, action, true) {
+                        self.run_action(action);
+                        ui.close_menu();
+                    }
+                }
+                ui.separator();
+                let doc = self.documents.active();
+                let text = !doc.is_read_only() && !doc.is_hex();
+                if self.action_button(ui, "↪ Go to Line", Action::GoToLine, text) {
+                    self.run_action(Action::GoToLine);
                     ui.close_menu();
                 }
+                ui.separator();
+                ui.add_enabled_ui(text, |ui| {
+                    ui.menu_button("↵ Line Endings", |ui| {
+                        line_ending_menu(ui, self.documents.active_mut());
+                    });
+                });
             });
 
             ui.menu_button("View", |ui| {
@@ -140,30 +462,50 @@ impl DNotepadX {
                     ui.close_menu();
                 }
                 ui.separator();
+
+                if ui.checkbox(&mut self.settings.show_line_numbers, "Line Numbers").changed() {
+                    self.settings.save();
+                }
+                if ui.add_enabled(
+                    self.settings.show_line_numbers,
+                    egui::Checkbox::new(&mut self.settings.relative_line_numbers, "Relative Line Numbers"),
+                ).changed() {
+                    self.settings.save();
+                }
+                let doc = self.documents.active();
+                let label = if doc.is_hex() { "📝 Text Editor" } else { "🔢 Hex Editor" };
+                if self.action_button(ui, label, Action::ToggleHex, !doc.is_read_only()) {
+                    self.run_action(Action::ToggleHex);
+                    ui.close_menu();
+                }
+                ui.separator();
                 
                 ui.menu_button("🎨 Themes", |ui| {
-                    if ui.button("📜 Classic Renaissance").clicked() {
-                        self.settings.theme = Theme::default();
-                        self.temp_theme = self.settings.theme.clone();
-                        self.settings.save();
-                        ui.close_menu();
+                    let mut picked = None;
+                    for (label, theme) in Theme::built_in() {
+                        if ui.button(label).clicked() {
+                            picked = Some(theme);
+                        }
                     }
-                    if ui.button("🌙 Dark Renaissance").clicked() {
-                        self.settings.theme = Theme::dark_renaissance();
-                        self.temp_theme = self.settings.theme.clone();
-                        self.settings.save();
+                    if !self.user_themes.is_empty() {
+                        ui.separator();
+                        for file in &self.user_themes {
+                            if ui.button(format!("🖌 {}", file.name)).clicked() {
+                                picked = Some(file.theme.clone());
+                            }
+                        }
+                    }
+                    ui.separator();
+                    if ui.button("💾 Save Current as Theme…").clicked() {
+                        self.save_theme();
                         ui.close_menu();
                     }
-                    if ui.button("👑 Royal Blue").clicked() {
-                        self.settings.theme = Theme::royal_blue();
-                        self.temp_theme = self.settings.theme.clone();
-                        self.settings.save();
+                    if ui.button("📥 Import Theme…").clicked() {
+                        self.import_theme();
                         ui.close_menu();
                     }
-                    if ui.button("🌲 Forest Green").clicked() {
-                        self.settings.theme = Theme::forest_green();
-                        self.temp_theme = self.settings.theme.clone();
-                        self.settings.save();
+                    if let Some(theme) = picked {
+                        self.set_theme(theme);
                         ui.close_menu();
                     }
                 });
@@ -178,24 +520,80 @@ impl DNotepadX {
 
             // File status
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                if let Some(ref path) = self.current_file {
-                    let file_name = path.file_name()
-                        .and_then(|n| n.to_str())
-                        .unwrap_or("Unknown");
-                    let status = if self.is_modified { 
-                        format!("● {}", file_name) 
-                    } else { 
-                        file_name.to_string() 
-                    };
-                    ui.label(status);
-                } else {
-                    let status = if self.is_modified { "● Untitled" } else { "Untitled" };
-                    ui.label(status);
-                }
+                ui.label(self.documents.active().display_title());
             });
         });
     }
 
+    fn tab_bar(&mut self, ui: &mut Ui) {
+        let mut activate = None;
+        let mut close = None;
+        let mut close_others = None;
+        let mut reorder = None;
+
+        egui::ScrollArea::horizontal()
+            .id_source("tab_bar_scroll")
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for (index, doc) in self.documents.iter().enumerate() {
+                        let is_active = index == self.documents.active_index();
+                        let response = ui
+                            .selectable_label(is_active, doc.display_title())
+                            .interact(egui::Sense::click_and_drag())
+                            .on_hover_text(
+                                doc.path
+                                    .as_ref()
+                                    .map(|p| p.display().to_string())
+                                    .unwrap_or_else(|| "Untitled".to_string()),
+                            );
+
+                        // Drag a tab onto another one to reorder
+                        if response.drag_started() {
+                            response.dnd_set_drag_payload(index);
+                        }
+                        if let Some(from) = response.dnd_release_payload::<usize>() {
+                            reorder = Some((*from, index));
+                        }
+
+                        if response.clicked() {
+                            activate = Some(index);
+                        }
+                        if response.middle_clicked() {
+                            close = Some(index);
+                        }
+                        response.context_menu(|ui| {
+                            if ui.button("Close").clicked() {
+                                close = Some(index);
+                                ui.close_menu();
+                            }
+                            if ui.button("Close Others").clicked() {
+                                close_others = Some(index);
+                                ui.close_menu();
+                            }
+                        });
+
+                        if ui.small_button("✖").on_hover_text("Close").clicked() {
+                            close = Some(index);
+                        }
+                        ui.separator();
+                    }
+                });
+            });
+
+        if let Some(index) = activate {
+            self.documents.set_active(index);
+        }
+        if let Some((from, to)) = reorder {
+            self.documents.move_tab(from, to);
+        }
+        if let Some(index) = close {
+            self.close_document(index);
+        }
+        if let Some(index) = close_others {
+            self.close_other_documents(index);
+        }
+    }
+
     fn settings_window(&mut self, ctx: &egui::Context) {
         if !self.show_settings {
             return;
@@ -253,10 +651,13 @@ impl DNotepadX {
                         egui::ComboBox::from_label("")
                             .selected_text(&self.temp_font_family)
                             .show_ui(ui, |ui| {
-                                for font in get_available_fonts() {
-                                    ui.selectable_value(&mut self.temp_font_family, font.clone(), font);
+                                for font in self.fonts.families() {
+                                    ui.selectable_value(&mut self.temp_font_family, font.to_string(), font);
                                 }
                             });
+                        if ui.button("📂 Load Font File…").clicked() {
+                            self.load_font_file();
+                        }
                     });
 
                     ui.horizontal(|ui| {
@@ -270,58 +671,127 @@ impl DNotepadX {
 
                     ui.checkbox(&mut self.temp_word_wrap, "Word wrap");
 
-                    ui.add_space(10.0);
-                    ui.colored_label(self.settings.theme.settings_text(), "🎨 Custom Colors");
-                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.colored_label(self.settings.theme.settings_text(), "Tab size:");
+                        ui.add(egui::DragValue::new(&mut self.temp_tab_size).range(1..=16).suffix(" columns"));
+                    });
+                    ui.checkbox(&mut self.temp_insert_spaces, "Insert spaces when pressing Tab")
+                        .on_hover_text("For new files; opened files keep the indentation they use");
+                    ui.checkbox(&mut self.temp_auto_indent, "Auto-indent new lines");
+                    ui.add_enabled(
+                        self.temp_auto_indent,
+                        egui::Checkbox::new(&mut self.temp_smart_indent, "Indent after { ( [ and Python/YAML :"),
+                    );
+
+                    ui.checkbox(&mut self.temp_auto_save, "Auto-save files");
+                    ui.add_enabled_ui(self.temp_auto_save, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.colored_label(self.settings.theme.settings_text(), "Save after typing pauses for:");
+                            ui.add(egui::DragValue::new(&mut self.temp_auto_save_idle).range(1..=600).suffix(" s"));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.colored_label(self.settings.theme.settings_text(), "Save at least every:");
+                            ui.add(egui::DragValue::new(&mut self.temp_auto_save_interval).range(5..=3600).suffix(" s"));
+                        });
+                    });
+
+                    ui.checkbox(&mut self.temp_restore_session, "Reopen files from the last session");
 
                     ui.horizontal(|ui| {
-                        ui.colored_label(self.settings.theme.settings_text(), "Background:");
-                        let mut bg_color = [
-                            self.temp_theme.background_color[0] as f32 / 255.0,
-                            self.temp_theme.background_color[1] as f32 / 255.0,
-                            self.temp_theme.background_color[2] as f32 / 255.0,
-                        ];
-                        if ui.color_edit_button_rgb(&mut bg_color).changed() {
-                            self.temp_theme.background_color = [
-                                (bg_color[0] * 255.0) as u8,
-                                (bg_color[1] * 255.0) as u8,
-                                (bg_color[2] * 255.0) as u8,
-                            ];
-                        }
+                        ui.colored_label(self.settings.theme.settings_text(), "Line endings for new files:");
+                        egui::ComboBox::from_id_source("default_line_ending")
+                            .selected_text(self.temp_default_line_ending.label())
+                            .show_ui(ui, |ui| {
+                                for ending in LineEnding::ALL {
+                                    ui.selectable_value(&mut self.temp_default_line_ending, ending, ending.label());
+                                }
+                            });
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.colored_label(self.settings.theme.settings_text(), "Backup on save:");
+                        egui::ComboBox::from_id_source("backup_policy")
+                            .selected_text(self.temp_backup_policy.label())
+                            .show_ui(ui, |ui| {
+                                for policy in BackupPolicy::ALL {
+                                    ui.selectable_value(&mut self.temp_backup_policy, policy, policy.label());
+                                }
+                            });
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.colored_label(self.settings.theme.settings_text(), "Undo History Depth:");
+                        ui.add(egui::DragValue::new(&mut self.temp_undo_depth).range(10..=10000).suffix(" steps"));
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.colored_label(self.settings.theme.settings_text(), "Large-file mode from:");
+                        ui.add(egui::DragValue::new(&mut self.temp_large_file_threshold).range(1..=4096).suffix(" MB"));
                     });
 
                     ui.horizontal(|ui| {
-                        ui.colored_label(self.settings.theme.settings_text(), "Text Color:");
-                        let mut text_color = [
-                            self.temp_theme.text_color[0] as f32 / 255.0,
-                            self.temp_theme.text_color[1] as f32 / 255.0,
-                            self.temp_theme.text_color[2] as f32 / 255.0,
-                        ];
-                        if ui.color_edit_button_rgb(&mut text_color).changed() {
-                            self.temp_theme.text_color = [
-                                (text_color[0] * 255.0) as u8,
-                                (text_color[1] * 255.0) as u8,
-                                (text_color[2] * 255.0) as u8,
-                            ];
+                        ui.colored_label(self.settings.theme.settings_text(), "Read-only viewer from:");
+                        ui.add(egui::DragValue::new(&mut self.temp_viewer_threshold).range(1..=1_048_576).suffix(" MB"))
+                            .on_hover_text("Larger files, and binary files, open in a read-only viewer");
+                    });
+
+                    ui.add_space(10.0);
+                    ui.colored_label(self.settings.theme.settings_text(), "⌨ Keyboard Shortcuts");
+                    ui.separator();
+
+                    egui::Grid::new("keybindings_grid").num_columns(2).show(ui, |ui| {
+                        for action in Action::ALL {
+                            ui.colored_label(self.settings.theme.settings_text(), action.label());
+                            let text = self.temp_keybindings.entry(action).or_default();
+                            let valid = parse_chord_list(text).is_some();
+                            ui.add(TextEdit::singleline(text).hint_text("unbound").desired_width(160.0));
+                            if !valid {
+                                ui.colored_label(egui::Color32::RED, "invalid");
+                            }
+                            ui.end_row();
                         }
                     });
 
+                    ui.add_space(10.0);
+                    let label_color = self.settings.theme.settings_text();
+                    ui.colored_label(label_color, "🎨 Theme Colors");
+                    ui.separator();
+
+                    egui::Grid::new("theme_colors_grid").num_columns(4).show(ui, |ui| {
+                        for (i, (label, color)) in self.temp_theme.colors_mut().into_iter().enumerate() {
+                            ui.colored_label(label_color, label);
+                            ui.color_edit_button_srgba_unmultiplied(&mut color.0);
+                            if i % 2 == 1 {
+                                ui.end_row();
+                            }
+                        }
+                    });
                     ui.horizontal(|ui| {
-                        ui.label("Line Numbers:");
-                        let mut line_color = [
-                            self.temp_theme.line_number_color[0] as f32 / 255.0,
-                            self.temp_theme.line_number_color[1] as f32 / 255.0,
-                            self.temp_theme.line_number_color[2] as f32 / 255.0,
-                        ];
-                        if ui.color_edit_button_rgb(&mut line_color).changed() {
-                            self.temp_theme.line_number_color = [
-                                (line_color[0] * 255.0) as u8,
-                                (line_color[1] * 255.0) as u8,
-                                (line_color[2] * 255.0) as u8,
-                            ];
+                        ui.colored_label(label_color, "UI text:");
+                        let mut automatic = self.temp_theme.ui_text_color.is_none();
+                        if ui.checkbox(&mut automatic, "Automatic")
+                            .on_hover_text("Light or dark, whichever stands out more against the menu background")
+                            .changed()
+                        {
+                            let current = self.temp_theme.ui_text();
+                            self.temp_theme.ui_text_color = (!automatic).then_some(current);
+                        }
+                        if let Some(color) = &mut self.temp_theme.ui_text_color {
+                            ui.color_edit_button_srgba_unmultiplied(&mut color.0);
                         }
                     });
 
+                    for (pair, ratio) in self.temp_theme.contrast_warnings() {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            format!("⚠ {}: contrast {:.1}:1, below the {}:1 of WCAG AA", pair, ratio, AA_CONTRAST),
+                        );
+                    }
+
+                    ui.add_space(5.0);
+                    let preview_font = fonts::font_id(ui.ctx(), &self.settings.font_family, 13.0);
+                    theme_preview(ui, &self.temp_theme, &preview_font);
+
                     ui.add_space(20.0);
                     ui.horizontal(|ui| {
                         // Style buttons with proper text color
@@ -332,9 +802,35 @@ impl DNotepadX {
                         
                         if ui.button("✅ Apply").clicked() {
                             self.settings.theme = self.temp_theme.clone();
+                            if self.temp_font_family != self.settings.font_family {
+                                self.fonts.install(ui.ctx(), &self.temp_font_family);
+                            }
                             self.settings.font_family = self.temp_font_family.clone();
                             self.settings.font_size = self.temp_font_size;
                             self.settings.word_wrap = self.temp_word_wrap;
+                            self.settings.undo_depth = self.temp_undo_depth;
+                            self.settings.auto_save = self.temp_auto_save;
+                            self.settings.auto_save_interval_secs = self.temp_auto_save_interval;
+                            self.settings.auto_save_idle_secs = self.temp_auto_save_idle;
+                            self.settings.backup_policy = self.temp_backup_policy;
+                            self.settings.default_line_ending = self.temp_default_line_ending;
+                            self.documents.set_line_ending(self.temp_default_line_ending);
+                            self.settings.restore_session = self.temp_restore_session;
+                            self.settings.tab_size = self.temp_tab_size;
+                            self.settings.insert_spaces = self.temp_insert_spaces;
+                            self.settings.auto_indent = self.temp_auto_indent;
+                            self.settings.smart_indent = self.temp_smart_indent;
+                            self.documents.set_indentation(self.settings.indentation());
+                            self.documents.set_undo_depth(self.temp_undo_depth);
+                            self.settings.large_file_threshold_mb = self.temp_large_file_threshold;
+                            self.documents.set_large_file_bytes(megabytes(self.temp_large_file_threshold));
+                            self.settings.viewer_threshold_mb = self.temp_viewer_threshold;
+                            for (action, text) in &self.temp_keybindings {
+                                if let Some(chords) = parse_chord_list(text) {
+                                    self.settings.keybindings.set_chords(*action, chords);
+                                }
+                            }
+                            self.temp_keybindings = Self::keybinding_texts(&self.settings);
                             self.settings.save();
                         }
                         
@@ -343,14 +839,42 @@ impl DNotepadX {
                             self.temp_font_family = self.settings.font_family.clone();
                             self.temp_font_size = self.settings.font_size;
                             self.temp_word_wrap = self.settings.word_wrap;
+                            self.temp_undo_depth = self.settings.undo_depth;
+                            self.temp_large_file_threshold = self.settings.large_file_threshold_mb;
+                            self.temp_viewer_threshold = self.settings.viewer_threshold_mb;
+                            self.temp_auto_save = self.settings.auto_save;
+                            self.temp_auto_save_interval = self.settings.auto_save_interval_secs;
+                            self.temp_auto_save_idle = self.settings.auto_save_idle_secs;
+                            self.temp_backup_policy = self.settings.backup_policy;
+                            self.temp_default_line_ending = self.settings.default_line_ending;
+                            self.temp_restore_session = self.settings.restore_session;
+                            self.temp_tab_size = self.settings.tab_size;
+                            self.temp_insert_spaces = self.settings.insert_spaces;
+                            self.temp_auto_indent = self.settings.auto_indent;
+                            self.temp_smart_indent = self.settings.smart_indent;
+                            self.temp_keybindings = Self::keybinding_texts(&self.settings);
                             self.show_settings = false;
                         }
 
                         if ui.button("🔄 Reset to Default").clicked() {
                             self.temp_theme = Theme::default();
-                            self.temp_font_family = "Consolas".to_string();
+                            self.temp_font_family = "Monospace".to_string();
                             self.temp_font_size = 14.0;
                             self.temp_word_wrap = true;
+                            self.temp_undo_depth = 1000;
+                            self.temp_large_file_threshold = 8;
+                            self.temp_viewer_threshold = 256;
+                            self.temp_auto_save = false;
+                            self.temp_auto_save_interval = 30;
+                            self.temp_auto_save_idle = 2;
+                            self.temp_backup_policy = BackupPolicy::None;
+                            self.temp_default_line_ending = LineEnding::native();
+                            self.temp_restore_session = true;
+                            self.temp_tab_size = 4;
+                            self.temp_insert_spaces = true;
+                            self.temp_auto_indent = true;
+                            self.temp_smart_indent = true;
+                            self.temp_keybindings = Self::keybinding_texts(&Settings::default());
                         }
                     });
                 });
@@ -362,28 +886,86 @@ impl DNotepadX {
             return;
         }
 
+        self.refresh_search();
+
         egui::Window::new("🔍 Find & Replace")
             .resizable(false)
             .default_width(300.0)
             .show(ctx, |ui| {
+                let hex = self.documents.active().is_hex();
                 ui.horizontal(|ui| {
                     ui.label("Find:");
-                    ui.text_edit_singleline(&mut self.find_text);
+                    let field = ui.text_edit_singleline(&mut self.search.find_text);
+                    if hex {
+                        field.on_hover_text("Bytes in hex, ?? for any byte and quoted text, e.g. 4D 5A ?? 00 \"PE\"");
+                    }
                 });
                 
                 ui.horizontal(|ui| {
                     ui.label("Replace:");
-                    ui.text_edit_singleline(&mut self.replace_text);
+                    ui.text_edit_singleline(&mut self.search.replace_text)
+                        .on_hover_text("With Regex enabled, $1 or ${name} insert capture groups");
+                });
+
+                ui.horizontal(|ui| {
+                    // Byte patterns have none of these options
+                    ui.add_enabled_ui(!hex, |ui| {
+                        ui.checkbox(&mut self.search.options.regex, "Regex");
+                        ui.checkbox(&mut self.search.options.case_sensitive, "Match case");
+                        ui.checkbox(&mut self.search.options.whole_word, "Whole word");
+                    });
+
+                    let doc = self.documents.active();
+                    let selection = doc.cursor;
+                    let can_scope = (!selection.is_empty() || self.search.options.in_selection)
+                        && !doc.is_read_only()
+                        && !hex;
+                    let toggled = ui
+                        .add_enabled(can_scope, egui::Checkbox::new(&mut self.search.options.in_selection, "In selection"))
+                        .changed();
+                    if toggled && self.search.options.in_selection {
+                        self.search.scope = Some((doc.id(), selection.start()..selection.end()));
+                    }
                 });
 
+                if let Some(error) = self.search.error() {
+                    ui.colored_label(egui::Color32::RED, error);
+                } else if let Some(file) = &self.documents.active().mapped {
+                    match file.search_status() {
+                        SearchStatus::Idle => {}
+                        SearchStatus::Searching(done) => {
+                            ui.label(format!("Searching… {:.0}%", done * 100.0));
+                        }
+                        SearchStatus::Found(range) => {
+                            ui.label(format!("Match at offset {}", range.start));
+                        }
+                        SearchStatus::NotFound => {
+                            ui.label("No results");
+                        }
+                    }
+                } else if !self.search.find_text.is_empty() {
+                    let matches = self.search.matches();
+                    let selection = self.documents.active().selection_bytes();
+                    let status = match match_ordinal(matches, &selection) {
+                        _ if matches.is_empty() => "No results".to_string(),
+                        Some(current) => format!("{} of {}", current, matches.len()),
+                        None => format!("{} matches", matches.len()),
+                    };
+                    ui.label(status);
+                }
+
                 ui.horizontal(|ui| {
+                    if ui.button("Find Previous").clicked() {
+                        self.find_previous();
+                    }
                     if ui.button("Find Next").clicked() {
                         self.find_next();
                     }
-                    if ui.button("Replace").clicked() {
+                    let writable = !self.documents.active().is_read_only() && !hex;
+                    if ui.add_enabled(writable, egui::Button::new("Replace")).clicked() {
                         self.replace_current();
                     }
-                    if ui.button("Replace All").clicked() {
+                    if ui.add_enabled(writable, egui::Button::new("Replace All")).clicked() {
                         self.replace_all();
                     }
                 });
@@ -395,7 +977,7 @@ impl DNotepadX {
     }
 
     fn text_editor(&mut self, ui: &mut Ui) {
-        let font_id = create_font_id(&self.settings.font_family, self.settings.font_size);
+        let font_id = fonts::font_id(ui.ctx(), &self.settings.font_family, self.settings.font_size);
         
         egui::Frame::none()
             .fill(self.settings.theme.background())
@@ -406,131 +988,1127 @@ impl DNotepadX {
                 // For text editor content, use the original theme text color for proper contrast with background
                 ui.style_mut().visuals.override_text_color = Some(self.settings.theme.text());
                 
-                let available_size = ui.available_size();
-                let text_edit = TextEdit::multiline(&mut self.content)
-                    .font(font_id.clone())
-                    .desired_width(available_size.x.max(800.0))
-                    .desired_rows(0)
-                    .lock_focus(true);
-
-                let response = ui.add_sized(available_size, text_edit);
-                
-                if response.changed() {
-                    self.is_modified = true;
+                let viewer_bytes = self.viewer_bytes();
+                let doc = self.documents.active_mut();
+                let (id, tab) = (doc.editor_id(), doc.indentation.width.max(1));
+                if let Some(file) = &mut doc.mapped {
+                    let theme = &self.settings.theme;
+                    let style = PagerStyle {
+                        font_id: font_id.clone(),
+                        text_color: theme.text(),
+                        offset_color: theme.line_number(),
+                        gutter_background: theme.menu_bg(),
+                        highlight: theme.selection(),
+                        scrollbar: theme.border(),
+                    };
+                    // Files too big for the viewer threshold are too big to load for editing
+                    let editable = file.len() < viewer_bytes;
+                    if pager::show(ui, id, file, &style, tab, editable) {
+                        self.edit_in_hex();
+                    }
+                    return;
                 }
-            });
-    }
 
-    fn new_file(&mut self) {
-        if self.is_modified && !FileOperations::confirm_unsaved_changes() {
-            return;
-        }
-        
-        self.content.clear();
-        self.current_file = None;
-        self.is_modified = false;
-    }
+                if self.documents.active().is_hex() {
+                    let matches = if self.show_find_replace {
+                        self.refresh_search();
+                        self.search.matches()
+                    } else {
+                        &[]
+                    };
+                    let theme = &self.settings.theme;
+                    let style = HexStyle {
+                        font_id: font_id.clone(),
+                        text_color: theme.text(),
+                        offset_color: theme.line_number(),
+                        gutter_background: theme.menu_bg(),
+                        selection: theme.selection(),
+                        match_color: theme.selection().gamma_multiply(0.45),
+                        scrollbar: theme.border(),
+                    };
+                    hex::show(ui, self.documents.active_mut(), &style, matches);
+                    return;
+                }
 
-    fn open_file(&mut self) {
-        if self.is_modified && !FileOperations::confirm_unsaved_changes() {
-            return;
-        }
+                if self.documents.active().is_large() {
+                    let matches = if self.show_find_replace {
+                        self.refresh_search();
+                        self.search.matches()
+                    } else {
+                        &[]
+                    };
+                    let theme = &self.settings.theme;
+                    let style = ViewportStyle {
+                        font_id: font_id.clone(),
+                        text_color: theme.text(),
+                        selection: theme.selection(),
+                        match_color: theme.selection().gamma_multiply(0.45),
+                        scrollbar: theme.border(),
+                        ruler: theme.border().gamma_multiply(0.6),
+                        gutter: self.settings.show_line_numbers.then(|| GutterStyle {
+                            font_id: font_id.clone(),
+                            number_color: theme.line_number(),
+                            current_color: theme.text(),
+                            background: theme.menu_bg(),
+                            relative: self.settings.relative_line_numbers,
+                        }),
+                    };
+                    let indent = self.indent_options();
+                    viewport::show(ui, self.documents.active_mut(), &style, &indent, matches);
+                    return;
+                }
 
-        if let Some((path, content)) = FileOperations::open_file() {
-            self.content = content;
-            self.settings.add_recent_file(path.clone());
-            self.current_file = Some(path);
-            self.is_modified = false;
-            self.settings.save();
-        }
-    }
+                let time = ui.input(|i| i.time);
+                let marks = self.search_marks();
+                let indent = self.indent_options();
+                let space_width = ui.fonts(|f| f.glyph_width(&font_id, ' '));
+                let theme = &self.settings.theme;
+                let highlighter = &mut self.highlighter;
+                let doc = self.documents.active_mut();
+                let language = doc
+                    .language
+                    .clone()
+                    .unwrap_or_else(|| highlighter.detect(doc.path.as_deref(), &doc.content).to_string());
+                let editor_id = doc.editor_id();
+                let reveal = std::mem::take(&mut doc.reveal_cursor);
+                let tab_width = doc.indentation.width;
 
-    fn open_recent_file(&mut self, path: PathBuf) {
-        if self.is_modified && !FileOperations::confirm_unsaved_changes() {
-            return;
-        }
+                if ui.memory(|m| m.has_focus(editor_id)) {
+                    indent::handle_keys(ui, doc, &indent);
+                }
 
-        match std::fs::read_to_string(&path) {
-            Ok(content) => {
-                self.content = content;
-                self.settings.add_recent_file(path.clone());
-                self.current_file = Some(path);
-                self.is_modified = false;
-                self.settings.save();
-            }
-            Err(_) => {
-                // Remove from recent files if it doesn't exist
-                self.settings.recent_files.retain(|p| p != &path);
-                self.settings.save();
-            }
-        }
-    }
+                if doc.sync_cursor {
+                    doc.sync_cursor = false;
+                    let mut state = TextEdit::load_state(ui.ctx(), editor_id).unwrap_or_default();
+                    state.cursor.set_char_range(Some(CCursorRange::two(
+                        CCursor::new(doc.cursor.anchor),
+                        CCursor::new(doc.cursor.head),
+                    )));
+                    TextEdit::store_state(ui.ctx(), editor_id, state);
+                }
 
-    fn save_file(&mut self) {
-        if let Some(ref path) = self.current_file.clone() {
-            if FileOperations::save_file(path, &self.content) {
-                self.is_modified = false;
-            }
-        } else {
-            self.save_as_file();
-        }
-    }
+                let show_line_numbers = self.settings.show_line_numbers;
+                let gutter_style = GutterStyle {
+                    font_id: font_id.clone(),
+                    number_color: self.settings.theme.line_number(),
+                    current_color: self.settings.theme.text(),
+                    background: self.settings.theme.menu_bg(),
+                    relative: self.settings.relative_line_numbers,
+                };
 
-    fn save_as_file(&mut self) {
-        let default_name = self.current_file
-            .as_ref()
-            .and_then(|p| p.file_name())
-            .and_then(|n| n.to_str());
-            
-        if let Some(path) = FileOperations::save_as_dialog(&self.content, default_name) {
-            self.settings.add_recent_file(path.clone());
-            self.current_file = Some(path);
-            self.is_modified = false;
-            self.settings.save();
-        }
-    }
+                let (output, edits, gutter_rect) = ui.horizontal_top(|ui| {
+                    ui.spacing_mut().item_spacing.x = 0.0;
+                    let gutter_rect = show_line_numbers.then(|| {
+                        let line_count = doc.content.matches('\n').count() + 1;
+                        let size = egui::vec2(gutter::width(ui, &font_id, line_count), ui.available_height());
+                        ui.allocate_exact_size(size, egui::Sense::hover()).0
+                    });
 
-    fn save_as_copy(&mut self) {
-        FileOperations::save_as_copy_dialog(&self.content, self.current_file.as_deref());
-    }
+                    let available_size = ui.available_size();
+                    let mut layouter = |ui: &Ui, text: &str, wrap_width: f32| {
+                        let tokens = highlighter.highlight(&language, text);
+                        let job = highlight::layout_job(text, &font_id, theme, wrap_width, tab_width, space_width, &tokens, &marks);
+                        ui.fonts(|f| f.layout_job(job))
+                    };
+                    let mut buffer = RecordingBuffer::new(&mut doc.content);
+                    let text_edit = TextEdit::multiline(&mut buffer)
+                        .id(editor_id)
+                        .font(font_id.clone())
+                        .desired_width(available_size.x.max(800.0))
+                        .desired_rows(0)
+                        .lock_focus(true)
+                        .layouter(&mut layouter);
 
-    fn find_next(&mut self) {
-        if self.find_text.is_empty() {
-            return;
-        }
-        
-        let start_pos = self.cursor_position.unwrap_or(0);
-        if let Some(pos) = self.content[start_pos..].find(&self.find_text) {
-            self.cursor_position = Some(start_pos + pos);
-        } else if let Some(pos) = self.content[..start_pos].find(&self.find_text) {
-            self.cursor_position = Some(pos);
-        }
+                    let output = ui
+                        .allocate_ui_with_layout(
+                            available_size,
+                            egui::Layout::centered_and_justified(egui::Direction::TopDown),
+                            |ui| text_edit.show(ui),
+                        )
+                        .inner;
+                    (output, buffer.edits, gutter_rect)
+                }).inner;
+
+                let ruler = doc.editorconfig.as_ref().and_then(EditorConfig::max_line_length);
+                if let Some(column) = ruler {
+                    let x = output.galley_pos.x + column as f32 * space_width;
+                    if output.text_clip_rect.x_range().contains(x) {
+                        let stroke = Stroke::new(1.0, theme.border().gamma_multiply(0.6));
+                        ui.painter().vline(x, output.text_clip_rect.y_range(), stroke);
+                    }
+                }
+
+                if let Some(gutter_rect) = gutter_rect {
+                    let current_line = output.cursor_range.map_or(0, |r| r.primary.pcursor.paragraph);
+                    gutter::paint(ui, gutter_rect, &output.galley, output.galley_pos, current_line, &gutter_style);
+                }
+
+                let selection = output.cursor_range.map_or(doc.cursor, |range| Selection {
+                    anchor: range.secondary.ccursor.index,
+                    head: range.primary.ccursor.index,
+                });
+                if !edits.is_empty() {
+                    doc.record_edits(edits, selection, time);
+                } else if selection != doc.cursor {
+                    // Moving the caret ends the current run of typing
+                    doc.history.seal();
+                    doc.cursor = selection;
+                }
+
+                if reveal {
+                    let cursor_rect = output.galley
+                        .pos_from_ccursor(CCursor::new(doc.cursor.head))
+                        .translate(output.galley_pos.to_vec2());
+                    ui.scroll_to_rect(cursor_rect, Some(egui::Align::Center));
+                    ui.memory_mut(|mem| mem.request_focus(editor_id));
+                }
+            });
     }
 
-    fn replace_current(&mut self) {
-        if self.find_text.is_empty() {
+    fn status_bar(&mut self, ui: &mut Ui) {
+        let (mut reopen_with, mut save_with) = (None, None);
+        ui.visuals_mut().button_frame = false;
+        if let Some(file) = &self.documents.active().mapped {
+            viewer_status(ui, file);
             return;
         }
-        
-        if let Some(pos) = self.cursor_position {
-            if self.content[pos..].starts_with(&self.find_text) {
-                let end_pos = pos + self.find_text.len();
-                self.content.replace_range(pos..end_pos, &self.replace_text);
-                self.cursor_position = Some(pos + self.replace_text.len());
-                self.is_modified = true;
-            }
+        if self.documents.active().is_hex() {
+            hex_status(ui, self.documents.active_mut());
+            return;
         }
-    }
-
+        ui.horizontal(|ui| {
+            let doc = self.documents.active();
+            let key = (doc.id(), doc.revision());
+            let stats = match self.text_stats {
+                Some((cached, stats)) if cached == key => stats,
+                _ => {
+                    let stats = if doc.is_large() {
+                        TextStats {
+                            lines: doc.line_count(),
+                            words: None,
+                        }
+                    } else {
+                        TextStats::of(&doc.content)
+                    };
+                    self.text_stats = Some((key, stats));
+                    stats
+                }
+            };
+
+            let (line, column) = doc.line_column();
+            ui.label(format!("Ln {}, Col {}", line, column));
+            if !doc.cursor.is_empty() {
+                let (chars, lines) = doc.selection_size();
+                ui.separator();
+                ui.label(format!("{} selected, {} lines", chars, lines));
+            }
+            ui.separator();
+            match stats.words {
+                Some(words) => ui.label(format!("{} lines, {} words", stats.lines, words)),
+                None => ui.label(format!("{} lines", stats.lines)),
+            };
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                let doc = self.documents.active_mut();
+                let detected = self.highlighter.detect(doc.path.as_deref(), &doc.content).to_string();
+                let current = doc.language.clone().unwrap_or_else(|| detected.clone());
+                ui.menu_button(current, |ui| {
+                    egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                        let auto = format!("Auto-detect ({})", detected);
+                        if ui.selectable_label(doc.language.is_none(), auto).clicked() {
+                            doc.language = None;
+                            ui.close_menu();
+                        }
+                        ui.separator();
+                        for name in self.highlighter.languages() {
+                            if ui.selectable_label(doc.language.as_deref() == Some(name), name).clicked() {
+                                doc.language = Some(name.to_string());
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                })
+                .response
+                .on_hover_text("Language");
+
+                ui.separator();
+                let format = doc.format();
+                let (label, hover) = if doc.mixed_line_endings {
+                    let ending = format.line_ending.label();
+                    (
+                        format!("⚠ Mixed ({})", ending),
+                        format!("The file mixes line endings; saving writes {} everywhere", ending),
+                    )
+                } else {
+                    (format.line_ending.label().to_string(), "Line endings".to_string())
+                };
+                ui.menu_button(label, |ui| line_ending_menu(ui, doc))
+                    .response
+                    .on_hover_text(hover);
+
+                ui.separator();
+                ui.menu_button(format.encoding.label(), |ui| {
+                    ui.add_enabled_ui(doc.path.is_some(), |ui| {
+                        ui.menu_button("Reopen with Encoding", |ui| {
+                            reopen_with = encoding_menu(ui, format.encoding);
+                        });
+                    });
+                    ui.menu_button("Save with Encoding", |ui| {
+                        save_with = encoding_menu(ui, format.encoding);
+                    });
+                })
+                .response
+                .on_hover_text("Encoding");
+
+                ui.separator();
+                ui.menu_button(doc.indentation.label(), |ui| {
+                    ui.selectable_value(&mut doc.indentation.use_tabs, false, "Indent Using Spaces");
+                    ui.selectable_value(&mut doc.indentation.use_tabs, true, "Indent Using Tabs");
+                    ui.separator();
+                    for width in Indentation::WIDTHS {
+                        ui.selectable_value(&mut doc.indentation.width, width, format!("Width: {}", width));
+                    }
+                    ui.separator();
+                    if ui.button("Convert Indentation to Spaces").clicked() {
+                        doc.indentation.use_tabs = false;
+                        doc.reindent();
+                        ui.close_menu();
+                    }
+                    if ui.button("Convert Indentation to Tabs").clicked() {
+                        doc.indentation.use_tabs = true;
+                        doc.reindent();
+                        ui.close_menu();
+                    }
+                })
+                .response
+                .on_hover_text("Indentation");
+
+                if let Some(config) = &doc.editorconfig {
+                    ui.separator();
+                    let sources: Vec<String> = config.sources.iter().map(|path| path.display().to_string()).collect();
+                    let mut hover = format!("{}\n\nFrom {}", config.describe().join("\n"), sources.join(", "));
+                    let label = if doc.editorconfig_converts {
+                        hover.push_str(&format!(
+                            "\n\nSaving converts the file to {}, {}",
+                            format.encoding.label(),
+                            format.line_ending.label()
+                        ));
+                        "⚠ EditorConfig"
+                    } else {
+                        "EditorConfig"
+                    };
+                    ui.label(label).on_hover_text(hover);
+                }
+            });
+        });
+
+        if let Some(encoding) = reopen_with {
+            self.reopen_with_encoding(encoding);
+        }
+        if let Some(encoding) = save_with {
+            self.save_with_encoding(encoding);
+        }
+    }
+
+    /// Background marks for the search matches while Find & Replace is open;
+    /// the current match is drawn like a selection, the others more faintly.
+    fn search_marks(&mut self) -> Vec<Mark> {
+        const MAX_MARKS: usize = 10_000;
+
+        if !self.show_find_replace {
+            return Vec::new();
+        }
+        self.refresh_search();
+        let current = self.documents.active().selection_bytes();
+        let selection = self.settings.theme.selection();
+        self.search
+            .matches()
+            .iter()
+            .take(MAX_MARKS)
+            .map(|range| Mark {
+                range: range.clone(),
+                background: if *range == current {
+                    selection
+                } else {
+                    selection.gamma_multiply(0.45)
+                },
+            })
+            .collect()
+    }
+
+    fn new_file(&mut self) {
+        self.documents.new_untitled();
+    }
+
+    fn open_file(&mut self) {
+        if let Some((path, opened)) = FileOperations::open_file(self.viewer_bytes()) {
+            self.open_document(path, opened);
+        }
+    }
+
+    fn open_recent_file(&mut self, path: PathBuf) {
+        if let Some(index) = self.documents.find_by_path(&path) {
+            self.documents.set_active(index);
+            return;
+        }
+
+        match FileOperations::open_path(&path, self.viewer_bytes()) {
+            Ok(opened) => {
+                self.open_document(path, opened);
+            }
+            Err(_) => {
+                // Remove from recent files if it doesn't exist
+                self.settings.recent_files.retain(|p| p != &path);
+                self.settings.save();
+            }
+        }
+    }
+
+    pub fn listen(&mut self, listener: Listener) {
+        self.listener = Some(listener);
+    }
+
+    /// Opens the files named on the command line. With `wait`, the window
+    /// closes once they have all been closed again.
+    pub fn open_file_args(&mut self, files: Vec<FileArg>, wait: bool) {
+        let documents = self.open_files(files);
+        if wait && !documents.is_empty() {
+            self.waits.push(Wait {
+                documents,
+                waiter: None,
+            });
+        }
+    }
+
+    /// Opens files forwarded by other invocations and brings the window to the front.
+    fn handle_forwarded_files(&mut self, ctx: &egui::Context) {
+        let Some(listener) = &self.listener else {
+            return;
+        };
+        let requests: Vec<_> = listener.receiver().try_iter().collect();
+        if requests.is_empty() {
+            return;
+        }
+        for request in requests {
+            let documents = self.open_files(request.files);
+            if let Some(waiter) = request.waiter {
+                if !documents.is_empty() {
+                    self.waits.push(Wait {
+                        documents,
+                        waiter: Some(waiter),
+                    });
+                }
+            }
+        }
+        ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+    }
+
+    /// Opens `files` at their line and column, returning the ids of their documents.
+    fn open_files(&mut self, files: Vec<FileArg>) -> Vec<u64> {
+        let mut opened = Vec::new();
+        for file in files {
+            if file.path.exists() {
+                match FileOperations::open_path(&file.path, self.viewer_bytes()) {
+                    Ok(opened) => self.open_document(file.path, opened),
+                    Err(e) => {
+                        FileOperations::show_open_error(&e);
+                        continue;
+                    }
+                }
+            } else if let Some(index) = self.documents.find_by_path(&file.path) {
+                self.documents.set_active(index);
+            } else {
+                // Nothing is written until the user saves, like `vim new.txt`
+                self.documents.open(file.path, LoadedFile::default());
+            }
+
+            let doc = self.documents.active_mut();
+            if let Some(line) = file.line {
+                doc.go_to(line, file.column.unwrap_or(1));
+            }
+            opened.push(doc.id());
+        }
+        opened
+    }
+
+    /// Releases `--wait` invocations whose documents have all been closed. Our own
+    /// `--wait` ends by closing the window.
+    fn finish_waits(&mut self, ctx: &egui::Context) {
+        let documents = &self.documents;
+        let mut close_window = false;
+        self.waits.retain_mut(|wait| {
+            wait.documents.retain(|&id| documents.iter().any(|doc| doc.id() == id));
+            if !wait.documents.is_empty() {
+                return true;
+            }
+            close_window |= wait.waiter.is_none();
+            false
+        });
+        if close_window {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+    }
+
+    fn open_document(&mut self, path: PathBuf, opened: Opened) {
+        // Switch to the existing tab instead of opening the same file twice
+        if let Some(index) = self.documents.find_by_path(&path) {
+            self.documents.set_active(index);
+        } else {
+            match opened {
+                Opened::Text(file) => self.documents.open(path.clone(), file),
+                Opened::Mapped(file) => self.documents.open_mapped(path.clone(), file),
+            };
+        }
+        self.settings.add_recent_file(path);
+        self.settings.save();
+    }
+
+    /// Switches the active document between the text editor and the hex editor.
+    fn toggle_hex(&mut self) {
+        let large_file_bytes = megabytes(self.settings.large_file_threshold_mb);
+        let doc = self.documents.active_mut();
+        if doc.is_read_only() {
+            return;
+        }
+        let hex = !doc.is_hex();
+        if let Err(e) = doc.set_hex(hex, large_file_bytes) {
+            FileOperations::show_switch_error(&e);
+        }
+    }
+
+    /// Loads the file shown in the active viewer tab into the hex editor.
+    fn edit_in_hex(&mut self) {
+        let doc = self.documents.active_mut();
+        let Some(path) = doc.path.clone() else {
+            return;
+        };
+        match FileOperations::read_bytes(&path) {
+            Ok((bytes, disk)) => doc.load_bytes(bytes, disk),
+            Err(e) => FileOperations::show_open_error(&e),
+        }
+    }
+
+    /// Follows the window's size and maximized state for the settings and the session.
+    fn track_window(&mut self, ctx: &egui::Context) {
+        let (maximized, fullscreen, size) = ctx.input(|i| {
+            let viewport = i.viewport();
+            (
+                viewport.maximized.unwrap_or(false),
+                viewport.fullscreen.unwrap_or(false),
+                viewport.inner_rect.map(|rect| rect.size()),
+            )
+        });
+        self.maximized = maximized;
+        if let Some(size) = size.filter(|_| !maximized && !fullscreen) {
+            self.settings.window_width = size.x;
+            self.settings.window_height = size.y;
+        }
+    }
+
+    /// Asks about unsaved documents the session won't keep before the window closes,
+    /// and keeps it open if the user cancels or a save doesn't go through.
+    fn confirm_close(&mut self, ctx: &egui::Context) {
+        if self.closing || !ctx.input(|i| i.viewport().close_requested()) {
+            return;
+        }
+        let (unsaved, titles): (Vec<usize>, Vec<String>) = self
+            .documents
+            .iter()
+            .enumerate()
+            .filter(|(_, doc)| doc.is_modified && !self.settings.restore_session)
+            .map(|(index, doc)| (index, doc.title()))
+            .unzip();
+        if !unsaved.is_empty() {
+            match FileOperations::confirm_close(&titles) {
+                CloseChoice::Save => {
+                    for &index in &unsaved {
+                        self.documents.set_active(index);
+                        self.save_file();
+                    }
+                    if self.documents.iter().any(|doc| doc.is_modified) {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+                        return;
+                    }
+                }
+                CloseChoice::Discard => self.discard_unsaved = true,
+                CloseChoice::Cancel => {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+                    return;
+                }
+            }
+        }
+        self.closing = true;
+    }
+
+    /// How Tab and Enter indent in the active document.
+    fn indent_options(&self) -> IndentOptions {
+        let doc = self.documents.active();
+        let language = match &doc.language {
+            Some(language) => language.as_str(),
+            None => self.highlighter.detect(doc.path.as_deref(), &doc.content),
+        };
+        IndentOptions::new(doc.indentation, self.settings.auto_indent, self.settings.smart_indent, language)
+    }
+
+    /// Files of at least this size open in the read-only viewer.
+    fn viewer_bytes(&self) -> usize {
+        megabytes(self.settings.viewer_threshold_mb)
+    }
+
+    fn close_document(&mut self, index: usize) {
+        let Some(doc) = self.documents.get(index) else {
+            return;
+        };
+        if doc.is_modified && !FileOperations::confirm_unsaved_changes() {
+            return;
+        }
+        self.journal.remove(doc.id());
+        self.documents.close(index);
+    }
+
+    fn close_other_documents(&mut self, index: usize) {
+        let others_modified = self
+            .documents
+            .iter()
+            .enumerate()
+            .any(|(i, doc)| i != index && doc.is_modified);
+        if others_modified && !FileOperations::confirm_unsaved_changes() {
+            return;
+        }
+        for (i, doc) in self.documents.iter().enumerate() {
+            if i != index {
+                self.journal.remove(doc.id());
+            }
+        }
+        self.documents.close_others(index);
+    }
+
+    fn save_file(&mut self) {
+        let doc = self.documents.active_mut();
+        if doc.is_read_only() {
+            return;
+        }
+        if let Some(ref path) = doc.path.clone() {
+            if FileOperations::changed_on_disk(path, doc.disk.as_ref())
+                && !FileOperations::confirm_overwrite_changed(path)
+            {
+                return;
+            }
+            doc.apply_save_rules();
+            if let Some(disk) = FileOperations::save_file(path, &doc.contents(), self.settings.backup_policy) {
+                doc.mark_saved(disk);
+                self.journal.remove(doc.id());
+            }
+        } else {
+            self.save_as_file();
+        }
+    }
+
+    fn save_as_file(&mut self) {
+        let doc = self.documents.active_mut();
+        if doc.is_read_only() {
+            return;
+        }
+        let default_name = doc.path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .and_then(|n| n.to_str());
+            
+        if let Some((path, disk)) = FileOperations::save_as_dialog(
+            &doc.contents(),
+            self.settings.backup_policy,
+            default_name,
+        ) {
+            doc.path = Some(path.clone());
+            doc.mark_saved(disk);
+            // Written in the old format; a different one from the new folder is a pending change
+            doc.set_editorconfig(EditorConfig::resolve(&path));
+            self.journal.remove(doc.id());
+            self.settings.add_recent_file(path);
+            self.settings.save();
+        }
+    }
+
+    /// Reads the active document's file again as `encoding`, for when the guess on opening was wrong.
+    fn reopen_with_encoding(&mut self, encoding: Encoding) {
+        let doc = self.documents.active_mut();
+        let Some(path) = doc.path.clone() else {
+            return;
+        };
+        match FileOperations::read_file_as(&path, encoding) {
+            Ok(file) => doc.reload(file),
+            Err(e) => FileOperations::show_open_error(&e),
+        }
+    }
+
+    /// Converts the active document to `encoding` and saves it, unless it has
+    /// characters the encoding can't represent.
+    fn save_with_encoding(&mut self, encoding: Encoding) {
+        let doc = self.documents.active_mut();
+        let format = FileFormat {
+            encoding,
+            ..doc.format()
+        };
+        if let Err(e) = format.encode(&doc.text()) {
+            FileOperations::show_save_error(&e);
+            return;
+        }
+        doc.set_format(format);
+        self.save_file();
+    }
+
+    fn save_as_copy(&mut self) {
+        let doc = self.documents.active();
+        if doc.is_read_only() {
+            return;
+        }
+        FileOperations::save_as_copy_dialog(
+            &doc.contents(),
+            self.settings.backup_policy,
+            doc.path.as_deref(),
+        );
+    }
+
+    /// Writes documents whose edits are due: files with a path are saved in place when
+    /// auto-save is on, everything else goes to the recovery journal. The `.editorconfig`
+    /// save rules wait for a save from the menu; trimming the space just typed before a
+    /// pause would move the caret and add undo steps nobody made.
+    fn auto_save(&mut self, ctx: &egui::Context) {
+        let now = Instant::now();
+        let idle = Duration::from_secs(self.settings.auto_save_idle_secs);
+        let interval = Duration::from_secs(self.settings.auto_save_interval_secs);
+        let mut any_pending = false;
+
+        for doc in self.documents.iter_mut() {
+            if !doc.is_modified {
+                continue;
+            }
+            if !doc.persist_due(now, idle, interval) {
+                any_pending = true;
+                continue;
+            }
+            // A file changed by another program is never overwritten without asking
+            let saved = self
+                .settings
+                .auto_save
+                .then_some(doc.path.as_ref())
+                .flatten()
+                .filter(|path| !FileOperations::changed_on_disk(path, doc.disk.as_ref()))
+                // Backups are kept for deliberate saves only, not every few seconds
+                .and_then(|path| {
+                    FileOperations::write_file(path, &doc.contents(), BackupPolicy::None).ok()
+                });
+            if let Some(disk) = saved {
+                doc.mark_saved(disk);
+                self.journal.remove(doc.id());
+            } else {
+                self.journal.write(doc.id(), doc.path.clone(), doc.title(), doc.format(), doc.journal_snapshot());
+                doc.mark_journaled();
+            }
+        }
+
+        if any_pending {
+            // Wake up to persist even if no input arrives
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+    }
+
+    /// Reacts to files changed by other programs: unmodified documents are reloaded,
+    /// modified ones get a banner asking what to do.
+    fn check_external_changes(&mut self, ctx: &egui::Context) {
+        let watcher = self.watcher.get_or_insert_with(|| FileWatcher::new(ctx.clone()));
+        watcher.watch(self.documents.iter().filter_map(|doc| doc.path.as_deref()));
+        let changed = watcher.changed();
+        if changed.is_empty() {
+            return;
+        }
+
+        for doc in self.documents.iter_mut() {
+            let Some(path) = doc.path.clone().filter(|path| changed.contains(path)) else {
+                continue;
+            };
+            if let Some(file) = &mut doc.mapped {
+                // There are no edits to lose; the viewer simply shows the file as it is now
+                if path.exists() {
+                    if let Err(e) = file.remap(&path) {
+                        FileOperations::show_open_error(&e);
+                    }
+                }
+                continue;
+            }
+            if !FileOperations::changed_on_disk(&path, doc.disk.as_ref()) {
+                continue;
+            }
+            if !path.exists() {
+                doc.external_change = Some(ExternalChange::Deleted);
+                continue;
+            }
+            if !doc.is_modified {
+                if doc.is_hex() {
+                    if let Ok((bytes, disk)) = FileOperations::read_bytes(&path) {
+                        doc.load_bytes(bytes, disk);
+                        continue;
+                    }
+                } else if let Ok(file) = FileOperations::read_file(&path) {
+                    doc.reload(file);
+                    continue;
+                }
+            }
+            doc.external_change = Some(ExternalChange::Modified);
+        }
+    }
+
+    /// Notice above the editor while the active document's file has changed on disk.
+    fn external_change_banner(&mut self, ctx: &egui::Context) {
+        let doc = self.documents.active();
+        let Some(change) = doc.external_change else {
+            return;
+        };
+        let (id, hex) = (doc.id(), doc.is_hex());
+
+        let (mut reload, mut keep, mut compare) = (false, false, false);
+        egui::TopBottomPanel::top("external_change_banner").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                match change {
+                    ExternalChange::Modified => {
+                        ui.label("⚠ This file was changed by another program.");
+                        reload = ui.button("Reload").clicked();
+                        keep = ui.button("Keep mine").clicked();
+                        // The comparison is line by line, which bytes don't have
+                        compare = ui.add_enabled(!hex, egui::Button::new("Compare")).clicked();
+                    }
+                    ExternalChange::Deleted => {
+                        ui.label("⚠ This file was deleted or moved by another program.");
+                        keep = ui.button("Keep mine").clicked();
+                    }
+                }
+            });
+        });
+
+        if reload || keep {
+            self.resolve_external_change(id, reload);
+        } else if compare {
+            self.compare_with_disk(id);
+        }
+    }
+
+    /// Settles an outside change to document `id`, either by loading the file from
+    /// disk or by keeping the buffer as it is.
+    fn resolve_external_change(&mut self, id: u64, reload: bool) {
+        if self.comparison.as_ref().is_some_and(|c| c.document_id == id) {
+            self.comparison = None;
+        }
+        let Some(doc) = self.documents.iter_mut().find(|doc| doc.id() == id) else {
+            return;
+        };
+        let Some(path) = doc.path.clone() else {
+            return;
+        };
+        if !reload {
+            doc.keep_mine(FileOperations::disk_state(&path));
+            return;
+        }
+        if doc.is_hex() {
+            match FileOperations::read_bytes(&path) {
+                Ok((bytes, disk)) => doc.load_bytes(bytes, disk),
+                Err(e) => FileOperations::show_open_error(&e),
+            }
+            return;
+        }
+        match FileOperations::read_file(&path) {
+            Ok(file) => doc.reload(file),
+            Err(e) => FileOperations::show_open_error(&e),
+        }
+    }
+
+    fn compare_with_disk(&mut self, id: u64) {
+        let Some(doc) = self.documents.iter().find(|doc| doc.id() == id) else {
+            return;
+        };
+        let Some(path) = &doc.path else {
+            return;
+        };
+        match FileOperations::read_file(path) {
+            Ok(file) => {
+                let lines = TextDiff::from_lines(file.content.as_str(), doc.text().as_ref())
+                    .iter_all_changes()
+                    .map(|change| (change.tag(), change.value().trim_end_matches('\n').to_string()))
+                    .collect();
+                self.comparison = Some(Comparison {
+                    document_id: id,
+                    title: doc.title(),
+                    lines,
+                });
+            }
+            Err(e) => FileOperations::show_open_error(&e),
+        }
+    }
+
+    fn compare_window(&mut self, ctx: &egui::Context) {
+        let Some(comparison) = &self.comparison else {
+            return;
+        };
+
+        let mut open = true;
+        let (mut reload, mut keep) = (false, false);
+        egui::Window::new(format!("Compare: {}", comparison.title))
+            .open(&mut open)
+            .default_size([720.0, 480.0])
+            .show(ctx, |ui| {
+                ui.label("Lines marked - are only in the file on disk, lines marked + only in the editor.");
+                ui.horizontal(|ui| {
+                    reload = ui.button("Reload").clicked();
+                    keep = ui.button("Keep mine").clicked();
+                });
+                ui.separator();
+
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                egui::ScrollArea::both()
+                    .auto_shrink([false; 2])
+                    .show_rows(ui, row_height, comparison.lines.len(), |ui, rows| {
+                        for (tag, line) in &comparison.lines[rows] {
+                            let (sign, color) = match tag {
+                                ChangeTag::Delete => ("-", Color32::from_rgb(190, 70, 60)),
+                                ChangeTag::Insert => ("+", Color32::from_rgb(70, 150, 70)),
+                                ChangeTag::Equal => (" ", ui.visuals().text_color()),
+                            };
+                            let text = RichText::new(format!("{} {}", sign, line)).monospace().color(color);
+                            ui.add(egui::Label::new(text).extend());
+                        }
+                    });
+            });
+
+        let id = comparison.document_id;
+        if reload || keep {
+            self.resolve_external_change(id, reload);
+        } else if !open {
+            self.comparison = None;
+        }
+    }
+
+    fn open_go_to(&mut self) {
+        let doc = self.documents.active();
+        if doc.is_read_only() || doc.is_hex() {
+            return;
+        }
+        self.go_to = Some(GoToPrompt {
+            text: String::new(),
+            error: None,
+            focus: true,
+        });
+    }
+
+    fn go_to_window(&mut self, ctx: &egui::Context) {
+        let Some(prompt) = &mut self.go_to else {
+            return;
+        };
+        let doc = self.documents.active();
+        if doc.is_read_only() || doc.is_hex() {
+            self.go_to = None;
+            return;
+        }
+        let (line, _) = doc.line_column();
+        // The status bar has just counted the lines of the current revision
+        let lines = match self.text_stats {
+            Some((key, stats)) if key == (doc.id(), doc.revision()) => stats.lines,
+            _ => doc.line_count(),
+        };
+
+        let (mut open, mut go, mut cancel) = (true, false, false);
+        egui::Window::new("↪ Go to Line")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 60.0))
+            .show(ctx, |ui| {
+                ui.label(format!("Line {} of {}", line, lines));
+                let field = ui.add(
+                    TextEdit::singleline(&mut prompt.text)
+                        .hint_text("line[:column], +N, -N or N%")
+                        .desired_width(220.0),
+                );
+                if std::mem::take(&mut prompt.focus) {
+                    field.request_focus();
+                }
+                if field.changed() {
+                    prompt.error = None;
+                }
+                go = field.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+                if let Some(error) = &prompt.error {
+                    ui.colored_label(Color32::RED, error);
+                }
+                ui.horizontal(|ui| {
+                    go |= ui.button("Go").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if !open || cancel || ctx.input(|i| i.key_pressed(Key::Escape)) {
+            self.go_to = None;
+        } else if go {
+            match LineTarget::parse(&prompt.text) {
+                Ok(target) => {
+                    self.documents.active_mut().go_to_target(target);
+                    self.go_to = None;
+                }
+                Err(e) => {
+                    prompt.error = Some(e);
+                    prompt.focus = true;
+                }
+            }
+        }
+    }
+
+    fn recovery_window(&mut self, ctx: &egui::Context) {
+        if self.recovered.is_empty() {
+            return;
+        }
+
+        let mut restore = false;
+        let mut discard = false;
+        egui::Window::new("♻ Recover Unsaved Work")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label("DNotepadX did not shut down cleanly. These unsaved documents can be restored:");
+                ui.add_space(6.0);
+                let now = recovery::now_secs();
+                for buffer in &self.recovered {
+                    let age = now.saturating_sub(buffer.entry.timestamp) / 60;
+                    let location = buffer.entry.path
+                        .as_ref()
+                        .map(|p| p.display().to_string())
+                        .unwrap_or_else(|| "never saved".to_string());
+                    ui.label(format!("• {} ({}, {} min ago)", buffer.entry.title, location, age));
+                }
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    restore = ui.button("♻ Restore All").clicked();
+                    discard = ui.button("🗑 Discard").clicked();
+                });
+            });
+
+        if restore || discard {
+            for buffer in std::mem::take(&mut self.recovered) {
+                recovery::discard(&buffer);
+                if restore {
+                    let disk = buffer.entry.path.as_deref().and_then(FileOperations::disk_state);
+                    self.documents.restore(buffer.entry, disk);
+                }
+            }
+        }
+    }
+
+    fn refresh_search(&mut self) {
+        self.search.refresh_document(self.documents.active());
+    }
+
+    fn find_next(&mut self) {
+        if self.documents.active().is_read_only() {
+            self.find_in_file(true);
+            return;
+        }
+        // Continue after the current selection, which is the current match after a find
+        let from = self.documents.active().selection_bytes().end;
+        self.select_match_from(from);
+    }
+
+    fn find_previous(&mut self) {
+        if self.documents.active().is_read_only() {
+            self.find_in_file(false);
+            return;
+        }
+        self.refresh_search();
+        let before = self.documents.active().selection_bytes().start;
+        let matches = self.search.matches();
+        let previous = matches
+            .iter()
+            .rev()
+            .find(|m| m.end <= before)
+            .or(matches.last())
+            .cloned();
+        if let Some(range) = previous {
+            self.documents.active_mut().select_bytes(range);
+        }
+    }
+
+    /// Starts searching the viewer's file from the last match, or from the top of the view.
+    fn find_in_file(&mut self, forward: bool) {
+        // An invalid pattern is reported in the Find & Replace window
+        let Ok(regex) = byte_regex(&self.search.find_text, &self.search.options) else {
+            return;
+        };
+        let Some(file) = &mut self.documents.active_mut().mapped else {
+            return;
+        };
+        let from = match file.highlight() {
+            Some(range) if forward => range.end,
+            Some(range) => range.start,
+            None => file.top,
+        };
+        file.find(regex, from, forward);
+    }
+
+    /// Selects the first match starting at or after byte `from`, wrapping around at the end.
+    fn select_match_from(&mut self, from: usize) {
+        self.refresh_search();
+        let matches = self.search.matches();
+        let next = matches
+            .iter()
+            .find(|m| m.start >= from)
+            .or(matches.first())
+            .cloned();
+        if let Some(range) = next {
+            self.documents.active_mut().select_bytes(range);
+        }
+    }
+
+    fn replace_current(&mut self) {
+        self.refresh_search();
+        let selection = self.documents.active().selection_bytes();
+        let current = self.search.matches().iter().find(|m| **m == selection).cloned();
+        let (Some(range), Some(query)) = (current, self.search.query()) else {
+            // Nothing selected yet: the first click just finds the next match
+            self.find_next();
+            return;
+        };
+
+        let doc = self.documents.active_mut();
+        let text = doc.text();
+        let replacement = query.replacement(&text, range.clone(), &self.search.replace_text);
+        let at = text[..range.start].chars().count();
+        let edit = Edit::new(at, &text[range.clone()], replacement.as_str());
+        drop(text);
+        let after = Selection::caret(at + replacement.chars().count());
+        doc.edit(vec![edit], after);
+
+        // Move on to the match following the replaced text
+        self.refresh_search();
+        self.select_match_from(range.start + replacement.len());
+    }
+
     fn replace_all(&mut self) {
-        if self.find_text.is_empty() {
+        self.refresh_search();
+        let Some(query) = self.search.query() else {
+            return;
+        };
+
+        let doc = self.documents.active_mut();
+        let (edits, shift) = query.replace_all_edits(&doc.text(), self.search.matches(), &self.search.replace_text);
+        if edits.is_empty() {
             return;
         }
-        
-        let new_content = self.content.replace(&self.find_text, &self.replace_text);
-        if new_content != self.content {
-            self.content = new_content;
-            self.is_modified = true;
+
+        let after = doc.cursor;
+        let doc_id = doc.id();
+        doc.edit(edits, after);
+        // Keep "In selection" covering the replaced text
+        if let Some((id, scope)) = &mut self.search.scope {
+            if *id == doc_id {
+                scope.end = (scope.end as isize + shift).max(scope.start as isize) as usize;
+            }
         }
     }
 }
@@ -538,24 +2116,305 @@ impl DNotepadX {
 impl eframe::App for DNotepadX {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.apply_theme(ctx);
+        self.track_window(ctx);
+        self.confirm_close(ctx);
+        self.handle_forwarded_files(ctx);
+        self.check_external_changes(ctx);
+
+        // Undo in the Find or Go to Line field undoes the typing there, not the document
+        let editor_id = self.documents.active().editor_id();
+        let field_focused = ctx
+            .memory(|m| m.focused())
+            .is_some_and(|id| id != editor_id && egui::TextEdit::load_state(ctx, id).is_some());
+        for action in self.settings.keybindings.pressed(ctx, field_focused) {
+            self.run_action(action);
+        }
+        if !field_focused {
+            // The text widget has its own undo history on these keys; ours replaces it
+            // even when undo/redo were remapped
+            ctx.input_mut(|i| {
+                i.consume_key(Modifiers::COMMAND, Key::Z);
+                i.consume_key(Modifiers::COMMAND, Key::Y);
+            });
+        }
 
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
             self.menu_bar(ui);
         });
 
+        egui::TopBottomPanel::top("tab_bar").show(ctx, |ui| {
+            self.tab_bar(ui);
+        });
+
+        self.external_change_banner(ctx);
+
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            self.status_bar(ui);
+        });
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            egui::ScrollArea::both()
-                .auto_shrink([false; 2])
-                .show(ui, |ui| {
-                    self.text_editor(ui);
-                });
+            let doc = self.documents.active_mut();
+            let mut scroll_area = egui::ScrollArea::both()
+                .id_source(doc.scroll_id())
+                .auto_shrink([false; 2]);
+            if let Some(offset) = doc.pending_scroll.take() {
+                scroll_area = scroll_area.scroll_offset(offset);
+            }
+            let output = scroll_area.show(ui, |ui| {
+                self.text_editor(ui);
+            });
+            self.documents.active_mut().scroll_offset = output.state.offset;
         });
 
         self.settings_window(ctx);
         self.find_replace_window(ctx);
+        self.go_to_window(ctx);
+        self.recovery_window(ctx);
+        self.compare_window(ctx);
+        self.auto_save(ctx);
+        self.finish_waits(ctx);
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // Snapshots go once their text is saved, discarded or kept by the session;
+        // anything else stays for recovery
+        for doc in self.documents.iter() {
+            let kept = self.settings.restore_session && Session::keeps_unsaved(doc, self.closing);
+            if !doc.is_modified || kept || self.discard_unsaved {
+                self.journal.remove(doc.id());
+            }
+        }
+        self.journal.flush();
+        // Later invocations start their own window instead of talking to a closed one
+        self.listener = None;
     }
 
-    fn save(&mut self, _storage: &mut dyn eframe::Storage) {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        let session = if self.settings.restore_session {
+            // Large documents are copied once on exit rather than every time eframe saves
+            Session::capture(&self.documents, self.maximized, self.closing)
+        } else {
+            Session::default()
+        };
+        session.store(storage);
         self.settings.save();
     }
+
+    /// Widget state is keyed by document ids, which start over on every run; the
+    /// session brings back what matters instead.
+    fn persist_egui_memory(&self) -> bool {
+        false
+    }
+}
+
+/// Documents opened by one `--wait` invocation, either ours (no waiter) or a forwarded one.
+struct Wait {
+    documents: Vec<u64>,
+    waiter: Option<Waiter>,
+}
+
+/// Text typed into the Go to Line prompt.
+struct GoToPrompt {
+    text: String,
+    error: Option<String>,
+    /// Set when the field should take the keyboard focus.
+    focus: bool,
+}
+
+/// A document's buffer compared line by line with its file on disk.
+struct Comparison {
+    document_id: u64,
+    title: String,
+    lines: Vec<(ChangeTag, String)>,
+}
+
+fn megabytes(mb: u64) -> usize {
+    usize::try_from(mb.saturating_mul(1024 * 1024)).unwrap_or(usize::MAX)
+}
+
+/// Size of a file in the largest unit that keeps it above 1, e.g. "3.2 GB".
+fn file_size(bytes: usize) -> String {
+    let mut size = bytes as f64;
+    for unit in ["bytes", "KB", "MB", "GB"] {
+        if size < 1024.0 {
+            return match unit {
+                "bytes" => format!("{} bytes", bytes),
+                _ => format!("{:.1} {}", size, unit),
+            };
+        }
+        size /= 1024.0;
+    }
+    format!("{:.1} TB", size)
+}
+
+/// Status bar of a read-only viewer tab: where the view is and how far indexing has got.
+fn viewer_status(ui: &mut Ui, file: &MappedFile) {
+    ui.horizontal(|ui| {
+        if let Some(line) = file.line_at(file.top) {
+            ui.label(format!("Ln {}", line + 1));
+            ui.separator();
+        }
+        ui.label(format!("Offset {} (0x{:X})", file.top, file.top));
+        ui.separator();
+        match file.line_count() {
+            Some(lines) => ui.label(format!("{} lines", lines)),
+            None => ui.label(format!("Counting lines… {:.0}%", file.indexed() * 100.0)),
+        };
+
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            ui.label(file_size(file.len()));
+            ui.separator();
+            ui.label(if file.binary { "Binary" } else { "Text" });
+            ui.separator();
+            ui.label("Read-only");
+        });
+    });
+}
+
+/// Status bar of a document in the hex editor: the cursor's offset and byte, and
+/// whether typing overwrites or inserts, which can be switched from here.
+fn hex_status(ui: &mut Ui, doc: &mut Document) {
+    let cursor = doc.cursor;
+    let Some(hex) = &mut doc.hex else {
+        return;
+    };
+    ui.horizontal(|ui| {
+        ui.label(format!("Offset {} (0x{:X})", cursor.head, cursor.head));
+        if let Some(byte) = hex.bytes.get(cursor.head) {
+            ui.separator();
+            ui.label(format!("Value 0x{:02X} ({})", byte, byte));
+        }
+        if !cursor.is_empty() {
+            ui.separator();
+            ui.label(format!("{} bytes selected", cursor.end() - cursor.start()));
+        }
+
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            ui.label(file_size(hex.bytes.len()));
+            ui.separator();
+            let mode = if hex.overwrite { "Overwrite" } else { "Insert" };
+            if ui.button(mode).on_hover_text("Typing mode, also switched with the Insert key").clicked() {
+                hex.overwrite = !hex.overwrite;
+            }
+            ui.separator();
+            ui.label("Hex");
+        });
+    });
+}
+
+/// Lists the line endings to convert `doc` to.
+fn line_ending_menu(ui: &mut Ui, doc: &mut Document) {
+    let current = doc.format().line_ending;
+    for ending in LineEnding::ALL {
+        if ui.selectable_label(ending == current, ending.label()).clicked() {
+            doc.set_line_ending(ending);
+            ui.close_menu();
+        }
+    }
+}
+
+/// Lists the encodings that can be picked, returning the one clicked.
+fn encoding_menu(ui: &mut Ui, current: Encoding) -> Option<Encoding> {
+    let mut picked = None;
+    ui.set_min_width(220.0);
+    egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+        for encoding in Encoding::all() {
+            if ui.selectable_label(encoding == current, encoding.label()).clicked() {
+                picked = Some(encoding);
+                ui.close_menu();
+            }
+        }
+    });
+    picked
+}
+
+/// Sample lines for the theme preview: pieces of text with their token kind, and
+/// whether they are selected. The caret goes after the selection.
+const PREVIEW: [&[(&str, Option<TokenKind>, bool)]; 6] = [
+    &[("// Greets everyone on the list", Some(TokenKind::Comment), false)],
+    &[
+        ("fn ", Some(TokenKind::Keyword), false),
+        ("greet", Some(TokenKind::Function), false),
+        ("(names: &[", None, false),
+        ("String", Some(TokenKind::Type), false),
+        ("]) {", None, false),
+    ],
+    &[
+        ("    for ", Some(TokenKind::Keyword), false),
+        ("name", None, true),
+        (" in names.iter().take(", None, false),
+        ("MAX", Some(TokenKind::Constant), false),
+        (" + ", None, false),
+        ("1", Some(TokenKind::Number), false),
+        (") {", None, false),
+    ],
+    &[
+        ("        println!(", None, false),
+        ("\"Hello, {}!\"", Some(TokenKind::String), false),
+        (", name);", None, false),
+    ],
+    &[("    }", None, false)],
+    &[("}", None, false)],
+];
+
+/// A menu bar, a button and a few lines of code in `theme`'s colors, for the theme editor.
+fn theme_preview(ui: &mut Ui, theme: &Theme, font_id: &FontId) {
+    let ui_text = theme.settings_text();
+    egui::Frame::none()
+        .fill(theme.menu_bg())
+        .stroke(Stroke::new(1.0, theme.border()))
+        .inner_margin(Margin::same(6.0))
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                for menu in ["File", "Edit", "View"] {
+                    ui.colored_label(ui_text, menu);
+                }
+                // Drawn by hand: real buttons have the applied theme's style
+                egui::Frame::none()
+                    .fill(theme.button())
+                    .stroke(Stroke::new(1.0, theme.border()))
+                    .rounding(Rounding::same(4.0))
+                    .inner_margin(Margin::symmetric(6.0, 2.0))
+                    .show(ui, |ui| ui.colored_label(ui_text, "Button"));
+            });
+
+            egui::Frame::none()
+                .fill(theme.background())
+                .inner_margin(Margin::same(6.0))
+                .show(ui, |ui| {
+                    ui.spacing_mut().item_spacing.y = 0.0;
+                    for (number, pieces) in PREVIEW.iter().enumerate() {
+                        let mut job = egui::text::LayoutJob::default();
+                        job.append(&format!("{:>2}  ", number + 1), 0.0, TextFormat::simple(font_id.clone(), theme.line_number()));
+                        let mut caret = None;
+                        for &(text, kind, selected) in pieces.iter() {
+                            let color = kind.map_or(theme.text(), |kind| theme.syntax(kind));
+                            let mut format = TextFormat::simple(font_id.clone(), color);
+                            if selected {
+                                format.background = theme.selection();
+                            }
+                            job.append(text, 0.0, format);
+                            if selected {
+                                caret = Some(job.text.chars().count());
+                            }
+                        }
+                        let galley = ui.fonts(|f| f.layout_job(job));
+                        let (rect, _) = ui.allocate_exact_size(galley.size(), egui::Sense::hover());
+                        let caret = caret.map(|caret| rect.left() + galley.pos_from_ccursor(CCursor::new(caret)).left());
+                        ui.painter().galley(rect.min, galley, theme.text());
+                        if let Some(x) = caret {
+                            ui.painter().vline(x, rect.y_range(), Stroke::new(2.0, theme.cursor()));
+                        }
+                    }
+                });
+        });
+}
+
+/// Parses a comma-separated list of chords as typed in the settings window.
+fn parse_chord_list(text: &str) -> Option<Vec<KeyChord>> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(KeyChord::parse)
+        .collect()
 }
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: dnotepadx [OPTIONS] [FILE[:LINE[:COLUMN]]]...
+
+Opens each FILE in a tab. Files that don't exist yet are created when saved.
+
+Options:
+  -l, --line N      Put the caret on line N of the file that follows
+      --new-window  Open a separate window instead of handing the files to
+                    the DNotepadX window that is already running
+  -w, --wait        Keep running until the files given are closed
+  -h, --help        Show this help";
+
+/// A file named on the command line and where to put the caret in it (1-based).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileArg {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl FileArg {
+    /// Splits a `path:line:col` suffix off `arg` unless a file with the full name exists.
+    fn parse(arg: OsString) -> Self {
+        let path = PathBuf::from(&arg);
+        let (path, line, column) = match arg.to_str().and_then(split_position) {
+            Some((name, line, column)) if !path.exists() => (PathBuf::from(name), Some(line), column),
+            _ => (path, None, None),
+        };
+        Self {
+            path: std::path::absolute(&path).unwrap_or(path),
+            line,
+            column,
+        }
+    }
+}
+
+fn split_position(text: &str) -> Option<(&str, usize, Option<usize>)> {
+    let (rest, last) = text.rsplit_once(':')?;
+    let last = last.parse().ok()?;
+    let line_and_column = rest
+        .rsplit_once(':')
+        .and_then(|(name, line)| Some((name, line.parse().ok()?)));
+    match line_and_column {
+        Some((name, line)) if !name.is_empty() => Some((name, line, Some(last))),
+        _ if !rest.is_empty() => Some((rest, last, None)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub files: Vec<FileArg>,
+    pub new_window: bool,
+    pub wait: bool,
+    pub help: bool,
+}
+
+impl Args {
+    pub fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut line = None;
+        let mut only_files = false;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let flag = arg.to_str().filter(|a| !only_files && a.starts_with('-') && *a != "-");
+            match flag {
+                Some("--") => only_files = true,
+                Some("-l" | "--line") => {
+                    let value = args.next().ok_or("--line needs a line number")?;
+                    line = Some(parse_number(&value.to_string_lossy())?);
+                }
+                Some(flag) if flag.starts_with("--line=") => {
+                    line = Some(parse_number(&flag["--line=".len()..])?);
+                }
+                Some("--new-window") => parsed.new_window = true,
+                Some("-w" | "--wait") => parsed.wait = true,
+                Some("-h" | "--help") => parsed.help = true,
+                Some(flag) => return Err(format!("unknown option: {}", flag)),
+                None => {
+                    let mut file = FileArg::parse(arg);
+                    if let Some(line) = line.take() {
+                        file.line = Some(line);
+                        file.column = None;
+                    }
+                    parsed.files.push(file);
+                }
+            }
+        }
+
+        // `--line` after the last file applies to it
+        if let Some(line) = line {
+            let file = parsed.files.last_mut().ok_or("--line needs a file to go with")?;
+            file.line = Some(line);
+            file.column = None;
+        }
+        Ok(parsed)
+    }
+}
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    text.parse().map_err(|_| format!("not a line number: {}", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(OsString::from))
+    }
+
+    fn name(file: &FileArg) -> &str {
+        file.path.file_name().unwrap().to_str().unwrap()
+    }
+
+    #[test]
+    fn position_is_split_off_the_name() {
+        assert_eq!(split_position("main.rs:42:5"), Some(("main.rs", 42, Some(5))));
+        assert_eq!(split_position("main.rs:42"), Some(("main.rs", 42, None)));
+        assert_eq!(split_position("a:b:7"), Some(("a:b", 7, None)));
+        assert_eq!(split_position("a:b:7:3"), Some(("a:b", 7, Some(3))));
+        assert_eq!(split_position("main.rs"), None);
+        assert_eq!(split_position("main.rs:x"), None);
+        assert_eq!(split_position(":42"), None);
+        assert_eq!(split_position("::42"), Some((":", 42, None)));
+    }
+
+    #[test]
+    fn missing_file_takes_line_and_column() {
+        let file = FileArg::parse("no-such-file.txt:12:3".into());
+        assert_eq!(name(&file), "no-such-file.txt");
+        assert!(file.path.is_absolute());
+        assert_eq!((file.line, file.column), (Some(12), Some(3)));
+    }
+
+    // Windows doesn't allow `:` in file names
+    #[cfg(unix)]
+    #[test]
+    fn existing_file_with_colon_keeps_its_name() {
+        let dir = std::env::temp_dir().join(format!("dnotepadx-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes:12");
+        std::fs::write(&path, "").unwrap();
+        let file = FileArg::parse(path.clone().into_os_string());
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(file.path, path);
+        assert_eq!((file.line, file.column), (None, None));
+    }
+
+    #[test]
+    fn line_option_applies_to_the_next_file() {
+        let args = parse(&["--line", "7", "a.txt:1:2", "b.txt", "--line=3", "c.txt"]).unwrap();
+        let positions: Vec<_> = args.files.iter().map(|f| (name(f), f.line, f.column)).collect();
+        assert_eq!(
+            positions,
+            vec![("a.txt", Some(7), None), ("b.txt", None, None), ("c.txt", Some(3), None)]
+        );
+    }
+
+    #[test]
+    fn trailing_line_option_applies_to_the_last_file() {
+        let args = parse(&["a.txt", "b.txt", "-l", "9"]).unwrap();
+        assert_eq!(args.files[0].line, None);
+        assert_eq!(args.files[1].line, Some(9));
+        assert!(parse(&["--line", "9"]).is_err());
+    }
+
+    #[test]
+    fn flags_and_double_dash() {
+        let args = parse(&["-w", "--new-window", "--", "--help", "-"]).unwrap();
+        assert!(args.wait && args.new_window && !args.help);
+        let names: Vec<_> = args.files.iter().map(name).collect();
+        assert_eq!(names, vec!["--help", "-"]);
+
+        let args = parse(&["-"]).unwrap();
+        assert_eq!(args.files.len(), 1);
+        assert!(parse(&["-h"]).unwrap().help);
+    }
+
+    #[test]
+    fn bad_options_are_errors() {
+        assert_eq!(parse(&["--bogus"]).unwrap_err(), "unknown option: --bogus");
+        assert!(parse(&["--line"]).is_err());
+        assert!(parse(&["--line", "x", "a.txt"]).is_err());
+        assert!(parse(&["--line=-1", "a.txt"]).is_err());
+    }
+}
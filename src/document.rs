@@ -0,0 +1,1042 @@
+use crate::editorconfig::EditorConfig;
+use crate::file_operations::{Contents, DiskState, LoadedFile};
+use crate::hex::{as_chars, HexBuffer};
+use crate::mapped::MappedFile;
+use crate::recovery::{JournalEntry, Snapshot};
+use crate::text_format::{FileFormat, Indentation, LineEnding};
+use crate::undo::{apply_all, byte_index, Edit, EditKind, TextStorage, UndoHistory};
+use eframe::egui::{Id, Vec2};
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A text selection expressed in character indices. `head` is where the caret is,
+/// `anchor` is the other end of the selection (equal to `head` when nothing is selected).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Selection {
+    pub anchor: usize,
+    pub head: usize,
+}
+
+impl Selection {
+    pub fn caret(index: usize) -> Self {
+        Self { anchor: index, head: index }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    pub fn start(&self) -> usize {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> usize {
+        self.anchor.max(self.head)
+    }
+}
+
+/// Line and word totals shown in the status bar.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextStats {
+    pub lines: usize,
+    /// Not counted in large-file mode, where it would mean reading the whole text on every edit.
+    pub words: Option<usize>,
+}
+
+impl TextStats {
+    pub fn of(text: &str) -> Self {
+        Self {
+            lines: line_count(text),
+            words: Some(text.split_whitespace().count()),
+        }
+    }
+}
+
+fn line_count(text: &str) -> usize {
+    text.bytes().filter(|&b| b == b'\n').count() + 1
+}
+
+/// Where Go to Line puts the caret: `42`, `42:5`, a number of lines from the caret's
+/// line like `+10` or `-5`, a share of the text like `50%`, or `:5` for a column of the current line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineTarget {
+    line: LinePosition,
+    column: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinePosition {
+    Current,
+    Absolute(usize),
+    Relative(isize),
+    Percent(usize),
+}
+
+impl LineTarget {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || "Enter a line[:column], +N, -N or N%".to_string();
+        let text = text.trim();
+        let (line, column) = match text.split_once(':') {
+            Some((line, column)) => (line.trim(), Some(column.trim().parse().map_err(|_| invalid())?)),
+            None => (text, None),
+        };
+        let line = if line.is_empty() && column.is_some() {
+            LinePosition::Current
+        } else if let Some(percent) = line.strip_suffix('%') {
+            LinePosition::Percent(percent.trim().parse().map_err(|_| invalid())?)
+        } else if line.starts_with(['+', '-']) {
+            LinePosition::Relative(line.parse().map_err(|_| invalid())?)
+        } else {
+            LinePosition::Absolute(line.parse().map_err(|_| invalid())?)
+        };
+        Ok(Self { line, column })
+    }
+
+    /// The 1-based line and column this points at, from the caret's `current` line in a
+    /// text of `lines` lines. Lines past the end are left for `Document::go_to` to clamp.
+    pub fn resolve(&self, current: usize, lines: usize) -> (usize, usize) {
+        let line = match self.line {
+            LinePosition::Current => current,
+            LinePosition::Absolute(line) => line,
+            LinePosition::Relative(offset) => current.saturating_add_signed(offset).max(1),
+            LinePosition::Percent(percent) => lines.saturating_sub(1) * percent.min(100) / 100 + 1,
+        };
+        (line, self.column.unwrap_or(1))
+    }
+}
+
+/// How the file behind a document changed on disk without the editor's involvement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalChange {
+    Modified,
+    Deleted,
+}
+
+/// One open buffer in the editor, shown as a tab.
+pub struct Document {
+    id: u64,
+    pub content: String,
+    /// Holds the text instead of `content` in large-file mode, where `content` stays empty.
+    pub large: Option<Rope>,
+    /// The file behind a read-only viewer tab, which has no `content` of its own.
+    pub mapped: Option<MappedFile>,
+    /// Holds the bytes instead of `content` while the document is in the hex editor.
+    /// Selections and edits then count bytes rather than characters.
+    pub hex: Option<HexBuffer>,
+    pub path: Option<PathBuf>,
+    pub is_modified: bool,
+    /// Encoding and line endings used when the document is written.
+    format: FileFormat,
+    /// Set while the file on disk mixes line endings; saving writes `format`'s everywhere.
+    pub mixed_line_endings: bool,
+    /// The file as last loaded or saved, `None` if it didn't exist then.
+    pub disk: Option<DiskState>,
+    /// Set while the file has changed on disk and the user hasn't decided what to do.
+    pub external_change: Option<ExternalChange>,
+    pub indentation: Indentation,
+    /// What `.editorconfig` files say about the file, applied over the detected format and indentation.
+    pub editorconfig: Option<EditorConfig>,
+    /// Set while the line ending or charset `.editorconfig` asks for differs from the
+    /// file's, until a save converts it.
+    pub editorconfig_converts: bool,
+    pub cursor: Selection,
+    pub scroll_offset: Vec2,
+    /// Scroll offset the editor should jump to, set when a session is restored.
+    pub pending_scroll: Option<Vec2>,
+    /// Syntax chosen by hand in the status bar; `None` detects it from the file.
+    pub language: Option<String>,
+    pub history: UndoHistory,
+    /// History state at the last save, to detect when undo returns to it.
+    saved_state: u64,
+    /// Set when `cursor` was changed programmatically and must be pushed to the editor widget.
+    pub sync_cursor: bool,
+    /// Set when the editor should scroll the caret into view and take focus.
+    pub reveal_cursor: bool,
+    /// Incremented on every change to `content`, so derived data can be cached.
+    revision: u64,
+    last_edit: Option<Instant>,
+    /// First edit not yet written anywhere, neither to the file nor to the recovery journal.
+    pending_since: Option<Instant>,
+}
+
+impl Document {
+    fn new(id: u64, path: Option<PathBuf>, file: LoadedFile, defaults: &DocumentDefaults) -> Self {
+        // Files that don't exist yet get the configured line ending
+        let format = match file.disk {
+            Some(_) => file.format,
+            None => FileFormat {
+                line_ending: defaults.line_ending,
+                ..file.format
+            },
+        };
+        let indentation = Indentation::detect(&file.content, defaults.indentation);
+        let (content, large) = if file.content.len() >= defaults.large_file_bytes {
+            (String::new(), Some(Rope::from_str(&file.content)))
+        } else {
+            (file.content, None)
+        };
+        let mut doc = Self {
+            id,
+            indentation,
+            editorconfig: None,
+            editorconfig_converts: false,
+            content,
+            large,
+            mapped: None,
+            hex: None,
+            path,
+            is_modified: false,
+            format,
+            mixed_line_endings: file.mixed_line_endings,
+            disk: file.disk,
+            external_change: None,
+            cursor: Selection::default(),
+            scroll_offset: Vec2::ZERO,
+            pending_scroll: None,
+            language: None,
+            history: UndoHistory::new(defaults.undo_depth),
+            saved_state: 0,
+            sync_cursor: false,
+            reveal_cursor: false,
+            revision: 0,
+            last_edit: None,
+            pending_since: None,
+        };
+        let editorconfig = doc.path.as_deref().and_then(EditorConfig::resolve);
+        doc.set_editorconfig(editorconfig);
+        doc
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn title(&self) -> String {
+        self.path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .and_then(|n| n.to_str())
+            .unwrap_or("Untitled")
+            .to_string()
+    }
+
+    /// Title prefixed with a dot when the buffer has unsaved changes, or a lock in the viewer.
+    pub fn display_title(&self) -> String {
+        if self.is_modified {
+            format!("● {}", self.title())
+        } else if self.is_read_only() {
+            format!("🔒 {}", self.title())
+        } else {
+            self.title()
+        }
+    }
+
+    /// An untitled, empty and unmodified buffer can be replaced by an opened file
+    /// instead of leaving a useless tab behind.
+    pub fn is_blank(&self) -> bool {
+        self.path.is_none() && !self.is_modified && self.content.is_empty()
+    }
+
+    /// Widget id for this document's text editor, so every tab keeps its own egui state.
+    pub fn editor_id(&self) -> Id {
+        Id::new(("document_editor", self.id))
+    }
+
+    /// Widget id for this document's scroll area.
+    pub fn scroll_id(&self) -> Id {
+        Id::new(("document_scroll", self.id))
+    }
+
+    pub fn is_large(&self) -> bool {
+        self.large.is_some()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.mapped.is_some()
+    }
+
+    pub fn is_hex(&self) -> bool {
+        self.hex.is_some()
+    }
+
+    /// The whole text; in large-file mode this is a copy.
+    pub fn text(&self) -> Cow<'_, str> {
+        match &self.large {
+            Some(rope) => Cow::Owned(rope.to_string()),
+            None => Cow::Borrowed(&self.content),
+        }
+    }
+
+    /// The contents as one string: the text, or in the hex editor the bytes one per character.
+    pub fn snapshot(&self) -> Cow<'_, str> {
+        match &self.hex {
+            Some(hex) => Cow::Owned(as_chars(&hex.bytes)),
+            None => self.text(),
+        }
+    }
+
+    /// The contents for the recovery journal. Cloning a rope shares its text, so this
+    /// is cheap in large-file mode too.
+    pub fn journal_snapshot(&self) -> Snapshot {
+        match (&self.large, &self.hex) {
+            (Some(rope), _) => Snapshot::Rope(rope.clone()),
+            (_, Some(hex)) => Snapshot::Bytes(hex.bytes.clone()),
+            _ => Snapshot::Text(self.content.clone()),
+        }
+    }
+
+    /// Puts back contents taken with `snapshot`, as an undo step except in large-file mode,
+    /// like `reload`.
+    pub fn restore_snapshot(&mut self, snapshot: String) {
+        if let Some(rope) = &mut self.large {
+            *rope = Rope::from_str(&snapshot);
+            self.cursor = Selection::caret(self.cursor.head.min(rope.len_chars()));
+            self.history.clear();
+            self.touch();
+            self.sync_cursor = true;
+            return;
+        }
+        let current = self.snapshot();
+        if current != snapshot {
+            let removed = current.into_owned();
+            let after = Selection::caret(self.cursor.head.min(snapshot.chars().count()));
+            self.edit(vec![Edit::new(0, removed, snapshot)], after);
+        }
+    }
+
+    fn storage(&mut self) -> &mut dyn TextStorage {
+        match (&mut self.large, &mut self.hex) {
+            (Some(rope), _) => rope,
+            (_, Some(hex)) => &mut hex.bytes,
+            _ => &mut self.content,
+        }
+    }
+
+    /// What saving writes to the file.
+    pub fn contents(&self) -> Contents<'_> {
+        match &self.hex {
+            Some(hex) => Contents::Bytes(&hex.bytes),
+            None => Contents::Text(self.text(), self.format),
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        match &self.large {
+            Some(rope) => rope.len_lines(),
+            None => line_count(&self.content),
+        }
+    }
+
+    pub fn format(&self) -> FileFormat {
+        self.format
+    }
+
+    /// Changes how the document is written; it then needs saving like any other change.
+    pub fn set_format(&mut self, format: FileFormat) {
+        if format != self.format {
+            self.format = format;
+            self.touch();
+        }
+    }
+
+    /// Writes every line break as `line_ending` from now on. Picking the current one
+    /// still counts as a change when the file mixes line endings, so saving unifies them.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if line_ending != self.format.line_ending || self.mixed_line_endings {
+            self.format.line_ending = line_ending;
+            self.mixed_line_endings = false;
+            self.touch();
+        }
+    }
+
+    /// Takes the indentation `config` sets over the current one, and its line ending
+    /// and charset through `apply_editorconfig_format`.
+    pub fn set_editorconfig(&mut self, config: Option<EditorConfig>) {
+        if let Some(config) = &config {
+            self.indentation = config.indentation(self.indentation);
+        }
+        self.editorconfig = config;
+        self.apply_editorconfig_format();
+    }
+
+    /// Writes the document with the line ending and charset `.editorconfig` sets from
+    /// the next save on. Whenever that differs from the format the file was read in,
+    /// the document is modified until it is saved, whether it was just opened, reloaded
+    /// or switched back from the hex editor. Undo doesn't take the conversion back.
+    fn apply_editorconfig_format(&mut self) {
+        let Some(config) = &self.editorconfig else {
+            return;
+        };
+        let format = FileFormat {
+            encoding: config.encoding().unwrap_or(self.format.encoding),
+            line_ending: config.line_ending().unwrap_or(self.format.line_ending),
+        };
+        if format != self.format {
+            self.format = format;
+            self.editorconfig_converts = true;
+            self.is_modified = true;
+            self.saved_state = u64::MAX;
+        }
+    }
+
+    /// Trims trailing whitespace and adds or removes the final line break before a save,
+    /// as far as `.editorconfig` asks for it. The changes are an undo step of their own.
+    pub fn apply_save_rules(&mut self) {
+        let Some(config) = &self.editorconfig else {
+            return;
+        };
+        if self.is_hex() || self.is_read_only() {
+            return;
+        }
+        let edits = config.save_edits(&self.text());
+        // Positions inside removed text move to where it was
+        let map = |mut position: usize| {
+            for edit in &edits {
+                let removed = edit.removed.chars().count();
+                if position >= edit.at + removed {
+                    position = position - removed + edit.inserted.chars().count();
+                } else if position > edit.at {
+                    position = edit.at;
+                }
+            }
+            position
+        };
+        let after = Selection {
+            anchor: map(self.cursor.anchor),
+            head: map(self.cursor.head),
+        };
+        self.edit(edits, after);
+    }
+
+    /// Replaces the content with the file's current version on disk. The reload is
+    /// an undo step of its own, so the previous text can be brought back, except in
+    /// large-file mode where keeping both versions would cost more than it is worth.
+    pub fn reload(&mut self, file: LoadedFile) {
+        if let Some(rope) = &mut self.large {
+            *rope = Rope::from_str(&file.content);
+            self.cursor = Selection::caret(self.cursor.head.min(rope.len_chars()));
+            self.history.clear();
+            self.touch();
+            self.sync_cursor = true;
+        } else if file.content != self.content {
+            let chars = file.content.chars().count();
+            let after = Selection::caret(self.cursor.head.min(chars));
+            let removed = self.content.clone();
+            self.edit(vec![Edit::new(0, removed, file.content)], after);
+        }
+        self.format = file.format;
+        if let Some(disk) = file.disk {
+            self.mark_saved(disk);
+        }
+        self.mixed_line_endings = file.mixed_line_endings;
+        self.apply_editorconfig_format();
+    }
+
+    /// Switches between editing the text and editing the bytes it is saved as, which
+    /// fails for text the format can't encode or bytes that aren't text. The undo
+    /// history starts over, its steps don't carry over from one to the other.
+    pub fn set_hex(&mut self, hex: bool, large_file_bytes: usize) -> Result<(), String> {
+        if hex == self.is_hex() {
+            return Ok(());
+        }
+        if hex {
+            let bytes = self.format.encode(&self.text())?;
+            self.content = String::new();
+            self.large = None;
+            self.hex = Some(HexBuffer::new(bytes));
+            if std::mem::take(&mut self.mixed_line_endings) {
+                // Encoding unified the line endings, which differ from the file now
+                self.touch();
+            }
+        } else {
+            let bytes = self.hex.as_ref().map_or(&[][..], |hex| &hex.bytes);
+            let decoded = FileFormat::decode(bytes)?;
+            if decoded.text.len() >= large_file_bytes {
+                self.large = Some(Rope::from_str(&decoded.text));
+            } else {
+                self.content = decoded.text;
+            }
+            self.format = decoded.format;
+            self.mixed_line_endings = decoded.mixed_line_endings;
+            self.hex = None;
+            self.apply_editorconfig_format();
+        }
+        self.history.clear();
+        // With the history gone, only an unmodified document can be undone back to its saved state
+        self.saved_state = if self.is_modified { u64::MAX } else { self.history.state_id() };
+        self.revision += 1;
+        self.set_cursor(Selection::default());
+        Ok(())
+    }
+
+    /// Puts `bytes`, the file as it now is on disk, in the hex editor: a viewer tab
+    /// becomes editable this way, and a hex document is reloaded. There is no undo
+    /// step for it, like a reload in large-file mode.
+    pub fn load_bytes(&mut self, bytes: Vec<u8>, disk: DiskState) {
+        self.mapped = None;
+        self.content = String::new();
+        self.large = None;
+        self.cursor = Selection::caret(self.cursor.head.min(bytes.len()));
+        match &mut self.hex {
+            Some(hex) => hex.bytes = bytes,
+            None => self.hex = Some(HexBuffer::new(bytes)),
+        }
+        self.history.clear();
+        self.revision += 1;
+        self.sync_cursor = true;
+        self.mark_saved(disk);
+    }
+
+    /// Keeps the buffer after an outside change; saving it will overwrite `disk` without asking.
+    pub fn keep_mine(&mut self, disk: Option<DiskState>) {
+        self.disk = disk;
+        self.external_change = None;
+        self.touch();
+    }
+
+    /// Rewrites the indentation of the whole text in the document's `indentation` style.
+    pub fn reindent(&mut self) {
+        let text = self.text();
+        let converted = self.indentation.reindent(&text);
+        if converted != text {
+            let chars = converted.chars().count();
+            let after = Selection::caret(self.cursor.head.min(chars));
+            let removed = text.into_owned();
+            self.edit(vec![Edit::new(0, removed, converted)], after);
+        }
+    }
+
+    /// Moves the caret/selection and asks the editor widget to follow.
+    pub fn set_cursor(&mut self, selection: Selection) {
+        self.cursor = selection;
+        self.sync_cursor = true;
+    }
+
+    /// Brings back the selection and scroll offset of an earlier session. The editors
+    /// that scroll by lines or rows center the selection instead.
+    pub fn restore_view(&mut self, selection: Selection, scroll: Vec2) {
+        let len = match (&self.large, &self.hex) {
+            (Some(rope), _) => rope.len_chars(),
+            (_, Some(hex)) => hex.bytes.len(),
+            _ => self.content.chars().count(),
+        };
+        let selection = Selection {
+            anchor: selection.anchor.min(len),
+            head: selection.head.min(len),
+        };
+        if self.is_large() || self.is_hex() {
+            self.reveal(selection);
+        } else {
+            self.set_cursor(selection);
+            self.pending_scroll = Some(scroll);
+        }
+    }
+
+    /// Moves the selection and scrolls it into view.
+    pub fn reveal(&mut self, selection: Selection) {
+        self.set_cursor(selection);
+        self.reveal_cursor = true;
+    }
+
+    /// Byte range of the current selection in `content`.
+    pub fn selection_bytes(&self) -> Range<usize> {
+        if self.is_hex() {
+            return self.cursor.start()..self.cursor.end();
+        }
+        if let Some(rope) = &self.large {
+            return rope.char_to_byte(self.cursor.start())..rope.char_to_byte(self.cursor.end());
+        }
+        let start = byte_index(&self.content, self.cursor.start());
+        let end = start + byte_index(&self.content[start..], self.cursor.end() - self.cursor.start());
+        start..end
+    }
+
+    /// 1-based line and column of the caret.
+    pub fn line_column(&self) -> (usize, usize) {
+        if let Some(rope) = &self.large {
+            let line = rope.char_to_line(self.cursor.head);
+            return (line + 1, self.cursor.head - rope.line_to_char(line) + 1);
+        }
+        let before = &self.content[..byte_index(&self.content, self.cursor.head)];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (line_count(before), before[line_start..].chars().count() + 1)
+    }
+
+    /// Puts the caret on 1-based `line` and `column`, clamped to the text, and scrolls there.
+    pub fn go_to(&mut self, line: usize, column: usize) {
+        if let Some(rope) = &self.large {
+            let line = (line.max(1) - 1).min(rope.len_lines() - 1);
+            let start = rope.line_to_char(line);
+            let len = rope.line(line).chars().take_while(|&c| c != '\n').count();
+            self.reveal(Selection::caret(start + (column.max(1) - 1).min(len)));
+            return;
+        }
+        let mut index = 0;
+        let mut lines = self.content.split('\n').take(line.max(1)).peekable();
+        while let Some(text) = lines.next() {
+            let len = text.chars().count();
+            if lines.peek().is_some() {
+                index += len + 1;
+            } else {
+                index += (column.max(1) - 1).min(len);
+            }
+        }
+        self.reveal(Selection::caret(index));
+    }
+
+    /// Moves the caret to `target`, relative to where it is now, through `go_to`.
+    pub fn go_to_target(&mut self, target: LineTarget) {
+        let (line, column) = target.resolve(self.line_column().0, self.line_count());
+        self.go_to(line, column);
+    }
+
+    /// Number of characters and lines the selection spans.
+    pub fn selection_size(&self) -> (usize, usize) {
+        let chars = self.cursor.end() - self.cursor.start();
+        let lines = match &self.large {
+            Some(rope) => rope.char_to_line(self.cursor.end()) - rope.char_to_line(self.cursor.start()) + 1,
+            None => line_count(&self.content[self.selection_bytes()]),
+        };
+        (chars, lines)
+    }
+
+    /// Selects the given byte range of `content` and scrolls it into view.
+    pub fn select_bytes(&mut self, range: Range<usize>) {
+        if self.is_hex() {
+            self.reveal(Selection {
+                anchor: range.start,
+                head: range.end,
+            });
+            return;
+        }
+        if let Some(rope) = &self.large {
+            let selection = Selection {
+                anchor: rope.byte_to_char(range.start),
+                head: rope.byte_to_char(range.end),
+            };
+            self.reveal(selection);
+            return;
+        }
+        let anchor = self.content[..range.start].chars().count();
+        let head = anchor + self.content[range].chars().count();
+        self.reveal(Selection { anchor, head });
+    }
+
+    /// Applies programmatic `edits` (in order) as a single undo step.
+    pub fn edit(&mut self, edits: Vec<Edit>, after: Selection) {
+        if edits.is_empty() {
+            return;
+        }
+        apply_all(self.storage(), &edits);
+        self.history.record(edits, EditKind::Other, self.cursor, after, 0.0);
+        self.touch();
+        self.set_cursor(after);
+    }
+
+    /// Records edits the text widget already applied to `content`.
+    pub fn record_edits(&mut self, edits: Vec<Edit>, after: Selection, time: f64) {
+        let kind = EditKind::classify(&edits);
+        self.history.record(edits, kind, self.cursor, after, time);
+        self.touch();
+        self.cursor = after;
+    }
+
+    pub fn undo(&mut self) {
+        let storage: &mut dyn TextStorage = match (&mut self.large, &mut self.hex) {
+            (Some(rope), _) => rope,
+            (_, Some(hex)) => &mut hex.bytes,
+            _ => &mut self.content,
+        };
+        if let Some(selection) = self.history.undo(storage) {
+            self.touch();
+            self.is_modified = self.history.state_id() != self.saved_state;
+            self.reveal(selection);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        let storage: &mut dyn TextStorage = match (&mut self.large, &mut self.hex) {
+            (Some(rope), _) => rope,
+            (_, Some(hex)) => &mut hex.bytes,
+            _ => &mut self.content,
+        };
+        if let Some(selection) = self.history.redo(storage) {
+            self.touch();
+            self.is_modified = self.history.state_id() != self.saved_state;
+            self.reveal(selection);
+        }
+    }
+
+    /// Records that the content was written to disk, which now looks like `disk`.
+    pub fn mark_saved(&mut self, disk: DiskState) {
+        self.disk = Some(disk);
+        self.external_change = None;
+        self.mixed_line_endings = false;
+        self.editorconfig_converts = false;
+        self.is_modified = false;
+        self.saved_state = self.history.state_id();
+        self.history.seal();
+        self.pending_since = None;
+    }
+
+    fn touch(&mut self) {
+        let now = Instant::now();
+        self.revision += 1;
+        self.is_modified = true;
+        self.last_edit = Some(now);
+        self.pending_since.get_or_insert(now);
+    }
+
+    /// Whether unwritten edits should be persisted now: either typing paused for
+    /// `idle`, or edits have been piling up for longer than `interval`.
+    pub fn persist_due(&self, now: Instant, idle: Duration, interval: Duration) -> bool {
+        match (self.pending_since, self.last_edit) {
+            (Some(pending), Some(last)) => now - last >= idle || now - pending >= interval,
+            _ => false,
+        }
+    }
+
+    /// Marks pending edits as written to the recovery journal.
+    pub fn mark_journaled(&mut self) {
+        self.pending_since = None;
+    }
+}
+
+/// Settings applied to every document that is opened.
+pub struct DocumentDefaults {
+    pub undo_depth: usize,
+    /// Used when the text doesn't show how it is indented.
+    pub indentation: Indentation,
+    /// Line ending of files created in the editor.
+    pub line_ending: LineEnding,
+    /// Files at least this large open in large-file mode.
+    pub large_file_bytes: usize,
+}
+
+/// The ordered set of open documents together with the active tab.
+/// There is always at least one document open.
+pub struct Documents {
+    docs: Vec<Document>,
+    active: usize,
+    next_id: u64,
+    defaults: DocumentDefaults,
+}
+
+impl Documents {
+    pub fn new(defaults: DocumentDefaults) -> Self {
+        Self {
+            docs: vec![Document::new(0, None, LoadedFile::default(), &defaults)],
+            active: 0,
+            next_id: 1,
+            defaults,
+        }
+    }
+
+    pub fn set_undo_depth(&mut self, undo_depth: usize) {
+        self.defaults.undo_depth = undo_depth;
+        for doc in &mut self.docs {
+            doc.history.set_max_depth(undo_depth);
+        }
+    }
+
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.defaults.line_ending = line_ending;
+    }
+
+    /// Changes the indentation of new documents, and of blank ones that have nothing to detect it from.
+    pub fn set_indentation(&mut self, indentation: Indentation) {
+        self.defaults.indentation = indentation;
+        for doc in self.docs.iter_mut().filter(|doc| doc.is_blank()) {
+            doc.indentation = indentation;
+        }
+    }
+
+    pub fn set_large_file_bytes(&mut self, large_file_bytes: usize) {
+        self.defaults.large_file_bytes = large_file_bytes;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Document> {
+        self.docs.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Document> {
+        self.docs.iter_mut()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Document> {
+        self.docs.get(index)
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn active(&self) -> &Document {
+        &self.docs[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Document {
+        &mut self.docs[self.active]
+    }
+
+    pub fn set_active(&mut self, index: usize) {
+        if index < self.docs.len() {
+            self.active = index;
+        }
+    }
+
+    pub fn find_by_path(&self, path: &Path) -> Option<usize> {
+        self.docs.iter().position(|d| d.path.as_deref() == Some(path))
+    }
+
+    /// Opens a new untitled tab and makes it active.
+    pub fn new_untitled(&mut self) -> usize {
+        self.push(None, LoadedFile::default())
+    }
+
+    /// Opens `file` loaded from `path`, reusing a blank active tab if there is one.
+    pub fn open(&mut self, path: PathBuf, file: LoadedFile) -> usize {
+        if self.active().is_blank() {
+            let id = self.alloc_id();
+            self.docs[self.active] = Document::new(id, Some(path), file, &self.defaults);
+            self.active
+        } else {
+            self.push(Some(path), file)
+        }
+    }
+
+    /// Opens `file` from `path` in a read-only viewer tab.
+    pub fn open_mapped(&mut self, path: PathBuf, file: MappedFile) -> usize {
+        let index = self.open(path, LoadedFile::default());
+        self.docs[index].mapped = Some(file);
+        index
+    }
+
+    /// Opens a buffer recovered after a crash; it is unsaved until the user saves it.
+    /// A file the session already reopened gets the recovered text in its tab. `disk` is
+    /// the file as it is now, so saving only warns when it changed after the restore.
+    /// Hex entries hold bytes one per character and open in the hex editor.
+    pub fn restore(&mut self, entry: JournalEntry, disk: Option<DiskState>) {
+        let JournalEntry { path, content, hex, format, .. } = entry;
+        let reopened = path.as_deref().and_then(|path| self.find_by_path(path));
+        if let Some(index) = reopened.filter(|&i| self.docs[i].is_hex() == hex && !self.docs[i].is_read_only()) {
+            self.active = index;
+            let doc = &mut self.docs[index];
+            doc.restore_snapshot(content);
+            doc.set_format(format);
+            return;
+        }
+        let file = |content| LoadedFile {
+            content,
+            format,
+            disk,
+            ..Default::default()
+        };
+        if hex {
+            let index = match path {
+                Some(path) => self.open(path, file(String::new())),
+                None if self.active().is_blank() => self.active,
+                None => self.new_untitled(),
+            };
+            let doc = &mut self.docs[index];
+            doc.format = format;
+            // Empty text always encodes, so this can't fail
+            let _ = doc.set_hex(true, self.defaults.large_file_bytes);
+            doc.restore_snapshot(content);
+            return;
+        }
+        let index = match path {
+            Some(path) => self.open(path, file(content)),
+            None if self.active().is_blank() => {
+                let doc = &mut self.docs[self.active];
+                doc.indentation = Indentation::detect(&content, self.defaults.indentation);
+                doc.content = content;
+                doc.format = format;
+                self.active
+            }
+            None => self.push(None, file(content)),
+        };
+        self.docs[index].touch();
+    }
+
+    fn push(&mut self, path: Option<PathBuf>, file: LoadedFile) -> usize {
+        let id = self.alloc_id();
+        self.docs.push(Document::new(id, path, file, &self.defaults));
+        self.active = self.docs.len() - 1;
+        self.active
+    }
+
+    fn alloc_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Removes the tab at `index`. Closing the last tab leaves a fresh untitled one.
+    pub fn close(&mut self, index: usize) {
+        if index >= self.docs.len() {
+            return;
+        }
+        self.docs.remove(index);
+        if self.docs.is_empty() {
+            self.new_untitled();
+            return;
+        }
+        if self.active > index || self.active >= self.docs.len() {
+            self.active = self.active.saturating_sub(1);
+        }
+    }
+
+    /// Closes every tab except the one at `index`, which becomes active.
+    pub fn close_others(&mut self, index: usize) {
+        if index >= self.docs.len() {
+            return;
+        }
+        let keep = self.docs.swap_remove(index);
+        self.docs = vec![keep];
+        self.active = 0;
+    }
+
+    /// Moves the tab at `from` so that it ends up at position `to`, keeping the same tab active.
+    pub fn move_tab(&mut self, from: usize, to: usize) {
+        if from >= self.docs.len() || to >= self.docs.len() || from == to {
+            return;
+        }
+        let active_id = self.docs[self.active].id;
+        let doc = self.docs.remove(from);
+        self.docs.insert(to, doc);
+        self.active = self.docs.iter().position(|d| d.id == active_id).unwrap_or(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> DocumentDefaults {
+        DocumentDefaults {
+            undo_depth: 100,
+            indentation: Indentation::spaces(4),
+            line_ending: LineEnding::Lf,
+            large_file_bytes: usize::MAX,
+        }
+    }
+
+    fn loaded(text: &str) -> LoadedFile {
+        LoadedFile {
+            content: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn document(text: &str, large: bool) -> Document {
+        let mut doc = Document::new(0, None, loaded(text), &defaults());
+        if large {
+            doc.large = Some(Rope::from_str(&std::mem::take(&mut doc.content)));
+        }
+        doc
+    }
+
+    fn target(text: &str) -> LineTarget {
+        LineTarget::parse(text).unwrap()
+    }
+
+    #[test]
+    fn line_targets_parse() {
+        let at = |line, column| LineTarget { line, column };
+        assert_eq!(target("42"), at(LinePosition::Absolute(42), None));
+        assert_eq!(target(" 42 : 5 "), at(LinePosition::Absolute(42), Some(5)));
+        assert_eq!(target("+10"), at(LinePosition::Relative(10), None));
+        assert_eq!(target("-5:2"), at(LinePosition::Relative(-5), Some(2)));
+        assert_eq!(target("50 %"), at(LinePosition::Percent(50), None));
+        assert_eq!(target(":7"), at(LinePosition::Current, Some(7)));
+        for invalid in ["", "abc", "%", "+", "4:", "4:x", "-5%", "1:2:3"] {
+            assert!(LineTarget::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn line_targets_resolve_from_the_current_line() {
+        assert_eq!(target("42:5").resolve(10, 100), (42, 5));
+        assert_eq!(target(":5").resolve(10, 100), (10, 5));
+        assert_eq!(target("+10").resolve(10, 100), (20, 1));
+        assert_eq!(target("-50").resolve(10, 100), (1, 1));
+        // Past the end is clamped later, by `go_to`
+        assert_eq!(target("+1000").resolve(10, 100), (1010, 1));
+        assert_eq!(target("0%").resolve(10, 100), (1, 1));
+        assert_eq!(target("50%").resolve(10, 100), (50, 1));
+        assert_eq!(target("100%").resolve(10, 100), (100, 1));
+        assert_eq!(target("250%").resolve(10, 100), (100, 1));
+        assert_eq!(target("50%").resolve(1, 1), (1, 1));
+    }
+
+    #[test]
+    fn editorconfig_format_is_a_pending_change_until_saved() {
+        let dir = std::env::temp_dir().join(format!("dnotepadx-document-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(".editorconfig"), "root = true\n[*]\nend_of_line = crlf\n").unwrap();
+        let path = dir.join("notes.txt");
+        let disk = DiskState {
+            modified: None,
+            len: 2,
+            hash: 0,
+        };
+
+        let mut doc = Document::new(0, Some(path), loaded("a\n"), &defaults());
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(doc.format().line_ending, LineEnding::Crlf);
+        assert!(doc.is_modified && doc.editorconfig_converts);
+
+        // Undoing an edit doesn't undo the conversion
+        doc.edit(vec![Edit::new(1, "", "b")], Selection::caret(2));
+        doc.undo();
+        assert!(doc.is_modified);
+
+        doc.mark_saved(disk);
+        assert!(!doc.is_modified && !doc.editorconfig_converts);
+        // A reload reads the file's format, which gets the same treatment
+        doc.reload(LoadedFile {
+            disk: Some(disk),
+            ..loaded("a\n")
+        });
+        assert_eq!(doc.format().line_ending, LineEnding::Crlf);
+        assert!(doc.is_modified && doc.editorconfig_converts);
+
+        // A file already in the format asked for is left alone
+        let mut crlf = Document::new(1, None, loaded("a\n"), &defaults());
+        crlf.set_format(FileFormat {
+            line_ending: LineEnding::Crlf,
+            ..crlf.format()
+        });
+        crlf.mark_saved(disk);
+        crlf.set_editorconfig(doc.editorconfig.clone());
+        assert!(!crlf.is_modified && !crlf.editorconfig_converts);
+    }
+
+    #[test]
+    fn go_to_targets_past_the_end_stop_at_the_last_line() {
+        for large in [false, true] {
+            let mut doc = document("one\ntwo\nthree", large);
+            doc.go_to_target(target("2:3"));
+            assert_eq!(doc.cursor.head, 6);
+            doc.go_to_target(target("+10"));
+            assert_eq!(doc.line_column(), (3, 1));
+            doc.go_to_target(target(":99"));
+            assert_eq!(doc.line_column(), (3, 6));
+            doc.go_to_target(target("-99"));
+            assert_eq!(doc.line_column(), (1, 1));
+            doc.go_to_target(target("300%"));
+            assert_eq!(doc.line_column(), (3, 1));
+            doc.go_to_target(target("0"));
+            assert_eq!(doc.line_column(), (1, 1));
+        }
+    }
+}
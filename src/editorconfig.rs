@@ -0,0 +1,544 @@
+use crate::text_format::{Encoding, Indentation, LineEnding};
+use crate::undo::{apply_all, Edit};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The properties DNotepadX applies, in the order they are listed in the status bar.
+const PROPERTIES: [&str; 8] = [
+    "indent_style",
+    "indent_size",
+    "tab_width",
+    "end_of_line",
+    "charset",
+    "trim_trailing_whitespace",
+    "insert_final_newline",
+    "max_line_length",
+];
+
+/// What the `.editorconfig` files from a file's directory up to the one marked
+/// `root = true` say about that file, closer files overriding those further up.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EditorConfig {
+    /// Values of the properties in `PROPERTIES` that are set, lowercased.
+    properties: BTreeMap<&'static str, String>,
+    /// The files that set them, the nearest last.
+    pub sources: Vec<PathBuf>,
+}
+
+impl EditorConfig {
+    /// Reads the `.editorconfig` files that apply to `path`, or `None` when none of
+    /// them sets anything DNotepadX uses.
+    pub fn resolve(path: &Path) -> Option<Self> {
+        let mut files = Vec::new();
+        for dir in path.ancestors().skip(1) {
+            let file = dir.join(".editorconfig");
+            let Ok(text) = std::fs::read_to_string(&file) else {
+                continue;
+            };
+            let parsed = ConfigFile::parse(&text);
+            let root = parsed.root;
+            files.push((dir, file, parsed));
+            if root {
+                break;
+            }
+        }
+
+        let mut config = EditorConfig::default();
+        for (dir, file, parsed) in files.into_iter().rev() {
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+            let relative = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let mut used = false;
+            for section in parsed.sections.iter().filter(|s| s.pattern.is_match(&relative)) {
+                for (key, value) in &section.properties {
+                    let Some(name) = PROPERTIES.iter().find(|name| *name == key) else {
+                        continue;
+                    };
+                    if value == "unset" {
+                        config.properties.remove(name);
+                    } else {
+                        config.properties.insert(name, value.clone());
+                    }
+                    used = true;
+                }
+            }
+            if used {
+                config.sources.push(file);
+            }
+        }
+        (!config.properties.is_empty()).then_some(config)
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(String::as_str)
+    }
+
+    fn number(&self, name: &str) -> Option<usize> {
+        self.get(name).and_then(|value| value.parse().ok()).filter(|&n| n > 0)
+    }
+
+    /// `name = value` for every property in effect.
+    pub fn describe(&self) -> Vec<String> {
+        PROPERTIES
+            .iter()
+            .filter_map(|name| Some(format!("{} = {}", name, self.get(name)?)))
+            .collect()
+    }
+
+    /// `indentation` with what `indent_style`, `indent_size` and `tab_width` say.
+    /// Documents have one width for both, so tabs take `tab_width` and spaces `indent_size`.
+    pub fn indentation(&self, indentation: Indentation) -> Indentation {
+        let use_tabs = match self.get("indent_style") {
+            Some("tab") => true,
+            Some("space") => false,
+            _ => indentation.use_tabs,
+        };
+        let (size, tab_width) = (self.number("indent_size"), self.number("tab_width"));
+        let width = if use_tabs { tab_width.or(size) } else { size.or(tab_width) };
+        Indentation {
+            use_tabs,
+            width: width.unwrap_or(indentation.width),
+        }
+    }
+
+    pub fn line_ending(&self) -> Option<LineEnding> {
+        match self.get("end_of_line")? {
+            "lf" => Some(LineEnding::Lf),
+            "crlf" => Some(LineEnding::Crlf),
+            "cr" => Some(LineEnding::Cr),
+            _ => None,
+        }
+    }
+
+    pub fn encoding(&self) -> Option<Encoding> {
+        match self.get("charset")? {
+            "utf-8-bom" => Encoding::for_label("utf-8", true),
+            "utf-16le" | "utf-16be" => Encoding::for_label(self.get("charset")?, true),
+            charset @ ("utf-8" | "latin1") => Encoding::for_label(charset, false),
+            _ => None,
+        }
+    }
+
+    /// Column of the guide line drawn in the editor.
+    pub fn max_line_length(&self) -> Option<usize> {
+        self.number("max_line_length")
+    }
+
+    /// Edits that saving makes to `text`: trailing whitespace trimmed and the final
+    /// line break added or removed, as the properties ask. In order, for `Document::edit`.
+    pub fn save_edits(&self, text: &str) -> Vec<Edit> {
+        let mut edits = Vec::new();
+        if self.get("trim_trailing_whitespace") == Some("true") {
+            let (mut index, mut removed_so_far) = (0, 0);
+            for line in text.split('\n') {
+                let kept = line.trim_end();
+                if kept.len() < line.len() {
+                    let removed = &line[kept.len()..];
+                    edits.push(Edit::new(index + kept.chars().count() - removed_so_far, removed, ""));
+                    removed_so_far += removed.chars().count();
+                }
+                index += line.chars().count() + 1;
+            }
+        }
+
+        let trimmed;
+        let text = if edits.is_empty() {
+            text
+        } else {
+            let mut copy = text.to_string();
+            apply_all(&mut copy, &edits);
+            trimmed = copy;
+            &trimmed
+        };
+        let len = text.chars().count();
+        match self.get("insert_final_newline") {
+            Some("true") if !text.is_empty() && !text.ends_with('\n') => {
+                edits.push(Edit::new(len, "", "\n"));
+            }
+            Some("false") => {
+                let breaks = text.len() - text.trim_end_matches('\n').len();
+                if breaks > 0 {
+                    edits.push(Edit::new(len - breaks, "\n".repeat(breaks), ""));
+                }
+            }
+            _ => {}
+        }
+        edits
+    }
+}
+
+/// One `.editorconfig` file.
+struct ConfigFile {
+    root: bool,
+    sections: Vec<Section>,
+}
+
+/// A `[glob]` and the properties under it.
+struct Section {
+    pattern: Regex,
+    properties: Vec<(String, String)>,
+}
+
+impl ConfigFile {
+    fn parse(text: &str) -> Self {
+        let mut root = false;
+        let mut sections: Vec<Section> = Vec::new();
+        // Properties of a section whose glob didn't compile are skipped
+        let mut current: Option<usize> = None;
+        let mut in_preamble = true;
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+            if let Some(glob) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                in_preamble = false;
+                current = glob_regex(glob).map(|pattern| {
+                    sections.push(Section {
+                        pattern,
+                        properties: Vec::new(),
+                    });
+                    sections.len() - 1
+                });
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let (key, value) = (key.trim().to_ascii_lowercase(), value.trim().to_ascii_lowercase());
+            if in_preamble {
+                root |= key == "root" && value == "true";
+            } else if let Some(index) = current {
+                sections[index].properties.push((key, value));
+            }
+        }
+        Self { root, sections }
+    }
+}
+
+/// Compiles an EditorConfig glob to a regex over paths relative to the file's directory.
+/// A glob without a `/` matches file names in any directory below.
+fn glob_regex(glob: &str) -> Option<Regex> {
+    let glob = match glob.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if glob.contains('/') => glob.to_string(),
+        None => format!("**/{}", glob),
+    };
+    let chars: Vec<char> = glob.chars().collect();
+    let mut source = String::from("^");
+    translate(&chars, &mut source);
+    source.push('$');
+    Regex::new(&source).ok()
+}
+
+fn translate(chars: &[char], out: &mut String) {
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                out.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                i += 1;
+                if chars.get(i + 1) == Some(&'/') {
+                    // `**/` also matches no directory at all
+                    i += 1;
+                    out.push_str("(?:.*/)?");
+                } else {
+                    out.push_str(".*");
+                }
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            '[' => match chars[i + 1..].iter().position(|&c| c == ']') {
+                Some(length) => {
+                    let class: String = chars[i + 1..i + 1 + length].iter().collect();
+                    let (negated, class) = match class.strip_prefix('!') {
+                        Some(rest) => (true, rest.to_string()),
+                        None => (false, class),
+                    };
+                    let class = class.replace('\\', "\\\\").replace('[', "\\[");
+                    out.push_str(&format!("[{}{}]", if negated { "^/" } else { "" }, class));
+                    i += length + 1;
+                }
+                None => out.push_str("\\["),
+            },
+            '{' => match closing_brace(&chars[i..]) {
+                Some(length) => {
+                    let inner = &chars[i + 1..i + length];
+                    braces(inner, out);
+                    i += length;
+                }
+                None => out.push_str("\\{"),
+            },
+            c => out.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+}
+
+/// Offset of the `}` closing the `{` at the start of `chars`.
+fn closing_brace(chars: &[char]) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, &c) in chars.iter().enumerate() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `{a,b,c}` alternatives or a `{1..10}` range of integers; anything else stays literal.
+fn braces(inner: &[char], out: &mut String) {
+    let text: String = inner.iter().collect();
+    if let Some((low, high)) = text.split_once("..") {
+        if let (Ok(low), Ok(high)) = (low.parse::<i64>(), high.parse::<i64>()) {
+            let (low, high) = (low.min(high), low.max(high));
+            if high - low <= 1000 {
+                let numbers: Vec<String> = (low..=high).map(|n| n.to_string()).collect();
+                out.push_str(&format!("(?:{})", numbers.join("|")));
+            } else {
+                out.push_str("-?[0-9]+");
+            }
+            return;
+        }
+    }
+
+    let mut alternatives = Vec::new();
+    let (mut depth, mut start, mut escaped) = (0, 0, false);
+    for (i, &c) in inner.iter().enumerate() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    alternatives.push(&inner[start..]);
+    if alternatives.len() == 1 {
+        out.push_str(&regex::escape(&format!("{{{}}}", text)));
+        return;
+    }
+    out.push_str("(?:");
+    for (i, alternative) in alternatives.into_iter().enumerate() {
+        if i > 0 {
+            out.push('|');
+        }
+        translate(alternative, out);
+    }
+    out.push(')');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(glob: &str, path: &str) -> bool {
+        glob_regex(glob).unwrap().is_match(path)
+    }
+
+    fn config(properties: &[(&'static str, &str)]) -> EditorConfig {
+        EditorConfig {
+            properties: properties.iter().map(|&(name, value)| (name, value.to_string())).collect(),
+            sources: Vec::new(),
+        }
+    }
+
+    /// Applies the save edits for `properties` to `text`.
+    fn saved(properties: &[(&'static str, &str)], text: &str) -> String {
+        let mut text = text.to_string();
+        let edits = config(properties).save_edits(&text);
+        apply_all(&mut text, &edits);
+        text
+    }
+
+    /// A directory of its own under the temp directory, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("dnotepadx-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn write(&self, path: &str, text: &str) {
+            let file = self.0.join(path);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, text).unwrap();
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn globs_without_a_slash_match_in_any_directory() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", "src/deep/main.rs"));
+        assert!(!matches("*.rs", "main.rsx"));
+        assert!(matches("Makefile", "sub/Makefile"));
+        assert!(matches("file?.txt", "file1.txt"));
+        assert!(!matches("file?.txt", "file10.txt"));
+    }
+
+    #[test]
+    fn globs_with_a_slash_are_anchored() {
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(!matches("src/*.rs", "src/bin/main.rs"));
+        assert!(!matches("src/*.rs", "lib/src/main.rs"));
+        assert!(matches("/top.txt", "top.txt"));
+        assert!(!matches("/top.txt", "sub/top.txt"));
+        // `**/` matches any number of directories, none included
+        assert!(matches("src/**/*.rs", "src/main.rs"));
+        assert!(matches("src/**/*.rs", "src/a/b/main.rs"));
+        assert!(matches("docs/**", "docs/a/b.md"));
+    }
+
+    #[test]
+    fn brace_alternatives_nest() {
+        assert!(matches("*.{js,ts}", "app.ts"));
+        assert!(!matches("*.{js,ts}", "app.rs"));
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            assert!(matches("{a,{b,c}}.txt", name), "{}", name);
+        }
+        assert!(!matches("{a,{b,c}}.txt", "d.txt"));
+        assert!(matches("{src/*,tests/**/*}.rs", "tests/x/y.rs"));
+        // A single alternative or an unclosed brace is literal
+        assert!(matches("{single}.txt", "{single}.txt"));
+        assert!(matches("{open.txt", "{open.txt"));
+        assert!(matches("a\\{b\\}", "a{b}"));
+    }
+
+    #[test]
+    fn brace_ranges_match_integers() {
+        for n in [1, 5, 10] {
+            assert!(matches("file{1..10}.txt", &format!("file{}.txt", n)));
+        }
+        assert!(!matches("file{1..10}.txt", "file0.txt"));
+        assert!(!matches("file{1..10}.txt", "file11.txt"));
+        assert!(matches("v{3..-1}", "v-1"));
+        assert!(matches("n{0..100000}", "n54321"));
+        let mut source = String::new();
+        braces(&"1..3".chars().collect::<Vec<_>>(), &mut source);
+        assert_eq!(source, "(?:1|2|3)");
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(matches("[ab].txt", "a.txt"));
+        assert!(!matches("[ab].txt", "c.txt"));
+        assert!(matches("[!ab].txt", "c.txt"));
+        assert!(!matches("[!ab].txt", "a.txt"));
+        // A negated class never matches a directory separator
+        assert!(!matches("x[!a]y", "x/y"));
+        assert!(matches("[a-c]1", "b1"));
+        assert!(matches("[unclosed", "[unclosed"));
+        let mut source = String::new();
+        translate(&"[!x]".chars().collect::<Vec<_>>(), &mut source);
+        assert_eq!(source, "[^/x]");
+    }
+
+    #[test]
+    fn files_parse_with_any_line_ending() {
+        let text = "; comment\r\nroot = TRUE\r\n\r\n[*.md]\r\nIndent_Style = Space\r\n# note\r\n[{]\r\nignored = 1\r\n[*]\r\ncharset=utf-8\r\n";
+        let file = ConfigFile::parse(text);
+        assert!(file.root);
+        assert_eq!(file.sections.len(), 3);
+        assert!(file.sections[0].pattern.is_match("README.md"));
+        assert_eq!(file.sections[0].properties, vec![("indent_style".to_string(), "space".to_string())]);
+        assert_eq!(file.sections[2].properties, vec![("charset".to_string(), "utf-8".to_string())]);
+
+        // `root` only counts before the first section
+        assert!(!ConfigFile::parse("[*]\nroot = true\n").root);
+        assert!(!ConfigFile::parse("root = false\n").root);
+    }
+
+    #[test]
+    fn nearer_files_win_up_to_the_root() {
+        let dir = TempDir::new("editorconfig");
+        dir.write(".editorconfig", "[*]\nindent_size = 8\ncharset = latin1\n");
+        dir.write("repo/.editorconfig", "root = true\n[*]\nindent_style = space\nindent_size = 2\nend_of_line = crlf\n");
+        dir.write(
+            "repo/src/.editorconfig",
+            "[*.rs]\nindent_size = 4\nend_of_line = unset\n[*.md]\nindent_size = 3\n",
+        );
+
+        let config = EditorConfig::resolve(&dir.0.join("repo/src/main.rs")).unwrap();
+        assert_eq!(config.indentation(Indentation::spaces(1)), Indentation::spaces(4));
+        // `unset` drops what a file further up set; the file above the root isn't read
+        assert_eq!(config.line_ending(), None);
+        assert_eq!(config.encoding(), None);
+        assert_eq!(config.sources, vec![dir.0.join("repo/.editorconfig"), dir.0.join("repo/src/.editorconfig")]);
+
+        let config = EditorConfig::resolve(&dir.0.join("repo/notes.txt")).unwrap();
+        assert_eq!(config.line_ending(), Some(LineEnding::Crlf));
+        assert_eq!(config.sources, vec![dir.0.join("repo/.editorconfig")]);
+
+        let config = EditorConfig::resolve(&dir.0.join("other.txt")).unwrap();
+        assert_eq!(config.encoding(), Encoding::for_label("latin1", false));
+        assert_eq!(config.describe(), vec!["indent_size = 8", "charset = latin1"]);
+    }
+
+    #[test]
+    fn indentation_takes_the_width_for_its_style() {
+        let fallback = Indentation::spaces(4);
+        let tabs = config(&[("indent_style", "tab"), ("indent_size", "2"), ("tab_width", "8")]);
+        assert_eq!(tabs.indentation(fallback), Indentation { use_tabs: true, width: 8 });
+        let spaces = config(&[("indent_style", "space"), ("indent_size", "2"), ("tab_width", "8")]);
+        assert_eq!(spaces.indentation(fallback), Indentation::spaces(2));
+        let invalid = config(&[("indent_size", "tab")]);
+        assert_eq!(invalid.indentation(fallback), fallback);
+    }
+
+    #[test]
+    fn save_edits_trim_in_characters() {
+        let trim = [("trim_trailing_whitespace", "true")];
+        assert_eq!(saved(&trim, "é  \nàà\t\n  \nx"), "é\nàà\n\nx");
+        let edits = config(&trim).save_edits("é  \nàà\t\nx ");
+        // Each edit is at its place after the ones before it were applied
+        let positions: Vec<_> = edits.iter().map(|e| (e.at, e.removed.as_str())).collect();
+        assert_eq!(positions, vec![(1, "  "), (4, "\t"), (6, " ")]);
+    }
+
+    #[test]
+    fn save_edits_fix_the_final_line_break() {
+        let insert = [("insert_final_newline", "true")];
+        assert_eq!(saved(&insert, "ab"), "ab\n");
+        assert_eq!(saved(&insert, "ab\n"), "ab\n");
+        assert_eq!(saved(&insert, ""), "");
+        let remove = [("insert_final_newline", "false")];
+        assert_eq!(saved(&remove, "ab\n\n\n"), "ab");
+        assert_eq!(saved(&remove, "ab"), "ab");
+
+        let both = [("trim_trailing_whitespace", "true"), ("insert_final_newline", "true")];
+        assert_eq!(saved(&both, "é \nü  "), "é\nü\n");
+        let both = [("trim_trailing_whitespace", "true"), ("insert_final_newline", "false")];
+        assert_eq!(saved(&both, "é \n \n"), "é");
+    }
+}
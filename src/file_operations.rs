@@ -1,23 +1,132 @@
+use crate::mapped::MappedFile;
+use crate::text_format::{Decoded, Encoding, FileFormat};
 use rfd::{FileDialog, MessageDialog, MessageLevel};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::{File, Metadata};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What to do with unsaved documents when the window closes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseChoice {
+    Save,
+    Discard,
+    Cancel,
+}
+
+/// Whether saving keeps a copy of the version being overwritten.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupPolicy {
+    #[default]
+    None,
+    /// `notes.txt~`, replaced on every save.
+    Tilde,
+    /// `notes.txt.20261018-142233~` (UTC), a new one on every save.
+    Timestamped,
+}
+
+impl BackupPolicy {
+    pub const ALL: [BackupPolicy; 3] = [BackupPolicy::None, BackupPolicy::Tilde, BackupPolicy::Timestamped];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BackupPolicy::None => "No backup",
+            BackupPolicy::Tilde => "name~",
+            BackupPolicy::Timestamped => "Timestamped",
+        }
+    }
+
+    fn backup_path(self, target: &Path) -> Option<PathBuf> {
+        let name = target.file_name()?.to_string_lossy();
+        let backup = match self {
+            BackupPolicy::None => return None,
+            BackupPolicy::Tilde => format!("{}~", name),
+            BackupPolicy::Timestamped => format!("{}.{}~", name, timestamp(SystemTime::now())),
+        };
+        Some(target.with_file_name(backup))
+    }
+}
+
+/// What a file on disk looked like when the editor last read or wrote it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskState {
+    pub modified: Option<SystemTime>,
+    pub len: u64,
+    pub hash: u64,
+}
+
+impl DiskState {
+    fn new(path: &Path, bytes: &[u8]) -> io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        Ok(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: hasher.finish(),
+        })
+    }
+}
+
+/// A decoded file together with what it looked like on disk.
+#[derive(Debug, Default)]
+pub struct LoadedFile {
+    pub content: String,
+    pub format: FileFormat,
+    pub mixed_line_endings: bool,
+    /// `None` for a file that doesn't exist yet.
+    pub disk: Option<DiskState>,
+}
+
+impl LoadedFile {
+    fn new(decoded: Decoded, disk: DiskState) -> Self {
+        Self {
+            content: decoded.text,
+            format: decoded.format,
+            mixed_line_endings: decoded.mixed_line_endings,
+            disk: Some(disk),
+        }
+    }
+}
+
+/// A file read for a new tab: text for the editor, or a mapping for the read-only viewer.
+pub enum Opened {
+    Text(LoadedFile),
+    Mapped(MappedFile),
+}
+
+/// What a save writes: text, encoded in its format, or the hex editor's bytes as they are.
+pub enum Contents<'a> {
+    Text(Cow<'a, str>, FileFormat),
+    Bytes(&'a [u8]),
+}
+
+impl Contents<'_> {
+    fn encode(&self) -> Result<Cow<'_, [u8]>, String> {
+        match self {
+            Contents::Text(text, format) => format.encode(text).map(Cow::Owned),
+            Contents::Bytes(bytes) => Ok(Cow::Borrowed(bytes)),
+        }
+    }
+}
 
 pub struct FileOperations;
 
 impl FileOperations {
-    pub fn open_file() -> Option<(PathBuf, String)> {
+    pub fn open_file(viewer_bytes: usize) -> Option<(PathBuf, Opened)> {
         if let Some(path) = FileDialog::new()
             .add_filter("Text Files", &["txt", "md", "rs", "py", "js", "html", "css", "json"])
             .add_filter("All Files", &["*"])
             .pick_file()
         {
-            match std::fs::read_to_string(&path) {
-                Ok(content) => Some((path, content)),
+            match Self::open_path(&path, viewer_bytes) {
+                Ok(file) => Some((path, file)),
                 Err(e) => {
-                    MessageDialog::new()
-                        .set_level(MessageLevel::Error)
-                        .set_title("Error Opening File")
-                        .set_description(&format!("Failed to open file: {}", e))
-                        .show();
+                    Self::show_open_error(&e);
                     None
                 }
             }
@@ -26,21 +135,170 @@ impl FileOperations {
         }
     }
 
-    pub fn save_file(path: &Path, content: &str) -> bool {
-        match std::fs::write(path, content) {
-            Ok(()) => true,
+    pub fn show_open_error(error: &io::Error) {
+        MessageDialog::new()
+            .set_level(MessageLevel::Error)
+            .set_title("Error Opening File")
+            .set_description(format!("Failed to open file: {}", error))
+            .show();
+    }
+
+    pub fn pick_font_file() -> Option<PathBuf> {
+        FileDialog::new()
+            .add_filter("Font Files", &["ttf", "otf", "ttc", "otc"])
+            .pick_file()
+    }
+
+    pub fn pick_theme_file() -> Option<PathBuf> {
+        FileDialog::new()
+            .add_filter("Color Themes", &["json", "jsonc", "tmTheme", "yaml", "yml", "toml"])
+            .add_filter("All Files", &["*"])
+            .pick_file()
+    }
+
+    /// Asks where to save a theme, starting in `dir`.
+    pub fn save_theme_dialog(dir: &Path) -> Option<PathBuf> {
+        FileDialog::new()
+            .add_filter("Theme", &["json"])
+            .set_directory(dir)
+            .set_file_name("My Theme.json")
+            .save_file()
+    }
+
+    /// Reads and decodes `path` without any user interaction.
+    pub fn read_file(path: &Path) -> io::Result<LoadedFile> {
+        let bytes = std::fs::read(path)?;
+        let disk = DiskState::new(path, &bytes)?;
+        let decoded = FileFormat::decode(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(LoadedFile::new(decoded, disk))
+    }
+
+    /// Reads `path` for a new tab. Files of at least `viewer_bytes`, and binary files,
+    /// are mapped for the read-only viewer instead of being read into memory.
+    pub fn open_path(path: &Path, viewer_bytes: usize) -> io::Result<Opened> {
+        if std::fs::metadata(path)?.len() >= viewer_bytes as u64 {
+            return MappedFile::open(path).map(Opened::Mapped);
+        }
+        let bytes = std::fs::read(path)?;
+        let disk = DiskState::new(path, &bytes)?;
+        match FileFormat::decode(&bytes) {
+            Ok(decoded) => Ok(Opened::Text(LoadedFile::new(decoded, disk))),
+            // Only binary data fails to decode; the viewer shows it in hex
+            Err(_) => MappedFile::open(path).map(Opened::Mapped),
+        }
+    }
+
+    /// Reads `path` for the hex editor, without decoding it.
+    pub fn read_bytes(path: &Path) -> io::Result<(Vec<u8>, DiskState)> {
+        let bytes = std::fs::read(path)?;
+        let disk = DiskState::new(path, &bytes)?;
+        Ok((bytes, disk))
+    }
+
+    /// Reads `path` as `encoding` instead of recognizing the encoding from the bytes.
+    pub fn read_file_as(path: &Path, encoding: Encoding) -> io::Result<LoadedFile> {
+        let bytes = std::fs::read(path)?;
+        let disk = DiskState::new(path, &bytes)?;
+        Ok(LoadedFile::new(FileFormat::decode_as(&bytes, encoding), disk))
+    }
+
+    /// The current state of `path` on disk, `None` if it can't be read (e.g. it was deleted).
+    pub fn disk_state(path: &Path) -> Option<DiskState> {
+        let bytes = std::fs::read(path).ok()?;
+        DiskState::new(path, &bytes).ok()
+    }
+
+    /// Whether `path` no longer matches `known`, what the editor last read or wrote there.
+    /// A new modification time alone doesn't count when the contents are the same.
+    pub fn changed_on_disk(path: &Path, known: Option<&DiskState>) -> bool {
+        let Some(known) = known else {
+            return path.exists();
+        };
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return true;
+        };
+        if metadata.len() != known.len {
+            return true;
+        }
+        if metadata.modified().ok() == known.modified {
+            return false;
+        }
+        Self::disk_state(path).is_none_or(|current| current.hash != known.hash)
+    }
+
+    /// Writes `contents` to `path` without any user interaction. The bytes go to a
+    /// temporary file that is renamed over the target, so a crash or a full disk
+    /// never leaves a half-written file behind.
+    pub fn write_file(path: &Path, contents: &Contents, backup: BackupPolicy) -> io::Result<DiskState> {
+        let bytes = contents
+            .encode()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        // Replace the file a symlink points to, the link itself stays as it is
+        let target = resolve_symlinks(path);
+        let existing = std::fs::metadata(&target).ok();
+        if existing.is_some() {
+            if let Some(backup_path) = backup.backup_path(&target) {
+                std::fs::copy(&target, backup_path)?;
+            }
+        }
+
+        match write_atomically(&target, &bytes, existing.as_ref()) {
+            Ok(()) => {}
+            // A directory we may not create files in can still hold a file we may write
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied && existing.is_some() => {
+                std::fs::write(&target, &bytes)?;
+            }
+            Err(e) => return Err(e),
+        }
+        DiskState::new(path, &bytes)
+    }
+
+    pub fn save_file(path: &Path, contents: &Contents, backup: BackupPolicy) -> Option<DiskState> {
+        match Self::write_file(path, contents, backup) {
+            Ok(disk) => Some(disk),
             Err(e) => {
-                MessageDialog::new()
-                    .set_level(MessageLevel::Error)
-                    .set_title("Error Saving File")
-                    .set_description(&format!("Failed to save file: {}", e))
-                    .show();
-                false
+                Self::show_save_error(&e);
+                None
             }
         }
     }
 
-    pub fn save_as_dialog(content: &str, default_name: Option<&str>) -> Option<PathBuf> {
+    pub fn show_save_error(error: &dyn std::fmt::Display) {
+        MessageDialog::new()
+            .set_level(MessageLevel::Error)
+            .set_title("Error Saving File")
+            .set_description(format!("Failed to save file: {}", error))
+            .show();
+    }
+
+    pub fn show_switch_error(error: &dyn std::fmt::Display) {
+        MessageDialog::new()
+            .set_level(MessageLevel::Error)
+            .set_title("Error Switching Editor")
+            .set_description(format!("Failed to switch editor: {}", error))
+            .show();
+    }
+
+    /// Asks before saving over a file that changed on disk since it was loaded.
+    pub fn confirm_overwrite_changed(path: &Path) -> bool {
+        let result = MessageDialog::new()
+            .set_level(MessageLevel::Warning)
+            .set_title("File Changed on Disk")
+            .set_description(format!(
+                "{} was changed by another program since it was opened.\n\nOverwrite it with your version?",
+                path.display()
+            ))
+            .set_buttons(rfd::MessageButtons::YesNo)
+            .show();
+
+        matches!(result, rfd::MessageDialogResult::Yes)
+    }
+
+    pub fn save_as_dialog(
+        contents: &Contents,
+        backup: BackupPolicy,
+        default_name: Option<&str>,
+    ) -> Option<(PathBuf, DiskState)> {
         let mut dialog = FileDialog::new()
             .add_filter("Text Files", &["txt"])
             .add_filter("Markdown Files", &["md"])
@@ -50,18 +308,16 @@ impl FileOperations {
             dialog = dialog.set_file_name(name);
         }
 
-        if let Some(path) = dialog.save_file() {
-            if Self::save_file(&path, content) {
-                Some(path)
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+        let path = dialog.save_file()?;
+        let disk = Self::save_file(&path, contents, backup)?;
+        Some((path, disk))
     }
 
-    pub fn save_as_copy_dialog(content: &str, original_path: Option<&Path>) -> Option<PathBuf> {
+    pub fn save_as_copy_dialog(
+        contents: &Contents,
+        backup: BackupPolicy,
+        original_path: Option<&Path>,
+    ) -> Option<(PathBuf, DiskState)> {
         let default_name = if let Some(path) = original_path {
             let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("untitled");
             let extension = path.extension().and_then(|s| s.to_str()).unwrap_or("txt");
@@ -79,15 +335,9 @@ impl FileOperations {
             dialog = dialog.set_file_name(name);
         }
 
-        if let Some(path) = dialog.save_file() {
-            if Self::save_file(&path, content) {
-                Some(path)
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+        let path = dialog.save_file()?;
+        let disk = Self::save_file(&path, contents, backup)?;
+        Some((path, disk))
     }
 
     pub fn confirm_unsaved_changes() -> bool {
@@ -101,6 +351,25 @@ impl FileOperations {
         matches!(result, rfd::MessageDialogResult::Yes)
     }
 
+    /// Asks whether to save `titles` before the window closes.
+    pub fn confirm_close(titles: &[String]) -> CloseChoice {
+        let result = MessageDialog::new()
+            .set_level(MessageLevel::Warning)
+            .set_title("Unsaved Changes")
+            .set_description(format!(
+                "Save changes before closing?\n\n{}",
+                titles.join("\n")
+            ))
+            .set_buttons(rfd::MessageButtons::YesNoCancel)
+            .show();
+
+        match result {
+            rfd::MessageDialogResult::Yes => CloseChoice::Save,
+            rfd::MessageDialogResult::No => CloseChoice::Discard,
+            _ => CloseChoice::Cancel,
+        }
+    }
+
     pub fn show_about_dialog() {
         MessageDialog::new()
             .set_level(MessageLevel::Info)
@@ -119,3 +388,124 @@ impl FileOperations {
             .show();
     }
 }
+
+/// Follows `path` through symlinks to the file they end at, which may not exist yet.
+fn resolve_symlinks(path: &Path) -> PathBuf {
+    let mut target = path.to_path_buf();
+    // Bounded, so a symlink loop can't hang the save
+    for _ in 0..40 {
+        let Ok(link) = std::fs::read_link(&target) else {
+            break;
+        };
+        target = match target.parent() {
+            Some(dir) => dir.join(link),
+            None => link,
+        };
+    }
+    target
+}
+
+fn write_atomically(target: &Path, bytes: &[u8], existing: Option<&Metadata>) -> io::Result<()> {
+    let name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let temp = target.with_file_name(format!(".{}.{}.tmp", name.to_string_lossy(), std::process::id()));
+    let _ = std::fs::remove_file(&temp);
+
+    let result = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            if let Some(metadata) = existing {
+                preserve_metadata(&file, metadata);
+            }
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&temp, target));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    sync_parent(target);
+    result
+}
+
+/// Gives the replacement file the permissions and, where allowed, the owner of the original.
+fn preserve_metadata(file: &File, metadata: &Metadata) {
+    let _ = file.set_permissions(metadata.permissions());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let _ = std::os::unix::fs::fchown(file, Some(metadata.uid()), Some(metadata.gid()));
+    }
+}
+
+/// Makes the rename itself durable.
+#[cfg(unix)]
+fn sync_parent(target: &Path) {
+    if let Some(dir) = target.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        let _ = File::open(dir).and_then(|dir| dir.sync_all());
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_target: &Path) {}
+
+/// `YYYYMMDD-HHMMSS` in UTC.
+fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = ((secs / 86_400) as i64, secs % 86_400);
+    // Days since the epoch to a civil date, after Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> String {
+        timestamp(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn timestamp_of_known_epochs() {
+        assert_eq!(at(0), "19700101-000000");
+        assert_eq!(at(946_684_799), "19991231-235959");
+        assert_eq!(at(1_792_333_353), "20261018-142233");
+    }
+
+    #[test]
+    fn timestamp_around_leap_days() {
+        assert_eq!(at(951_782_399), "20000228-235959");
+        assert_eq!(at(951_782_400), "20000229-000000");
+        assert_eq!(at(951_868_800), "20000301-000000");
+        assert_eq!(at(1_709_210_096), "20240229-123456");
+        // 2100 is not a leap year, so February 28 is followed by March 1
+        assert_eq!(at(4_107_542_400 - 1), "21000228-235959");
+        assert_eq!(at(4_107_542_400), "21000301-000000");
+    }
+
+    #[test]
+    fn timestamp_before_the_epoch_is_the_epoch() {
+        assert_eq!(timestamp(UNIX_EPOCH - Duration::from_secs(60)), "19700101-000000");
+    }
+}
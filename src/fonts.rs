@@ -0,0 +1,177 @@
+use eframe::egui::{Context, FontData, FontDefinitions, FontFamily, FontId};
+use memmap2::Mmap;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use ttf_parser::{name_id, Face, Language};
+
+/// Families egui ships with, offered before the installed ones.
+const BUILT_IN: [&str; 2] = ["Monospace", "Proportional"];
+
+const EXTENSIONS: [&str; 4] = ["ttf", "otf", "ttc", "otc"];
+
+/// The face in a font file that stands for its family.
+#[derive(Debug, Clone)]
+struct FontFace {
+    path: PathBuf,
+    /// Index of the face in a font collection.
+    index: u32,
+    /// How far the face is from the family's regular style; the lowest wins.
+    rank: u8,
+}
+
+/// The monospaced fonts installed on the system plus the font files loaded by hand,
+/// by family name.
+#[derive(Default)]
+pub struct FontLibrary {
+    faces: BTreeMap<String, FontFace>,
+}
+
+impl FontLibrary {
+    /// Looks through the system's font directories, and adds `files`.
+    pub fn scan(files: &[PathBuf]) -> Self {
+        let mut library = Self::default();
+        let mut pending = font_dirs();
+        // Font directories often link to each other
+        let mut visited = HashSet::new();
+        while let Some(dir) = pending.pop() {
+            if !dir.canonicalize().is_ok_and(|dir| visited.insert(dir)) {
+                continue;
+            }
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for path in entries.flatten().map(|entry| entry.path()) {
+                if path.is_dir() {
+                    pending.push(path);
+                } else if is_font_file(&path) {
+                    let _ = library.add(&path, true);
+                }
+            }
+        }
+        for file in files {
+            let _ = library.add(file, false);
+        }
+        library
+    }
+
+    /// Adds the faces in the font file at `path`, leaving out proportional ones with
+    /// `monospaced_only`. Returns the families found, which fails for files that hold none.
+    pub fn add(&mut self, path: &Path, monospaced_only: bool) -> io::Result<Vec<String>> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is only read while the file is parsed here; the font
+        // data egui gets is a copy read when the font is installed.
+        let data = unsafe { Mmap::map(&file)? };
+        let mut families = Vec::new();
+        for index in 0..ttf_parser::fonts_in_collection(&data).unwrap_or(1) {
+            let Ok(face) = Face::parse(&data, index) else {
+                continue;
+            };
+            if monospaced_only && !face.is_monospaced() {
+                continue;
+            }
+            let Some(family) = family_name(&face) else {
+                continue;
+            };
+            let rank = u8::from(!face.is_regular()) + u8::from(face.is_bold()) + u8::from(face.is_italic());
+            if self.faces.get(&family).is_none_or(|known| rank < known.rank) {
+                let face = FontFace {
+                    path: path.to_path_buf(),
+                    index,
+                    rank,
+                };
+                self.faces.insert(family.clone(), face);
+            }
+            if !families.contains(&family) {
+                families.push(family);
+            }
+        }
+        if families.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a TrueType or OpenType font"));
+        }
+        Ok(families)
+    }
+
+    /// Names for the font picker: egui's own families, then the fonts found.
+    pub fn families(&self) -> impl Iterator<Item = &str> {
+        BUILT_IN.into_iter().chain(self.faces.keys().map(String::as_str))
+    }
+
+    /// Registers `family` as a font family of its own, for `font_id` from the next frame
+    /// on. egui's monospace fonts fill in the glyphs it lacks.
+    pub fn install(&self, ctx: &Context, family: &str) {
+        let mut fonts = FontDefinitions::default();
+        let data = self.faces.get(family).and_then(|face| Some((std::fs::read(&face.path).ok()?, face.index)));
+        if let Some((data, index)) = data {
+            fonts.font_data.insert(
+                family.to_string(),
+                FontData {
+                    index,
+                    ..FontData::from_owned(data)
+                },
+            );
+            let mut fallbacks = vec![family.to_string()];
+            fallbacks.extend(fonts.families[&FontFamily::Monospace].iter().cloned());
+            fonts.families.insert(FontFamily::Name(family.into()), fallbacks);
+        }
+        ctx.set_fonts(fonts);
+    }
+}
+
+/// The font for `family`, or egui's monospace font while that family isn't installed.
+pub fn font_id(ctx: &Context, family: &str, size: f32) -> FontId {
+    let family = match family {
+        "Monospace" => FontFamily::Monospace,
+        "Proportional" => FontFamily::Proportional,
+        name => FontFamily::Name(name.into()),
+    };
+    if matches!(family, FontFamily::Name(_)) && !ctx.fonts(|f| f.families().contains(&family)) {
+        return FontId::monospace(size);
+    }
+    FontId::new(size, family)
+}
+
+/// The family name of `face`, in English when it has several.
+fn family_name(face: &Face) -> Option<String> {
+    let names: Vec<_> = face
+        .names()
+        .into_iter()
+        .filter(|name| name.name_id == name_id::TYPOGRAPHIC_FAMILY || name.name_id == name_id::FAMILY)
+        .collect();
+    // The typographic family groups more styles than the legacy one, so it comes first
+    [name_id::TYPOGRAPHIC_FAMILY, name_id::FAMILY].into_iter().find_map(|id| {
+        let candidates = || names.iter().filter(move |name| name.name_id == id);
+        candidates()
+            .filter(|name| name.language() == Language::English_UnitedStates)
+            .chain(candidates())
+            .find_map(|name| name.to_string())
+            .filter(|name| !name.trim().is_empty())
+    })
+}
+
+fn is_font_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+/// Where the system and the user keep fonts; fontconfig's standard directories on Linux.
+fn font_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if cfg!(target_os = "windows") {
+        let windows = std::env::var_os("WINDIR").map_or_else(|| PathBuf::from("C:\\Windows"), PathBuf::from);
+        dirs.push(windows.join("Fonts"));
+        dirs.extend(dirs::data_local_dir().map(|dir| dir.join("Microsoft").join("Windows").join("Fonts")));
+    } else if cfg!(target_os = "macos") {
+        dirs.push(PathBuf::from("/System/Library/Fonts"));
+        dirs.push(PathBuf::from("/Library/Fonts"));
+        dirs.extend(dirs::home_dir().map(|home| home.join("Library").join("Fonts")));
+    } else {
+        dirs.push(PathBuf::from("/usr/share/fonts"));
+        dirs.push(PathBuf::from("/usr/local/share/fonts"));
+        dirs.extend(dirs::data_dir().map(|dir| dir.join("fonts")));
+        dirs.extend(dirs::home_dir().map(|home| home.join(".fonts")));
+    }
+    dirs
+}
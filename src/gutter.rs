@@ -0,0 +1,71 @@
+use eframe::egui::{self, Align2, Color32, FontId, Galley, Painter, Pos2, Rect, Ui};
+
+/// Space between the numbers and the text.
+const PADDING: f32 = 8.0;
+
+pub struct GutterStyle {
+    pub font_id: FontId,
+    pub number_color: Color32,
+    pub current_color: Color32,
+    pub background: Color32,
+    pub relative: bool,
+}
+
+/// Width needed to show numbers up to `line_count`.
+pub fn width(ui: &Ui, font_id: &FontId, line_count: usize) -> f32 {
+    let digits = line_count.max(1).ilog10() as f32 + 1.0;
+    let digit_width = ui.fonts(|f| f.glyph_width(font_id, '0'));
+    digits.max(2.0) * digit_width + PADDING * 2.0
+}
+
+/// Paints line numbers next to the rows of `galley`, which was laid out at `galley_pos`.
+/// Soft-wrapped continuation rows are left blank so numbers follow logical lines.
+pub fn paint(
+    ui: &Ui,
+    gutter: Rect,
+    galley: &Galley,
+    galley_pos: Pos2,
+    current_line: usize,
+    style: &GutterStyle,
+) {
+    let clip = ui.clip_rect();
+    let painter = ui.painter();
+    let height = galley.rect.height().max(gutter.height());
+    let background = Rect::from_min_size(gutter.min, egui::vec2(gutter.width(), height));
+    painter.rect_filled(background.intersect(clip), 0.0, style.background);
+
+    let mut line = 0;
+    let mut starts_line = true;
+    for row in &galley.rows {
+        let top = galley_pos.y + row.rect.min.y;
+        let bottom = galley_pos.y + row.rect.max.y;
+        if starts_line && bottom >= clip.min.y && top <= clip.max.y {
+            paint_number(painter, gutter, top, line, current_line, style);
+        }
+        if top > clip.max.y {
+            break;
+        }
+        starts_line = row.ends_with_newline;
+        if starts_line {
+            line += 1;
+        }
+    }
+}
+
+/// Paints the number of `line` (0-based) for a row whose top is at `top`.
+pub fn paint_number(painter: &Painter, gutter: Rect, top: f32, line: usize, current_line: usize, style: &GutterStyle) {
+    let (number, color) = if line == current_line {
+        (line + 1, style.current_color)
+    } else if style.relative {
+        (line.abs_diff(current_line), style.number_color)
+    } else {
+        (line + 1, style.number_color)
+    };
+    painter.text(
+        egui::pos2(gutter.max.x - PADDING, top),
+        Align2::RIGHT_TOP,
+        number.to_string(),
+        style.font_id.clone(),
+        color,
+    );
+}
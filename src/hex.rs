@@ -0,0 +1,404 @@
+use crate::document::{Document, Selection};
+use crate::mapped::HEX_ROW_BYTES;
+use crate::undo::Edit;
+use crate::viewport::{scrollbar, wheel_steps};
+use eframe::egui::{self, Align2, Color32, Event, EventFilter, FontId, Key, Rect, Sense, Stroke, Ui};
+use std::fmt::Write;
+use std::ops::Range;
+
+const SCROLLBAR_WIDTH: f32 = 10.0;
+/// Column where the characters start in a row, after 16 bytes in hex and a gap.
+pub const ASCII_COLUMN: usize = HEX_ROW_BYTES * 3 + 2;
+
+/// The bytes of a document open in the hex editor. Undo records changes to them like
+/// text edits, each byte standing for the character of the same number (U+0000 to U+00FF).
+pub struct HexBuffer {
+    pub bytes: Vec<u8>,
+    /// Typing replaces the byte at the cursor instead of inserting one before it.
+    pub overwrite: bool,
+}
+
+impl HexBuffer {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes, overwrite: true }
+    }
+}
+
+pub struct HexStyle {
+    pub font_id: FontId,
+    pub text_color: Color32,
+    pub offset_color: Color32,
+    pub gutter_background: Color32,
+    pub selection: Color32,
+    pub match_color: Color32,
+    pub scrollbar: Color32,
+}
+
+/// Where the editor is scrolled to and how the cursor stands.
+#[derive(Debug, Clone, Copy, Default)]
+struct EditorState {
+    /// First row in view.
+    top: usize,
+    /// The cursor is in the character column rather than among the hex digits.
+    ascii: bool,
+    /// The first digit of the byte at the cursor was typed; the next one completes it.
+    low_nibble: bool,
+}
+
+/// Shows and edits the bytes of a document in rows of `HEX_ROW_BYTES`: the offset,
+/// the bytes in hex, then as characters. Digits typed in the hex column and
+/// characters typed in the other one overwrite or insert bytes, Tab switches between
+/// the columns and Insert between overwriting and inserting. `matches` are the sorted
+/// byte ranges of search matches to mark.
+pub fn show(ui: &mut Ui, doc: &mut Document, style: &HexStyle, matches: &[Range<usize>]) {
+    let id = doc.editor_id();
+    let time = ui.input(|i| i.time);
+    let row_height = ui.fonts(|f| f.row_height(&style.font_id));
+    let char_width = ui.fonts(|f| f.glyph_width(&style.font_id, '0'));
+    let Some(hex) = doc.hex.as_mut() else {
+        return;
+    };
+    let digits = format!("{:X}", hex.bytes.len()).len().max(8);
+
+    let rect = ui.available_rect_before_wrap();
+    ui.allocate_rect(rect, Sense::hover());
+    let gutter_rect = Rect::from_min_max(
+        rect.min,
+        egui::pos2(rect.min.x + (digits + 2) as f32 * char_width, rect.max.y),
+    );
+    let text_rect = Rect::from_min_max(
+        egui::pos2(gutter_rect.max.x, rect.min.y),
+        egui::pos2(rect.max.x - SCROLLBAR_WIDTH, rect.max.y),
+    );
+    let rows = ((text_rect.height() / row_height) as usize).max(1);
+
+    let response = ui.interact(text_rect, id, Sense::click_and_drag());
+    let state_id = id.with("hex");
+    let mut state: EditorState = ui.data(|d| d.get_temp(state_id)).unwrap_or_default();
+    let mut cursor = doc.cursor;
+    let mut edits = Vec::new();
+    let mut follow_cursor = false;
+
+    if response.hovered() {
+        let delta = ui.input(|i| i.raw_scroll_delta);
+        state.top = state.top.saturating_add_signed(-wheel_steps(delta.y / row_height));
+    }
+
+    if let Some(pos) = response.interact_pointer_pos() {
+        let row = ((pos.y - text_rect.top()) / row_height).floor().max(0.0) as usize;
+        let column = (pos.x - text_rect.left()) / char_width;
+        let (index, ascii) = if column >= ASCII_COLUMN as f32 - 1.0 {
+            ((column - ASCII_COLUMN as f32).max(0.0) as usize, true)
+        } else {
+            let index = (0..HEX_ROW_BYTES).rev().find(|&i| hex_column(i) as f32 <= column);
+            (index.unwrap_or(0), false)
+        };
+        let offset = ((state.top + row) * HEX_ROW_BYTES + index.min(HEX_ROW_BYTES - 1)).min(hex.bytes.len());
+        let (pressed, shift) = ui.input(|i| (i.pointer.any_pressed(), i.modifiers.shift));
+        if pressed && !shift {
+            cursor = Selection::caret(offset);
+            state.ascii = ascii;
+        } else {
+            cursor.head = offset;
+        }
+        state.low_nibble = false;
+        follow_cursor = true;
+        response.request_focus();
+    }
+
+    if response.has_focus() {
+        ui.memory_mut(|m| {
+            let filter = EventFilter {
+                tab: true,
+                horizontal_arrows: true,
+                vertical_arrows: true,
+                escape: false,
+            };
+            m.set_focus_lock_filter(id, filter);
+        });
+        for event in ui.input(|i| i.events.clone()) {
+            let cursor_before = cursor;
+            let len = hex.bytes.len();
+            match event {
+                Event::Text(text) => {
+                    for c in text.chars() {
+                        if state.ascii {
+                            if c.is_ascii() {
+                                write(&mut hex.bytes, &mut cursor, &[c as u8], hex.overwrite, &mut edits);
+                            }
+                        } else if let Some(digit) = c.to_digit(16).map(|d| d as u8) {
+                            type_digit(hex, &mut cursor, &mut state, digit, &mut edits);
+                        }
+                    }
+                }
+                Event::Paste(text) => {
+                    let pasted = if state.ascii { Some(text.into_bytes()) } else { parse_hex(&text) };
+                    if let Some(pasted) = pasted {
+                        write(&mut hex.bytes, &mut cursor, &pasted, hex.overwrite, &mut edits);
+                    }
+                }
+                Event::Copy if !cursor.is_empty() => {
+                    ui.ctx().copy_text(to_hex(&hex.bytes[cursor.start()..cursor.end()]));
+                }
+                Event::Cut if !cursor.is_empty() => {
+                    ui.ctx().copy_text(to_hex(&hex.bytes[cursor.start()..cursor.end()]));
+                    write(&mut hex.bytes, &mut cursor, &[], false, &mut edits);
+                }
+                Event::Key {
+                    key,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } => {
+                    let head = cursor.head;
+                    let row_start = head - head % HEX_ROW_BYTES;
+                    let moved = match key {
+                        Key::ArrowLeft if !cursor.is_empty() && !modifiers.shift => Some(cursor.start()),
+                        Key::ArrowRight if !cursor.is_empty() && !modifiers.shift => Some(cursor.end()),
+                        Key::ArrowLeft => Some(head.saturating_sub(1)),
+                        Key::ArrowRight => Some((head + 1).min(len)),
+                        Key::ArrowUp => Some(head.checked_sub(HEX_ROW_BYTES).unwrap_or(head)),
+                        Key::ArrowDown => Some((head + HEX_ROW_BYTES).min(len)),
+                        Key::PageUp => Some(head.saturating_sub(rows * HEX_ROW_BYTES)),
+                        Key::PageDown => Some((head + rows * HEX_ROW_BYTES).min(len)),
+                        Key::Home if modifiers.command => Some(0),
+                        Key::End if modifiers.command => Some(len),
+                        Key::Home => Some(row_start),
+                        Key::End => Some((row_start + HEX_ROW_BYTES - 1).min(len)),
+                        Key::A if modifiers.command => {
+                            cursor = Selection { anchor: 0, head: len };
+                            None
+                        }
+                        _ => None,
+                    };
+                    if let Some(index) = moved {
+                        if modifiers.shift {
+                            cursor.head = index;
+                        } else {
+                            cursor = Selection::caret(index);
+                        }
+                    } else if !modifiers.command {
+                        match key {
+                            Key::Tab => state.ascii = !state.ascii,
+                            Key::Insert => hex.overwrite = !hex.overwrite,
+                            Key::Backspace => {
+                                if cursor.is_empty() && head > 0 {
+                                    cursor.anchor = head - 1;
+                                }
+                                write(&mut hex.bytes, &mut cursor, &[], false, &mut edits);
+                            }
+                            Key::Delete => {
+                                if cursor.is_empty() && head < len {
+                                    cursor.anchor = head + 1;
+                                }
+                                write(&mut hex.bytes, &mut cursor, &[], false, &mut edits);
+                            }
+                            _ => {}
+                        }
+                    }
+                    if moved.is_some() || matches!(key, Key::Tab | Key::Backspace | Key::Delete) {
+                        state.low_nibble = false;
+                    }
+                }
+                _ => {}
+            }
+            follow_cursor |= cursor != cursor_before;
+        }
+    }
+
+    let bytes = &hex.bytes;
+    // One more row than the bytes fill when they end a row, for the cursor at the end
+    let total_rows = bytes.len() / HEX_ROW_BYTES + 1;
+    let cursor_row = cursor.head / HEX_ROW_BYTES;
+    if std::mem::take(&mut doc.reveal_cursor) {
+        state.top = cursor_row.saturating_sub(rows / 2);
+        follow_cursor = true;
+        response.request_focus();
+    }
+    if follow_cursor {
+        if cursor_row < state.top {
+            state.top = cursor_row;
+        } else if cursor_row >= state.top + rows {
+            state.top = cursor_row + 1 - rows;
+        }
+    }
+    state.top = state.top.min(total_rows.saturating_sub(rows));
+
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(gutter_rect, 0.0, style.gutter_background);
+    let bottom = (state.top + rows + 1).min(total_rows);
+    let visible = state.top * HEX_ROW_BYTES..bottom * HEX_ROW_BYTES;
+    let first = matches.partition_point(|m| m.end <= visible.start);
+    let marks: Vec<&Range<usize>> = matches[first..]
+        .iter()
+        .take_while(|m| m.start < visible.end)
+        .collect();
+
+    for row in state.top..bottom {
+        let y = text_rect.top() + (row - state.top) as f32 * row_height;
+        let start = row * HEX_ROW_BYTES;
+        let row_bytes = &bytes[start.min(bytes.len())..(start + HEX_ROW_BYTES).min(bytes.len())];
+        painter.text(
+            egui::pos2(gutter_rect.left() + char_width, y),
+            Align2::LEFT_TOP,
+            format!("{:0width$X}", start, width = digits),
+            style.font_id.clone(),
+            style.offset_color,
+        );
+
+        let x = |column: usize| text_rect.left() + column as f32 * char_width;
+        let band = |columns: Range<usize>| Rect::from_x_y_ranges(x(columns.start)..=x(columns.end), y..=y + row_height);
+        let highlight = |range: &Range<usize>, color: Color32| {
+            let (first, last) = (range.start.max(start), range.end.min(start + row_bytes.len()));
+            if first < last {
+                for columns in byte_columns(first - start..last - start) {
+                    painter.rect_filled(band(columns), 0.0, color);
+                }
+            }
+        };
+        for mark in &marks {
+            highlight(mark, style.match_color);
+        }
+        highlight(&(cursor.start()..cursor.end()), style.selection);
+
+        if row == cursor_row && response.has_focus() {
+            let [hex_cell, ascii_cell] = byte_columns(cursor.head - start..cursor.head - start + 1);
+            let (active, other) = if state.ascii { (ascii_cell, hex_cell) } else { (hex_cell, ascii_cell) };
+            let active = match state.low_nibble && !state.ascii {
+                true => active.start + 1..active.start + 2,
+                false => active,
+            };
+            painter.rect_stroke(band(active), 0.0, Stroke::new(2.0, style.text_color));
+            painter.rect_stroke(band(other), 0.0, Stroke::new(1.0, style.text_color.gamma_multiply(0.5)));
+        }
+
+        painter.text(
+            egui::pos2(text_rect.left(), y),
+            Align2::LEFT_TOP,
+            row_text(row_bytes),
+            style.font_id.clone(),
+            style.text_color,
+        );
+    }
+
+    let vertical = Rect::from_min_max(egui::pos2(text_rect.right(), rect.top()), rect.max);
+    state.top = scrollbar(ui, id.with("vertical"), vertical, true, state.top, rows, total_rows, style.scrollbar);
+    ui.data_mut(|d| d.insert_temp(state_id, state));
+
+    doc.sync_cursor = false;
+    if !edits.is_empty() {
+        doc.record_edits(edits, cursor, time);
+    } else if cursor != doc.cursor {
+        // Moving the cursor ends the current run of typing
+        doc.history.seal();
+        doc.cursor = cursor;
+    }
+}
+
+/// Types hex digit `digit`: the first digit of a byte goes into its high half, the
+/// second into its low half, after which the cursor moves on to the next byte.
+fn type_digit(hex: &mut HexBuffer, cursor: &mut Selection, state: &mut EditorState, digit: u8, edits: &mut Vec<Edit>) {
+    let head = cursor.head;
+    if state.low_nibble && cursor.is_empty() && head < hex.bytes.len() {
+        let byte = hex.bytes[head] & 0xF0 | digit;
+        replace(&mut hex.bytes, head..head + 1, &[byte], edits);
+        *cursor = Selection::caret(head + 1);
+        state.low_nibble = false;
+        return;
+    }
+    let start = cursor.start();
+    if hex.overwrite && cursor.is_empty() && head < hex.bytes.len() {
+        let byte = digit << 4 | hex.bytes[head] & 0x0F;
+        replace(&mut hex.bytes, head..head + 1, &[byte], edits);
+    } else {
+        write(&mut hex.bytes, cursor, &[digit << 4], false, edits);
+    }
+    *cursor = Selection::caret(start);
+    state.low_nibble = true;
+}
+
+/// Puts `data` in place of the selection, or over as many bytes at the cursor when
+/// `overwrite` is on and nothing is selected, and moves the cursor after it.
+fn write(bytes: &mut Vec<u8>, cursor: &mut Selection, data: &[u8], overwrite: bool, edits: &mut Vec<Edit>) {
+    let start = cursor.start();
+    let end = if cursor.is_empty() && overwrite {
+        (start + data.len()).min(bytes.len())
+    } else {
+        cursor.end()
+    };
+    replace(bytes, start..end, data, edits);
+    *cursor = Selection::caret(start + data.len());
+}
+
+fn replace(bytes: &mut Vec<u8>, range: Range<usize>, data: &[u8], edits: &mut Vec<Edit>) {
+    if range.is_empty() && data.is_empty() {
+        return;
+    }
+    let removed = as_chars(&bytes[range.clone()]);
+    bytes.splice(range.clone(), data.iter().copied());
+    edits.push(Edit::new(range.start, removed, as_chars(data)));
+}
+
+/// `bytes` as characters of the same numbers, the form undo keeps them in.
+pub fn as_chars(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// Bytes as hex pairs separated by spaces, e.g. `DE AD BE EF`.
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len() * 3);
+    for (i, byte) in bytes.iter().enumerate() {
+        if i > 0 {
+            text.push(' ');
+        }
+        let _ = write!(text, "{:02X}", byte);
+    }
+    text
+}
+
+/// Bytes written in hex, as pairs with or without spaces in between, or `None`
+/// when `text` isn't hex.
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text
+        .split_whitespace()
+        .map(|word| word.strip_prefix("0x").unwrap_or(word))
+        .flat_map(str::chars)
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<_>>()?;
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    Some(digits.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect())
+}
+
+/// Column where byte `i` of a row starts in hex, with an extra space after the eighth.
+pub fn hex_column(i: usize) -> usize {
+    i * 3 + usize::from(i >= HEX_ROW_BYTES / 2)
+}
+
+/// A row of up to `HEX_ROW_BYTES`: the bytes in hex, then as ASCII with `.` for anything else.
+pub fn row_text(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(ASCII_COLUMN + HEX_ROW_BYTES);
+    for i in 0..HEX_ROW_BYTES {
+        if i == HEX_ROW_BYTES / 2 {
+            text.push(' ');
+        }
+        match bytes.get(i) {
+            Some(byte) => {
+                let _ = write!(text, "{:02X} ", byte);
+            }
+            None => text.push_str("   "),
+        }
+    }
+    text.push(' ');
+    text.extend(bytes.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }));
+    text
+}
+
+/// Columns that bytes `range` of a row cover in hex and in ASCII.
+pub fn byte_columns(range: Range<usize>) -> [Range<usize>; 2] {
+    [
+        hex_column(range.start)..hex_column(range.end - 1) + 2,
+        ASCII_COLUMN + range.start..ASCII_COLUMN + range.end,
+    ]
+}
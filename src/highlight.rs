@@ -0,0 +1,137 @@
+use crate::syntax::Token;
+use crate::theme::Theme;
+use eframe::egui::text::{LayoutJob, LayoutSection, TextFormat, TAB_SIZE};
+use eframe::egui::{Color32, FontId};
+use std::ops::Range;
+
+/// A byte range of the text painted with a background color, e.g. a search match.
+pub struct Mark {
+    pub range: Range<usize>,
+    pub background: Color32,
+}
+
+/// Builds the layout for the editor text, coloring `tokens` with the theme's syntax
+/// colors and painting `marks` (sorted, non-overlapping) behind the text. Marks that
+/// don't fit `text` are skipped, since the text may have been edited after they were computed.
+/// Tabs reach to the next multiple of `tab_width` columns of `space_width`.
+#[allow(clippy::too_many_arguments)]
+pub fn layout_job(
+    text: &str,
+    font_id: &FontId,
+    theme: &Theme,
+    wrap_width: f32,
+    tab_width: usize,
+    space_width: f32,
+    tokens: &[Token],
+    marks: &[Mark],
+) -> LayoutJob {
+    let mut job = LayoutJob {
+        text: text.to_owned(),
+        ..Default::default()
+    };
+    job.wrap.max_width = wrap_width;
+
+    let plain = TextFormat::simple(font_id.clone(), theme.text());
+    let mut valid_end = 0;
+    let marks: Vec<&Mark> = marks
+        .iter()
+        .filter(|mark| {
+            let Range { start, end } = mark.range;
+            let valid = start >= valid_end
+                && end <= text.len()
+                && text.is_char_boundary(start)
+                && text.is_char_boundary(end);
+            if valid {
+                valid_end = end;
+            }
+            valid
+        })
+        .collect();
+
+    let (mut tokens, mut marks) = (tokens.iter().peekable(), marks.into_iter().peekable());
+    let mut pos = 0;
+    while pos < text.len() {
+        while tokens.next_if(|t| t.range.end <= pos).is_some() {}
+        while marks.next_if(|m| m.range.end <= pos).is_some() {}
+
+        // The section runs until the next token or mark starts or ends
+        let mut end = text.len();
+        let mut format = plain.clone();
+        if let Some(token) = tokens.peek() {
+            if token.range.start <= pos {
+                format.color = theme.syntax(token.kind);
+                end = end.min(token.range.end);
+            } else {
+                end = end.min(token.range.start);
+            }
+        }
+        if let Some(mark) = marks.peek() {
+            if mark.range.start <= pos {
+                format.background = mark.background;
+                end = end.min(mark.range.end);
+            } else {
+                end = end.min(mark.range.start);
+            }
+        }
+        push_section(&mut job, pos..end, format);
+        pos = end;
+    }
+    if job.sections.is_empty() {
+        // An empty text still needs a section so the caret row gets the font's height
+        job.sections.push(LayoutSection {
+            leading_space: 0.0,
+            byte_range: 0..0,
+            format: plain,
+        });
+    }
+    if text.contains('\t') {
+        align_tabs(&mut job, tab_width.max(1), space_width);
+    }
+    job
+}
+
+/// Ends a section after every tab and moves the text behind it to the tab stop, since
+/// epaint draws a tab `TAB_SIZE` spaces wide wherever it is.
+fn align_tabs(job: &mut LayoutJob, tab_width: usize, space_width: f32) {
+    let mut sections = Vec::with_capacity(job.sections.len());
+    let mut column = 0;
+    let mut shift = 0.0;
+    for section in std::mem::take(&mut job.sections) {
+        let mut start = section.byte_range.start;
+        let mut split = |end: usize, shift: &mut f32| {
+            if start < end {
+                sections.push(LayoutSection {
+                    leading_space: std::mem::take(shift),
+                    byte_range: start..end,
+                    format: section.format.clone(),
+                });
+                start = end;
+            }
+        };
+        for (i, c) in job.text[section.byte_range.clone()].char_indices() {
+            match c {
+                '\n' => column = 0,
+                '\t' => {
+                    let at = section.byte_range.start + i;
+                    split(at + 1, &mut shift);
+                    let stop = (column / tab_width + 1) * tab_width;
+                    shift = (stop - column) as f32 * space_width - TAB_SIZE as f32 * space_width;
+                    column = stop;
+                }
+                _ => column += 1,
+            }
+        }
+        split(section.byte_range.end, &mut shift);
+    }
+    job.sections = sections;
+}
+
+fn push_section(job: &mut LayoutJob, byte_range: Range<usize>, format: TextFormat) {
+    if !byte_range.is_empty() {
+        job.sections.push(LayoutSection {
+            leading_space: 0.0,
+            byte_range,
+            format,
+        });
+    }
+}
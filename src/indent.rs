@@ -0,0 +1,313 @@
+use crate::document::{Document, Selection};
+use crate::text_format::Indentation;
+use crate::undo::{byte_index, Edit};
+use crate::viewport::advance;
+use eframe::egui::{Key, Modifiers, Ui};
+
+/// Languages in which a line ending in `:` opens an indented block.
+const COLON_BLOCKS: [&str; 2] = ["Python", "YAML"];
+
+/// How Tab and Enter indent in the editors.
+#[derive(Debug, Clone, Copy)]
+pub struct IndentOptions {
+    pub indentation: Indentation,
+    /// Enter starts the new line with the indentation of the line before.
+    pub auto_indent: bool,
+    /// Enter after an opening bracket, or a `:` in languages that use it, indents one level more.
+    pub smart_indent: bool,
+    pub colon_blocks: bool,
+}
+
+impl IndentOptions {
+    pub fn new(indentation: Indentation, auto_indent: bool, smart_indent: bool, language: &str) -> Self {
+        Self {
+            indentation,
+            auto_indent,
+            smart_indent,
+            colon_blocks: COLON_BLOCKS.contains(&language),
+        }
+    }
+}
+
+/// The whole lines a selection touches: `text` starts at character `start` of the
+/// document and runs to the end of the last line, without its line break.
+pub struct Lines<'a> {
+    pub text: &'a str,
+    pub start: usize,
+}
+
+impl<'a> Lines<'a> {
+    /// The lines of `text` that `selection` touches.
+    pub fn around(text: &'a str, selection: Selection) -> Self {
+        let from = byte_index(text, selection.start());
+        let to = from + byte_index(&text[from..], selection.end() - selection.start());
+        let line_start = text[..from].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = text[to..].find('\n').map_or(text.len(), |i| to + i);
+        Self {
+            text: &text[line_start..line_end],
+            start: selection.start() - text[line_start..from].chars().count(),
+        }
+    }
+
+    /// The text between characters `from` and `to` of the document.
+    fn slice(&self, from: usize, to: usize) -> String {
+        self.text.chars().skip(from - self.start).take(to - from).collect()
+    }
+}
+
+/// What Tab does: a selection over several lines, or any selection with `shift`, is
+/// indented or outdented a level; otherwise the selection is replaced with a tab, or
+/// with spaces up to the next tab stop.
+pub fn tab(lines: &Lines, selection: Selection, indentation: Indentation, shift: bool) -> (Vec<Edit>, Selection) {
+    if shift || lines.text.contains('\n') {
+        return shift_lines(lines, selection, indentation, shift);
+    }
+    let inserted = if indentation.use_tabs {
+        "\t".to_string()
+    } else {
+        let width = indentation.width.max(1);
+        let column = lines
+            .text
+            .chars()
+            .take(selection.start() - lines.start)
+            .fold(0, |column, c| advance(column, c, width));
+        " ".repeat(width - column % width)
+    };
+    let removed = lines.slice(selection.start(), selection.end());
+    let caret = selection.start() + inserted.chars().count();
+    (vec![Edit::new(selection.start(), removed, inserted)], Selection::caret(caret))
+}
+
+/// Adds a level of indentation to every line, or removes one with `outdent`. A selection
+/// ending at the start of a line leaves that line alone.
+fn shift_lines(lines: &Lines, selection: Selection, indentation: Indentation, outdent: bool) -> (Vec<Edit>, Selection) {
+    let width = indentation.width.max(1);
+    let unit = indentation.unit();
+    let line_count = lines.text.split('\n').count();
+    let mut edits = Vec::new();
+    // Where each line changed in the original text, and how many characters were removed and inserted
+    let mut changes = Vec::new();
+    let mut shift = 0isize;
+    let mut line_start = lines.start;
+    for (i, line) in lines.text.split('\n').enumerate() {
+        let skip = i > 0 && i + 1 == line_count && selection.end() == line_start;
+        let (removed, inserted) = if skip {
+            (String::new(), String::new())
+        } else if outdent {
+            let spaces = line.len() - line.trim_start_matches(' ').len();
+            let removed = match spaces {
+                0 if line.starts_with('\t') => "\t".to_string(),
+                // Back to the previous tab stop
+                _ => " ".repeat(match spaces % width {
+                    0 => spaces.min(width),
+                    partial => partial,
+                }),
+            };
+            (removed, String::new())
+        } else if line.is_empty() {
+            (String::new(), String::new())
+        } else {
+            (String::new(), unit.clone())
+        };
+        let (removed_chars, inserted_chars) = (removed.chars().count(), inserted.chars().count());
+        if removed_chars + inserted_chars > 0 {
+            edits.push(Edit::new(line_start.saturating_add_signed(shift), removed, inserted));
+            changes.push((line_start, removed_chars, inserted_chars));
+            shift += inserted_chars as isize - removed_chars as isize;
+        }
+        line_start += line.chars().count() + 1;
+    }
+
+    let map = |position: usize| {
+        let mut mapped = position as isize;
+        for &(at, removed, inserted) in &changes {
+            if at < position {
+                mapped += inserted as isize - removed.min(position - at) as isize;
+            }
+        }
+        mapped as usize
+    };
+    let after = Selection {
+        anchor: map(selection.anchor),
+        head: map(selection.head),
+    };
+    (edits, after)
+}
+
+/// What Enter does with auto-indent: the new line gets the indentation of the caret's
+/// line, one level more after an opening bracket (or a `:` with `colon_blocks`) when
+/// `smart_indent` is on. Between a pair of brackets the closing one moves to a line of its own.
+pub fn newline(lines: &Lines, selection: Selection, options: &IndentOptions) -> (Vec<Edit>, Selection) {
+    // The selection starts on the first of `lines` and ends on the last
+    let before: String = lines.text.chars().take(selection.start() - lines.start).collect();
+    let after: String = lines.text.chars().skip(selection.end() - lines.start).collect();
+
+    let indent: String = before.chars().take_while(|&c| c == ' ' || c == '\t').collect();
+    let opener = before.trim_end().chars().last();
+    let closer = match opener {
+        Some('{') => Some('}'),
+        Some('[') => Some(']'),
+        Some('(') => Some(')'),
+        _ => None,
+    };
+    let deeper = options.smart_indent && (closer.is_some() || (options.colon_blocks && opener == Some(':')));
+
+    let mut inserted = format!("\n{}", indent);
+    if deeper {
+        inserted.push_str(&options.indentation.unit());
+    }
+    let caret = selection.start() + inserted.chars().count();
+    if deeper && closer.is_some_and(|closer| after.trim_start().starts_with(closer)) {
+        inserted.push('\n');
+        inserted.push_str(&indent);
+    }
+    let removed = lines.slice(selection.start(), selection.end());
+    (vec![Edit::new(selection.start(), removed, inserted)], Selection::caret(caret))
+}
+
+/// Takes Tab, Shift+Tab and Enter away from `egui::TextEdit`, which would insert a
+/// plain tab or line break, and applies them to `doc` as `options` say.
+pub fn handle_keys(ui: &Ui, doc: &mut Document, options: &IndentOptions) {
+    let (outdents, tabs, enters) = ui.input_mut(|i| {
+        // Shift+Tab first: without shift in the pattern, Tab matches it as well
+        let outdents = i.count_and_consume_key(Modifiers::SHIFT, Key::Tab);
+        let tabs = i.count_and_consume_key(Modifiers::NONE, Key::Tab);
+        let enters = if options.auto_indent {
+            i.count_and_consume_key(Modifiers::NONE, Key::Enter)
+        } else {
+            0
+        };
+        (outdents, tabs, enters)
+    });
+    let presses = std::iter::repeat_n(Some(true), outdents)
+        .chain(std::iter::repeat_n(Some(false), tabs))
+        .chain(std::iter::repeat_n(None, enters));
+    for shift in presses {
+        let lines = Lines::around(&doc.content, doc.cursor);
+        let (edits, after) = match shift {
+            Some(shift) => tab(&lines, doc.cursor, options.indentation, shift),
+            None => newline(&lines, doc.cursor, options),
+        };
+        doc.edit(edits, after);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(anchor: usize, head: usize) -> Selection {
+        Selection { anchor, head }
+    }
+
+    /// Runs `action` on the lines around `selection` and applies its edits to `text`.
+    fn apply(
+        text: &str,
+        selection: Selection,
+        action: impl FnOnce(&Lines, Selection) -> (Vec<Edit>, Selection),
+    ) -> (String, Selection) {
+        let (edits, after) = action(&Lines::around(text, selection), selection);
+        let mut text = text.to_string();
+        crate::undo::apply_all(&mut text, &edits);
+        (text, after)
+    }
+
+    fn tab_in(text: &str, selection: Selection, indentation: Indentation, shift: bool) -> (String, Selection) {
+        apply(text, selection, |lines, selection| tab(lines, selection, indentation, shift))
+    }
+
+    fn enter(text: &str, selection: Selection, options: IndentOptions) -> (String, Selection) {
+        apply(text, selection, |lines, selection| newline(lines, selection, &options))
+    }
+
+    fn options(language: &str) -> IndentOptions {
+        IndentOptions::new(Indentation::spaces(4), true, true, language)
+    }
+
+    const TABS: Indentation = Indentation {
+        use_tabs: true,
+        width: 4,
+    };
+
+    #[test]
+    fn tab_goes_to_the_next_tab_stop() {
+        let spaces = Indentation::spaces(4);
+        assert_eq!(tab_in("ab", Selection::caret(2), spaces, false), ("ab  ".to_string(), Selection::caret(4)));
+        assert_eq!(tab_in("éé", Selection::caret(1), spaces, false), ("é   é".to_string(), Selection::caret(4)));
+        assert_eq!(tab_in("\tx", Selection::caret(1), spaces, false), ("\t    x".to_string(), Selection::caret(5)));
+        assert_eq!(tab_in("ab", Selection::caret(1), TABS, false), ("a\tb".to_string(), Selection::caret(2)));
+        // A selection within a line is replaced
+        assert_eq!(tab_in("abcd", select(1, 3), spaces, false), ("a   d".to_string(), Selection::caret(4)));
+    }
+
+    #[test]
+    fn tab_over_lines_indents_them() {
+        let spaces = Indentation::spaces(2);
+        let (text, after) = tab_in("a\nb\n\nc", select(0, 6), spaces, false);
+        assert_eq!(text, "  a\n  b\n\n  c");
+        assert_eq!(after, select(0, 12));
+
+        let (text, after) = tab_in("a\nb\nc", select(5, 1), TABS, false);
+        assert_eq!(text, "\ta\n\tb\n\tc");
+        assert_eq!(after, select(8, 2));
+
+        // A selection ending at the start of a line leaves that line alone
+        let (text, after) = tab_in("a\nb\nc", select(0, 4), spaces, false);
+        assert_eq!(text, "  a\n  b\nc");
+        assert_eq!(after, select(0, 8));
+    }
+
+    #[test]
+    fn outdent_removes_one_level_of_mixed_indentation() {
+        let spaces = Indentation::spaces(4);
+        let text = "\tw\n  x\n      y\n    \tz\n \tv\nu";
+        let (outdented, _) = tab_in(text, select(0, text.chars().count()), spaces, true);
+        assert_eq!(outdented, "w\nx\n    y\n\tz\n\tv\nu");
+
+        // Shift+Tab works on the caret's line alone, and keeps the caret on its text
+        let (outdented, after) = tab_in("        x", Selection::caret(9), spaces, true);
+        assert_eq!((outdented.as_str(), after), ("    x", Selection::caret(5)));
+        // A caret inside the removed indentation moves to where it was
+        let (_, after) = tab_in("      x", Selection::caret(1), spaces, true);
+        assert_eq!(after, Selection::caret(0));
+    }
+
+    #[test]
+    fn enter_keeps_the_indentation() {
+        let (text, after) = enter("  let a = 1;", Selection::caret(12), options("Rust"));
+        assert_eq!((text.as_str(), after), ("  let a = 1;\n  ", Selection::caret(15)));
+        // The selection is replaced
+        let (text, after) = enter("\tab cd", select(3, 5), options("Rust"));
+        assert_eq!((text.as_str(), after), ("\tab\n\td", Selection::caret(5)));
+    }
+
+    #[test]
+    fn enter_between_brackets_opens_a_block() {
+        let (text, after) = enter("fn main() {}", Selection::caret(11), options("Rust"));
+        assert_eq!(text, "fn main() {\n    \n}");
+        assert_eq!(after, Selection::caret(16));
+
+        let (text, after) = enter("  f([])", Selection::caret(5), options("Rust"));
+        assert_eq!(text, "  f([\n      \n  ])");
+        assert_eq!(after, Selection::caret(12));
+
+        // After an opener without its closer the block isn't closed
+        let (text, _) = enter("if x {", Selection::caret(6), options("Rust"));
+        assert_eq!(text, "if x {\n    ");
+    }
+
+    #[test]
+    fn colons_open_blocks_in_some_languages() {
+        let (text, _) = enter("if x:", Selection::caret(5), options("Python"));
+        assert_eq!(text, "if x:\n    ");
+        let (text, _) = enter("a:", Selection::caret(2), options("Rust"));
+        assert_eq!(text, "a:\n");
+    }
+
+    #[test]
+    fn enter_without_smart_indent_only_keeps_the_indentation() {
+        let plain = IndentOptions::new(Indentation::spaces(4), true, false, "Rust");
+        let (text, _) = enter("  x {}", Selection::caret(5), plain);
+        assert_eq!(text, "  x {\n  }");
+    }
+}
@@ -0,0 +1,169 @@
+use crate::cli::FileArg;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::Receiver;
+
+/// What a second `dnotepadx` invocation sends to the running one, as one JSON line.
+#[derive(Serialize, Deserialize)]
+struct Message {
+    files: Vec<FileArg>,
+    wait: bool,
+}
+
+/// Files forwarded by another invocation.
+pub struct Request {
+    pub files: Vec<FileArg>,
+    /// Present when the other invocation runs with `--wait`.
+    pub waiter: Option<Waiter>,
+}
+
+/// An invocation blocked in `--wait` until its files are closed. Dropping it lets the invocation exit.
+pub struct Waiter {
+    #[cfg(unix)]
+    _stream: std::os::unix::net::UnixStream,
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::{Message, Request, Waiter};
+    use crate::cli::FileArg;
+    use eframe::egui;
+    use std::fs::DirBuilder;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+    use std::sync::mpsc::{self, Receiver};
+    use std::time::Duration;
+
+    /// The socket goes in the user's runtime directory, or else in a directory of their
+    /// own under the temp directory. A socket in a shared directory could be taken over
+    /// by another user, who would then be sent the files. `None` when neither is safe.
+    fn socket_path() -> Option<PathBuf> {
+        if let Some(dir) = dirs::runtime_dir() {
+            return Some(dir.join("dnotepadx.sock"));
+        }
+        // SAFETY: getuid has no preconditions and can't fail
+        let uid = unsafe { libc::getuid() };
+        let dir = std::env::temp_dir().join(format!("dnotepadx-{}", uid));
+        // Fails when it already exists; the checks below decide whether it can be trusted
+        let _ = DirBuilder::new().mode(0o700).create(&dir);
+        let metadata = std::fs::symlink_metadata(&dir).ok()?;
+        let private = metadata.is_dir() && metadata.uid() == uid && metadata.mode() & 0o077 == 0;
+        private.then(|| dir.join("dnotepadx.sock"))
+    }
+
+    pub fn forward(files: &[FileArg], wait: bool) -> bool {
+        let Some(mut stream) = socket_path().and_then(|path| UnixStream::connect(path).ok()) else {
+            return false;
+        };
+        let message = Message {
+            files: files.to_vec(),
+            wait,
+        };
+        let Ok(mut line) = serde_json::to_string(&message) else {
+            return false;
+        };
+        line.push('\n');
+        if stream.write_all(line.as_bytes()).is_err() {
+            return false;
+        }
+        if wait {
+            // The running instance closes the connection once the files are closed
+            let _ = stream.read_to_end(&mut Vec::new());
+        }
+        true
+    }
+
+    pub struct Listener {
+        path: PathBuf,
+        pub receiver: Receiver<Request>,
+    }
+
+    impl Listener {
+        pub fn start(ctx: egui::Context) -> Option<Self> {
+            let path = socket_path()?;
+            let listener = match UnixListener::bind(&path) {
+                Ok(listener) => listener,
+                // A socket file nobody answers on is left over from a crash
+                Err(_) if UnixStream::connect(&path).is_err() => {
+                    std::fs::remove_file(&path).ok()?;
+                    UnixListener::bind(&path).ok()?
+                }
+                Err(_) => return None,
+            };
+
+            let (sender, receiver) = mpsc::channel();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let Some(request) = read_request(stream) else {
+                        continue;
+                    };
+                    if sender.send(request).is_err() {
+                        break;
+                    }
+                    ctx.request_repaint();
+                }
+            });
+            Some(Self { path, receiver })
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    fn read_request(stream: UnixStream) -> Option<Request> {
+        stream.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).ok()?;
+        let message: Message = serde_json::from_str(&line).ok()?;
+        Some(Request {
+            files: message.files,
+            waiter: message.wait.then_some(Waiter { _stream: stream }),
+        })
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use super::Request;
+    use crate::cli::FileArg;
+    use eframe::egui;
+    use std::sync::mpsc::Receiver;
+
+    pub fn forward(_files: &[FileArg], _wait: bool) -> bool {
+        false
+    }
+
+    pub struct Listener {
+        pub receiver: Receiver<Request>,
+    }
+
+    impl Listener {
+        pub fn start(_ctx: egui::Context) -> Option<Self> {
+            None
+        }
+    }
+}
+
+/// Hands the files to an already running instance. Returns false when there is none.
+pub fn forward(files: &[FileArg], wait: bool) -> bool {
+    imp::forward(files, wait)
+}
+
+/// Accepts files from later invocations while this instance runs.
+pub struct Listener(imp::Listener);
+
+impl Listener {
+    /// Starts listening unless another instance already is. `ctx` is woken for each request.
+    pub fn start(ctx: egui::Context) -> Option<Self> {
+        imp::Listener::start(ctx).map(Self)
+    }
+
+    pub fn receiver(&self) -> &Receiver<Request> {
+        &self.0.receiver
+    }
+}
@@ -0,0 +1,216 @@
+use eframe::egui::{self, Key, KeyboardShortcut, Modifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Editor commands that can be bound to a key chord.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    NewFile,
+    OpenFile,
+    Save,
+    SaveAs,
+    SaveAsCopy,
+    CloseTab,
+    Undo,
+    Redo,
+    FindReplace,
+    FindNext,
+    FindPrevious,
+    GoToLine,
+    ToggleHex,
+}
+
+impl Action {
+    pub const ALL: [Action; 13] = [
+        Action::NewFile,
+        Action::OpenFile,
+        Action::Save,
+        Action::SaveAs,
+        Action::SaveAsCopy,
+        Action::CloseTab,
+        Action::Undo,
+        Action::Redo,
+        Action::FindReplace,
+        Action::FindNext,
+        Action::FindPrevious,
+        Action::GoToLine,
+        Action::ToggleHex,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::NewFile => "New",
+            Action::OpenFile => "Open",
+            Action::Save => "Save",
+            Action::SaveAs => "Save As",
+            Action::SaveAsCopy => "Save As Copy",
+            Action::CloseTab => "Close Tab",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::FindReplace => "Find & Replace",
+            Action::FindNext => "Find Next",
+            Action::FindPrevious => "Find Previous",
+            Action::GoToLine => "Go to Line",
+            Action::ToggleHex => "Hex Editor",
+        }
+    }
+
+    /// Whether a text field handles the action's keys itself, so they are left to it
+    /// while it has the keyboard instead of acting on the document.
+    pub fn edits_text(self) -> bool {
+        matches!(self, Action::Undo | Action::Redo)
+    }
+}
+
+/// A key plus modifiers, written like `Ctrl+Shift+S` in the settings file.
+/// `Ctrl` means Cmd on macOS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyChord {
+    pub modifiers: Modifiers,
+    pub key: Key,
+}
+
+impl KeyChord {
+    pub const fn new(modifiers: Modifiers, key: Key) -> Self {
+        Self { modifiers, key }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut modifiers = Modifiers::NONE;
+        let mut key = None;
+        for part in text.split('+').map(str::trim) {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "cmd" | "command" => modifiers = modifiers | Modifiers::COMMAND,
+                "shift" => modifiers = modifiers | Modifiers::SHIFT,
+                "alt" | "option" => modifiers = modifiers | Modifiers::ALT,
+                _ if key.is_none() => key = Some(Key::from_name(part)?),
+                _ => return None,
+            }
+        }
+        Some(Self::new(modifiers, key?))
+    }
+
+    pub fn shortcut(&self) -> KeyboardShortcut {
+        KeyboardShortcut::new(self.modifiers, self.key)
+    }
+
+    /// Chords with more modifiers must be checked first, since egui matches
+    /// modifiers logically and `Ctrl+Shift+S` would otherwise also fire `Ctrl+S`.
+    fn specificity(&self) -> u8 {
+        let m = self.modifiers;
+        m.command as u8 + m.shift as u8 + m.alt as u8
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.command {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.shift {
+            write!(f, "Shift+")?;
+        }
+        write!(f, "{}", self.key.name())
+    }
+}
+
+impl TryFrom<String> for KeyChord {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        Self::parse(&text).ok_or_else(|| format!("invalid key chord: {}", text))
+    }
+}
+
+impl From<KeyChord> for String {
+    fn from(chord: KeyChord) -> Self {
+        chord.to_string()
+    }
+}
+
+/// Maps actions to the chords that trigger them. Persisted in `Settings`;
+/// an empty list leaves the action unbound.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "BTreeMap<Action, Vec<KeyChord>>")]
+pub struct Keybindings(BTreeMap<Action, Vec<KeyChord>>);
+
+impl From<BTreeMap<Action, Vec<KeyChord>>> for Keybindings {
+    /// Actions missing from the settings file (e.g. added in a newer version) keep their defaults.
+    fn from(saved: BTreeMap<Action, Vec<KeyChord>>) -> Self {
+        let mut bindings = Self::default();
+        bindings.0.extend(saved);
+        bindings
+    }
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        let ctrl = Modifiers::COMMAND;
+        let ctrl_shift = Modifiers::COMMAND | Modifiers::SHIFT;
+        let ctrl_alt = Modifiers::COMMAND | Modifiers::ALT;
+        let bindings = [
+            (Action::NewFile, vec![KeyChord::new(ctrl, Key::N)]),
+            (Action::OpenFile, vec![KeyChord::new(ctrl, Key::O)]),
+            (Action::Save, vec![KeyChord::new(ctrl, Key::S)]),
+            (Action::SaveAs, vec![KeyChord::new(ctrl_shift, Key::S)]),
+            (Action::SaveAsCopy, vec![KeyChord::new(ctrl_alt, Key::S)]),
+            (Action::CloseTab, vec![KeyChord::new(ctrl, Key::W)]),
+            (Action::Undo, vec![KeyChord::new(ctrl, Key::Z)]),
+            (
+                Action::Redo,
+                vec![KeyChord::new(ctrl, Key::Y), KeyChord::new(ctrl_shift, Key::Z)],
+            ),
+            (Action::FindReplace, vec![KeyChord::new(ctrl, Key::F)]),
+            (Action::FindNext, vec![KeyChord::new(Modifiers::NONE, Key::F3)]),
+            (Action::FindPrevious, vec![KeyChord::new(Modifiers::SHIFT, Key::F3)]),
+            (Action::GoToLine, vec![KeyChord::new(ctrl, Key::G)]),
+            (Action::ToggleHex, vec![KeyChord::new(ctrl_shift, Key::H)]),
+        ];
+        Self(bindings.into_iter().collect())
+    }
+}
+
+impl Keybindings {
+    pub fn chords(&self, action: Action) -> &[KeyChord] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn set_chords(&mut self, action: Action, chords: Vec<KeyChord>) {
+        self.0.insert(action, chords);
+    }
+
+    /// Text shown next to menu items, e.g. "Ctrl+S" (or "⌘S" on macOS).
+    pub fn shortcut_text(&self, ctx: &egui::Context, action: Action) -> String {
+        self.chords(action)
+            .first()
+            .map(|chord| ctx.format_shortcut(&chord.shortcut()))
+            .unwrap_or_default()
+    }
+
+    /// Consumes this frame's key presses that match a binding and returns the triggered actions.
+    /// With `field_focused`, a text field other than the editor has the keyboard and keeps
+    /// the keys of actions that `edits_text`.
+    pub fn pressed(&self, ctx: &egui::Context, field_focused: bool) -> Vec<Action> {
+        let mut bindings: Vec<(Action, KeyChord)> = self
+            .0
+            .iter()
+            .filter(|(action, _)| !(field_focused && action.edits_text()))
+            .flat_map(|(action, chords)| chords.iter().map(move |chord| (*action, *chord)))
+            .collect();
+        bindings.sort_by_key(|(_, chord)| std::cmp::Reverse(chord.specificity()));
+
+        ctx.input_mut(|i| {
+            bindings
+                .into_iter()
+                .filter(|(_, chord)| i.consume_shortcut(&chord.shortcut()))
+                .map(|(action, _)| action)
+                .collect()
+        })
+    }
+}
@@ -0,0 +1,27 @@
+//! The editor apart from its window: documents, files, search and the widgets that
+//! draw them. The `dnotepadx` binary builds the app on top; the benchmarks use it directly.
+
+pub mod cli;
+pub mod document;
+pub mod editorconfig;
+pub mod file_operations;
+pub mod fonts;
+pub mod gutter;
+pub mod hex;
+pub mod highlight;
+pub mod indent;
+pub mod instance;
+pub mod keybindings;
+pub mod mapped;
+pub mod pager;
+pub mod recovery;
+pub mod search;
+pub mod session;
+pub mod settings;
+pub mod syntax;
+pub mod text_format;
+pub mod theme;
+pub mod theme_files;
+pub mod undo;
+pub mod viewport;
+pub mod watcher;
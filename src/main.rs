@@ -1,54 +1,52 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod app;
-mod settings;
-mod theme;
-mod file_operations;
 
+use dnotepadx::{cli, instance, settings};
 use eframe::egui;
 
 fn main() -> Result<(), eframe::Error> {
+    let args = match cli::Args::parse(std::env::args_os().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("dnotepadx: {}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+    // Hand the files to the window that is already open rather than starting another
+    if !args.new_window && instance::forward(&args.files, args.wait) {
+        return Ok(());
+    }
+
+    let settings = settings::Settings::load();
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([1200.0, 800.0])
+            .with_app_id("dnotepadx")
+            .with_inner_size([settings.window_width, settings.window_height])
             .with_min_inner_size([800.0, 600.0])
             .with_icon(eframe::icon_data::from_png_bytes(&[]).unwrap_or_default()),
+        // eframe brings back the window's size and position; the app handles the rest of the session
+        persist_window: settings.restore_session,
         ..Default::default()
     };
 
     eframe::run_native(
         "DNotepadX - Renaissance Text Editor",
         options,
-        Box::new(|cc| {
-            // Load system fonts if available
-            let mut fonts = egui::FontDefinitions::default();
-            
-            // Try to load Consolas font
-            if let Ok(font_data) = std::fs::read("C:\\Windows\\Fonts\\consola.ttf") {
-                fonts.font_data.insert(
-                    "consolas".to_owned(),
-                    egui::FontData::from_owned(font_data),
-                );
-                fonts.families.get_mut(&egui::FontFamily::Monospace)
-                    .unwrap()
-                    .insert(0, "consolas".to_owned());
-            }
-            
-            // Try to load Courier New font
-            if let Ok(font_data) = std::fs::read("C:\\Windows\\Fonts\\courbd.ttf") {
-                fonts.font_data.insert(
-                    "courier_new".to_owned(),
-                    egui::FontData::from_owned(font_data),
-                );
-                fonts.families.get_mut(&egui::FontFamily::Monospace)
-                    .unwrap()
-                    .insert(1, "courier_new".to_owned());
+        Box::new(move |cc| {
+            let mut app = app::DNotepadX::new(cc, settings);
+            // A `--wait` window closes with its files, so it takes no files from later invocations
+            if !args.new_window && !args.wait {
+                if let Some(listener) = instance::Listener::start(cc.egui_ctx.clone()) {
+                    app.listen(listener);
+                }
             }
-            
-            // Set the fonts
-            cc.egui_ctx.set_fonts(fonts);
-            
-            Ok(Box::new(app::DNotepadX::new()))
+            app.open_file_args(args.files, args.wait);
+            Ok(Box::new(app))
         }),
     )
 }
@@ -0,0 +1,369 @@
+use memchr::{memchr, memchr_iter, memrchr};
+use memmap2::Mmap;
+use regex::bytes::Regex;
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+/// Bytes per row of the hex view.
+pub const HEX_ROW_BYTES: usize = 16;
+/// Longest row of the text view. Longer lines are broken at multiples of this in the
+/// file, so a file without line breaks can still be paged through in both directions.
+const ROW_LIMIT: usize = 4096;
+/// The line index remembers where every this many lines start.
+const CHECKPOINT_LINES: usize = 4096;
+/// Bytes indexed, or searched, per frame, so the window stays responsive meanwhile.
+const INDEX_STEP: usize = 32 << 20;
+const SEARCH_STEP: usize = 64 << 20;
+/// Searched chunks overlap by this much; a longer match across a chunk border is missed.
+const SEARCH_OVERLAP: usize = 4096;
+/// A file whose first this many bytes contain a NUL opens in the hex view.
+const SNIFF_BYTES: usize = 64 * 1024;
+
+/// A file shown in the read-only viewer. The bytes are memory-mapped instead of read,
+/// so opening costs the same whatever the size; line numbers and searches are worked
+/// out a chunk per frame.
+pub struct MappedFile {
+    map: Mmap,
+    /// Set when the start of the file contains NUL bytes.
+    pub binary: bool,
+    /// Shows rows of `HEX_ROW_BYTES` in hex instead of lines of text.
+    pub hex: bool,
+    /// First byte shown, the start of a row.
+    pub top: usize,
+    /// Offset to scroll into view on the next frame.
+    pub reveal: Option<usize>,
+    /// The last search match or go-to target, marked in the view.
+    highlight: Option<Range<usize>>,
+    index: LineIndex,
+    search: Option<PendingSearch>,
+    outcome: SearchStatus,
+}
+
+/// How the last search in a viewer went.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchStatus {
+    Idle,
+    /// Still running, with the fraction of the file searched so far.
+    Searching(f32),
+    Found(Range<usize>),
+    NotFound,
+}
+
+impl MappedFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let map = map_file(path)?;
+        let binary = memchr(0, &map[..map.len().min(SNIFF_BYTES)]).is_some();
+        Ok(Self {
+            map,
+            binary,
+            hex: binary,
+            top: 0,
+            reveal: None,
+            highlight: None,
+            index: LineIndex::default(),
+            search: None,
+            outcome: SearchStatus::Idle,
+        })
+    }
+
+    /// Maps the file again after it changed on disk, staying near the same offset.
+    pub fn remap(&mut self, path: &Path) -> io::Result<()> {
+        self.map = map_file(path)?;
+        self.index = LineIndex::default();
+        self.search = None;
+        self.outcome = SearchStatus::Idle;
+        self.highlight = None;
+        self.top = self.row_start(self.top);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Switches between the text and hex views, keeping the top row where it was.
+    pub fn set_hex(&mut self, hex: bool) {
+        self.hex = hex;
+        self.top = self.row_start(self.top);
+    }
+
+    /// Scrolls to `offset` and marks the byte there.
+    pub fn go_to(&mut self, offset: usize) {
+        let offset = offset.min(self.len());
+        self.highlight = Some(offset..(offset + 1).min(self.len()));
+        self.reveal = Some(offset);
+    }
+
+    pub fn highlight(&self) -> Option<Range<usize>> {
+        self.highlight.clone()
+    }
+
+    /// Start of the row that `offset` is on.
+    pub fn row_start(&self, offset: usize) -> usize {
+        let offset = offset.min(self.len().saturating_sub(1));
+        if self.hex {
+            return offset - offset % HEX_ROW_BYTES;
+        }
+        let aligned = offset - offset % ROW_LIMIT;
+        let floor = offset.saturating_sub(ROW_LIMIT);
+        let line_start = match memrchr(b'\n', &self.map[floor..offset]) {
+            Some(i) => floor + i + 1,
+            None if floor == 0 => 0,
+            // No line break for a whole row: this is a long line
+            None => return aligned,
+        };
+        // A line that fits in one row is never broken, even across a multiple of `ROW_LIMIT`
+        let row_end = line_start + ROW_LIMIT;
+        let short = offset < row_end
+            && (row_end >= self.len() || memchr(b'\n', &self.map[offset..row_end]).is_some());
+        if short {
+            line_start
+        } else {
+            line_start.max(aligned)
+        }
+    }
+
+    /// Where the row after the one starting at `start` begins, `None` on the last row.
+    pub fn next_row(&self, start: usize) -> Option<usize> {
+        let len = self.len();
+        let next = if self.hex {
+            start + HEX_ROW_BYTES
+        } else {
+            let end = (start + ROW_LIMIT).min(len);
+            match memchr(b'\n', &self.map[start.min(end)..end]) {
+                Some(i) => start + i + 1,
+                None if end == len => len,
+                None => start - start % ROW_LIMIT + ROW_LIMIT,
+            }
+        };
+        (next < len).then_some(next)
+    }
+
+    /// Moves `rows` rows down from the row starting at `start`, or up when negative,
+    /// stopping at either end of the file.
+    pub fn step(&self, mut start: usize, rows: isize) -> usize {
+        for _ in 0..rows.unsigned_abs() {
+            let next = if rows > 0 {
+                self.next_row(start)
+            } else {
+                (start > 0).then(|| self.row_start(start - 1))
+            };
+            match next {
+                Some(next) => start = next,
+                None => break,
+            }
+        }
+        start
+    }
+
+    /// The bytes of the row starting at `start`, without its line break.
+    pub fn row(&self, start: usize) -> &[u8] {
+        let end = self.next_row(start).unwrap_or(self.len());
+        let row = &self.map[start.min(end)..end];
+        if self.hex {
+            return row;
+        }
+        let row = row.strip_suffix(b"\n").unwrap_or(row);
+        row.strip_suffix(b"\r").unwrap_or(row)
+    }
+
+    /// Extends the line index by another chunk; returns true while there is more to do.
+    pub fn index_more(&mut self) -> bool {
+        self.index.advance(&self.map)
+    }
+
+    /// How much of the file the line index covers, from 0 to 1.
+    pub fn indexed(&self) -> f32 {
+        fraction(self.index.scanned, self.len())
+    }
+
+    /// Zero-based line that `offset` is on, once the index has got that far.
+    pub fn line_at(&self, offset: usize) -> Option<usize> {
+        self.index.line_at(&self.map, offset.min(self.len()))
+    }
+
+    /// Number of lines, once the whole file is indexed.
+    pub fn line_count(&self) -> Option<usize> {
+        (self.index.scanned == self.len()).then_some(self.index.breaks + 1)
+    }
+
+    /// Starts looking for `regex` from `from`, forward or backward, wrapping around
+    /// at the end of the file. The search runs in steps of `search_more`.
+    pub fn find(&mut self, regex: Regex, from: usize, forward: bool) {
+        let (len, from) = (self.len(), from.min(self.len()));
+        self.search = Some(PendingSearch {
+            regex,
+            forward,
+            next: from,
+            stop: if forward { len } else { 0 },
+            limit: if forward { len } else { from },
+            from,
+            wrapped: false,
+            searched: 0,
+        });
+    }
+
+    /// Searches another chunk; returns true while the search goes on.
+    pub fn search_more(&mut self) -> bool {
+        let Some(search) = &mut self.search else {
+            return false;
+        };
+        match search.advance(&self.map) {
+            Some(found) => {
+                self.highlight = found.clone();
+                self.reveal = found.as_ref().map(|range| range.start);
+                self.outcome = match found {
+                    Some(range) => SearchStatus::Found(range),
+                    None => SearchStatus::NotFound,
+                };
+                self.search = None;
+                false
+            }
+            None => true,
+        }
+    }
+
+    pub fn search_status(&self) -> SearchStatus {
+        match &self.search {
+            Some(search) => SearchStatus::Searching(fraction(search.searched, self.len())),
+            None => self.outcome.clone(),
+        }
+    }
+}
+
+fn map_file(path: &Path) -> io::Result<Mmap> {
+    let file = File::open(path)?;
+    // SAFETY: the mapping is only read. Another program truncating the file while it
+    // is mapped would make reads past the new end fault; the file watcher remaps on
+    // changes, which narrows that window but can't close it.
+    unsafe { Mmap::map(&file) }
+}
+
+fn fraction(part: usize, whole: usize) -> f32 {
+    if whole == 0 {
+        1.0
+    } else {
+        (part as f64 / whole as f64) as f32
+    }
+}
+
+/// Where lines start, recorded every `CHECKPOINT_LINES` lines as the file is scanned.
+struct LineIndex {
+    checkpoints: Vec<usize>,
+    /// Bytes scanned so far, from the start of the file.
+    scanned: usize,
+    /// Line breaks in the scanned bytes.
+    breaks: usize,
+}
+
+impl Default for LineIndex {
+    fn default() -> Self {
+        Self {
+            checkpoints: vec![0],
+            scanned: 0,
+            breaks: 0,
+        }
+    }
+}
+
+impl LineIndex {
+    fn advance(&mut self, bytes: &[u8]) -> bool {
+        let end = (self.scanned + INDEX_STEP).min(bytes.len());
+        for i in memchr_iter(b'\n', &bytes[self.scanned..end]) {
+            self.breaks += 1;
+            if self.breaks.is_multiple_of(CHECKPOINT_LINES) {
+                self.checkpoints.push(self.scanned + i + 1);
+            }
+        }
+        self.scanned = end;
+        end < bytes.len()
+    }
+
+    fn line_at(&self, bytes: &[u8], offset: usize) -> Option<usize> {
+        if offset > self.scanned {
+            return None;
+        }
+        let checkpoint = self.checkpoints.partition_point(|&start| start <= offset) - 1;
+        let from = self.checkpoints[checkpoint];
+        Some(checkpoint * CHECKPOINT_LINES + memchr_iter(b'\n', &bytes[from..offset]).count())
+    }
+}
+
+/// A search going through the file a chunk at a time. The first pass runs from where
+/// the search started to the end of the file in its direction, the second wraps around.
+struct PendingSearch {
+    regex: Regex,
+    forward: bool,
+    /// Edge of what is left of the current pass: its start going forward, its end going backward.
+    next: usize,
+    /// Where the current pass ends.
+    stop: usize,
+    /// Matches must end by here.
+    limit: usize,
+    from: usize,
+    wrapped: bool,
+    searched: usize,
+}
+
+impl PendingSearch {
+    /// Searches the next chunk: `Some` with the outcome once the search is over.
+    fn advance(&mut self, bytes: &[u8]) -> Option<Option<Range<usize>>> {
+        let chunk = if self.forward {
+            self.next..(self.next + SEARCH_STEP).min(self.stop)
+        } else {
+            self.next.saturating_sub(SEARCH_STEP).max(self.stop)..self.next
+        };
+        let haystack = &bytes[..(chunk.end + SEARCH_OVERLAP).min(self.limit)];
+        let mut matches = non_empty_matches(&self.regex, haystack, chunk.clone());
+        let found = if self.forward { matches.next() } else { matches.last() };
+        if found.is_some() {
+            return Some(found);
+        }
+
+        self.searched += chunk.len();
+        self.next = if self.forward { chunk.end } else { chunk.start };
+        if self.next != self.stop {
+            return None;
+        }
+        if self.wrapped {
+            return Some(None);
+        }
+        self.wrapped = true;
+        if self.forward {
+            (self.next, self.stop) = (0, self.from);
+        } else {
+            (self.next, self.stop, self.limit) = (bytes.len(), self.from, bytes.len());
+        }
+        None
+    }
+}
+
+/// Matches of `regex` in `haystack` that start inside `starts`, skipping empty ones.
+fn non_empty_matches<'a>(
+    regex: &'a Regex,
+    haystack: &'a [u8],
+    starts: Range<usize>,
+) -> impl Iterator<Item = Range<usize>> + 'a {
+    let mut at = starts.start;
+    std::iter::from_fn(move || {
+        while at <= haystack.len() {
+            let m = regex.find_at(haystack, at)?;
+            if m.start() >= starts.end {
+                return None;
+            }
+            if m.is_empty() {
+                at = m.end() + 1;
+                continue;
+            }
+            at = m.end();
+            return Some(m.range());
+        }
+        None
+    })
+}
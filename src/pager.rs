@@ -0,0 +1,289 @@
+use crate::hex::{byte_columns, row_text, ASCII_COLUMN};
+use crate::mapped::{MappedFile, HEX_ROW_BYTES};
+use crate::viewport::{advance, scrollbar, wheel_steps};
+use eframe::egui::{self, Align2, Color32, Event, EventFilter, FontId, Id, Key, Rect, Sense, TextEdit, Ui};
+use std::ops::Range;
+
+const SCROLLBAR_WIDTH: f32 = 10.0;
+
+pub struct PagerStyle {
+    pub font_id: FontId,
+    pub text_color: Color32,
+    pub offset_color: Color32,
+    pub gutter_background: Color32,
+    pub highlight: Color32,
+    pub scrollbar: Color32,
+}
+
+/// Horizontal scroll position of the text view, in columns.
+#[derive(Debug, Clone, Copy, Default)]
+struct ScrollState {
+    left: usize,
+    /// Widest row seen so far, which sizes the horizontal scroll bar.
+    widest: usize,
+}
+
+/// Shows a memory-mapped file read-only, either as text or as a hex dump. The view
+/// is kept as the offset of its top row and rows are found from there, so a frame
+/// only reads the bytes on screen. Each row starts with its offset in the file.
+/// With `editable`, the toolbar offers to edit the file in the hex editor instead;
+/// returns true when the user asks for that.
+pub fn show(ui: &mut Ui, id: Id, file: &mut MappedFile, style: &PagerStyle, tab: usize, editable: bool) -> bool {
+    let indexing = file.index_more();
+    let searching = file.search_more();
+    if indexing || searching {
+        ui.ctx().request_repaint();
+    }
+    let edit = toolbar(ui, id, file, editable);
+
+    let row_height = ui.fonts(|f| f.row_height(&style.font_id));
+    let char_width = ui.fonts(|f| f.glyph_width(&style.font_id, '0'));
+    let digits = format!("{:X}", file.len()).len().max(8);
+
+    let rect = ui.available_rect_before_wrap();
+    ui.allocate_rect(rect, Sense::hover());
+    let gutter_rect = Rect::from_min_max(
+        rect.min,
+        egui::pos2(rect.min.x + (digits + 2) as f32 * char_width, rect.max.y),
+    );
+    let text_rect = Rect::from_min_max(
+        egui::pos2(gutter_rect.max.x, rect.min.y),
+        egui::pos2(rect.max.x - SCROLLBAR_WIDTH, rect.max.y - SCROLLBAR_WIDTH),
+    );
+    let rows = ((text_rect.height() / row_height) as usize).max(1);
+    let cols = ((text_rect.width() / char_width) as usize).max(1);
+    let page = (rows as isize - 1).max(1);
+
+    let response = ui.interact(text_rect, id, Sense::click());
+    if response.clicked() {
+        response.request_focus();
+    }
+    let state_id = id.with("scroll");
+    let mut state: ScrollState = ui.data(|d| d.get_temp(state_id)).unwrap_or_default();
+    let mut top = file.top;
+
+    if response.hovered() {
+        let delta = ui.input(|i| i.raw_scroll_delta);
+        top = file.step(top, -wheel_steps(delta.y / row_height));
+        state.left = state.left.saturating_add_signed(-wheel_steps(delta.x / char_width));
+    }
+
+    if response.has_focus() {
+        ui.memory_mut(|m| {
+            let filter = EventFilter {
+                tab: false,
+                horizontal_arrows: true,
+                vertical_arrows: true,
+                escape: false,
+            };
+            m.set_focus_lock_filter(id, filter);
+        });
+        for event in ui.input(|i| i.events.clone()) {
+            let Event::Key {
+                key,
+                pressed: true,
+                modifiers,
+                ..
+            } = event
+            else {
+                continue;
+            };
+            match key {
+                Key::ArrowUp => top = file.step(top, -1),
+                Key::ArrowDown => top = file.step(top, 1),
+                Key::PageUp => top = file.step(top, -page),
+                Key::PageDown => top = file.step(top, page),
+                Key::Home if modifiers.command => top = 0,
+                Key::End if modifiers.command => top = file.row_start(file.len()),
+                Key::ArrowLeft => state.left = state.left.saturating_sub(1),
+                Key::ArrowRight => state.left += 1,
+                Key::Home => state.left = 0,
+                _ => {}
+            }
+        }
+    }
+
+    let highlight = file.highlight();
+    if let Some(offset) = file.reveal.take() {
+        let row = file.row_start(offset);
+        top = file.step(row, -(rows as isize / 3));
+        if !file.hex {
+            // Bring a match outside the columns in view into view as well
+            let (_, marks, _) = text_row(file.row(row), row, highlight.as_ref(), 0, 0, tab);
+            if let Some(mark) = marks.first().filter(|m| m.start < state.left || m.end > state.left + cols) {
+                state.left = mark.start.saturating_sub(cols / 4);
+            }
+        }
+    }
+
+    let mut starts = row_starts(file, top, rows);
+    if starts.len() < rows && top > 0 {
+        // Near the end, fill the view instead of leaving blank rows at the bottom
+        top = file.step(top, starts.len() as isize - rows as isize);
+        starts = row_starts(file, top, rows);
+    }
+    if file.hex {
+        state.left = 0;
+    }
+
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(gutter_rect, 0.0, style.gutter_background);
+    for (i, &start) in starts.iter().enumerate() {
+        let y = text_rect.top() + i as f32 * row_height;
+        painter.text(
+            egui::pos2(gutter_rect.left() + char_width, y),
+            Align2::LEFT_TOP,
+            format!("{:0width$X}", start, width = digits),
+            style.font_id.clone(),
+            style.offset_color,
+        );
+
+        let (text, marks, width) = if file.hex {
+            hex_row(file.row(start), start, highlight.as_ref())
+        } else {
+            text_row(file.row(start), start, highlight.as_ref(), state.left, cols + 1, tab)
+        };
+        state.widest = state.widest.max(width);
+        for mark in marks {
+            let x = |column: usize| text_rect.left() + (column as f32 - state.left as f32) * char_width;
+            let band = Rect::from_x_y_ranges(x(mark.start)..=x(mark.end), y..=y + row_height);
+            painter.rect_filled(band.intersect(text_rect), 0.0, style.highlight);
+        }
+        painter.text(
+            egui::pos2(text_rect.left(), y),
+            Align2::LEFT_TOP,
+            text,
+            style.font_id.clone(),
+            style.text_color,
+        );
+    }
+
+    let vertical = Rect::from_min_max(
+        egui::pos2(text_rect.right(), rect.top()),
+        egui::pos2(rect.right(), text_rect.bottom()),
+    );
+    let horizontal = Rect::from_min_max(
+        egui::pos2(text_rect.left(), text_rect.bottom()),
+        egui::pos2(text_rect.right(), rect.bottom()),
+    );
+    let shown = starts.last().map_or(0, |&last| {
+        file.next_row(last).unwrap_or(file.len()) - top
+    });
+    let dragged = scrollbar(ui, id.with("vertical"), vertical, true, top, shown, file.len(), style.scrollbar);
+    if dragged != top.min(file.len().saturating_sub(shown)) {
+        top = file.row_start(dragged);
+    }
+    state.left = scrollbar(ui, id.with("horizontal"), horizontal, false, state.left, cols, state.widest, style.scrollbar);
+    ui.data_mut(|d| d.insert_temp(state_id, state));
+    file.top = top;
+    edit
+}
+
+/// Text/hex switch and the go-to-offset field above the view; returns true when
+/// "Edit in Hex" was clicked.
+fn toolbar(ui: &mut Ui, id: Id, file: &mut MappedFile, editable: bool) -> bool {
+    let field_id = id.with("go_to_offset");
+    let (mut text, mut invalid): (String, bool) = ui.data(|d| d.get_temp(field_id)).unwrap_or_default();
+    let mut edit = false;
+    ui.horizontal(|ui| {
+        ui.style_mut().override_font_id = None;
+        ui.label("🔒 Read-only");
+        if editable {
+            edit = ui.button("✏ Edit in Hex").on_hover_text("Load the file into the hex editor").clicked();
+        }
+        ui.separator();
+        let mut hex = file.hex;
+        ui.selectable_value(&mut hex, false, "Text");
+        ui.selectable_value(&mut hex, true, "Hex");
+        if hex != file.hex {
+            file.set_hex(hex);
+        }
+        ui.separator();
+        ui.label("Go to offset:");
+        let field = ui.add(TextEdit::singleline(&mut text).hint_text("1234 or 0x4D2").desired_width(120.0));
+        let entered = field.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+        if ui.button("Go").clicked() || entered {
+            match parse_offset(&text).filter(|&offset| offset <= file.len()) {
+                Some(offset) => {
+                    file.go_to(offset);
+                    invalid = false;
+                }
+                None => invalid = true,
+            }
+        }
+        if invalid {
+            ui.colored_label(Color32::RED, format!("Enter an offset up to {}", file.len()));
+        }
+    });
+    ui.data_mut(|d| d.insert_temp(field_id, (text, invalid)));
+    edit
+}
+
+/// Starts of the rows on screen from `top`, one more than fits so a partly shown row is drawn.
+fn row_starts(file: &MappedFile, top: usize, rows: usize) -> Vec<usize> {
+    std::iter::successors(Some(top), |&start| file.next_row(start))
+        .take(rows + 1)
+        .collect()
+}
+
+/// A decimal offset, or a hexadecimal one starting with `0x`.
+fn parse_offset(text: &str) -> Option<usize> {
+    let text = text.trim().replace(['_', ','], "");
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Up to `width` columns of a text row from column `left`, the columns `highlight`
+/// covers in it, and how wide the whole row is. Bytes that aren't valid UTF-8 show
+/// as �, control characters other than tabs as ·.
+fn text_row(
+    bytes: &[u8],
+    start: usize,
+    highlight: Option<&Range<usize>>,
+    left: usize,
+    width: usize,
+    tab: usize,
+) -> (String, Vec<Range<usize>>, usize) {
+    let right = left + width;
+    let mut text = String::new();
+    let mut mark: Option<Range<usize>> = None;
+    let mut column = 0;
+    let mut offset = start;
+    for chunk in bytes.utf8_chunks() {
+        let valid = chunk.valid().char_indices().map(|(i, c)| (i, c, c.len_utf8()));
+        let invalid = (!chunk.invalid().is_empty())
+            .then_some((chunk.valid().len(), char::REPLACEMENT_CHARACTER, chunk.invalid().len()));
+        for (i, c, len) in valid.chain(invalid) {
+            let c = if c.is_control() && c != '\t' { '·' } else { c };
+            let next = advance(column, c, tab);
+            if highlight.is_some_and(|h| h.start < offset + i + len && offset + i < h.end) {
+                let range = mark.get_or_insert(column..next);
+                range.end = next;
+            }
+            if next > left && column < right {
+                match c {
+                    '\t' => text.extend(std::iter::repeat_n(' ', next.min(right) - column.max(left))),
+                    _ => text.push(c),
+                }
+            }
+            column = next;
+        }
+        offset += chunk.valid().len() + chunk.invalid().len();
+    }
+    // A highlight on the line break itself
+    if mark.is_none() && highlight.is_some_and(|h| h.start == offset && h.end > h.start) {
+        mark = Some(column..column + 1);
+    }
+    (text, mark.into_iter().collect(), column)
+}
+
+/// A hex view row and the columns `highlight` covers in both halves of it.
+fn hex_row(bytes: &[u8], start: usize, highlight: Option<&Range<usize>>) -> (String, Vec<Range<usize>>, usize) {
+    let marks = highlight
+        .map(|h| h.start.max(start)..h.end.min(start + bytes.len()))
+        .filter(|h| h.start < h.end)
+        .map_or(Vec::new(), |h| byte_columns(h.start - start..h.end - start).to_vec());
+    (row_text(bytes), marks, ASCII_COLUMN + HEX_ROW_BYTES)
+}
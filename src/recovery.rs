@@ -0,0 +1,250 @@
+use crate::hex::as_chars;
+use crate::text_format::FileFormat;
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Snapshot of an unsaved buffer, written so its text survives a crash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub path: Option<PathBuf>,
+    pub title: String,
+    /// The text, or for the hex editor the bytes one per character, like `Document::snapshot`.
+    pub content: String,
+    #[serde(default)]
+    pub hex: bool,
+    /// How the text is written when saved; entries from older versions get UTF-8 and LF.
+    #[serde(default)]
+    pub format: FileFormat,
+    /// Seconds since the Unix epoch when the snapshot was taken.
+    pub timestamp: u64,
+}
+
+/// A journal entry left behind by a session that did not exit cleanly.
+pub struct RecoveredBuffer {
+    pub file: PathBuf,
+    pub entry: JournalEntry,
+}
+
+/// The contents of a buffer to journal. Ropes and bytes only become a string on the
+/// journal's thread, so large documents don't hold up typing while they are copied.
+pub enum Snapshot {
+    Text(String),
+    Rope(Rope),
+    Bytes(Vec<u8>),
+}
+
+impl Snapshot {
+    fn into_content(self) -> String {
+        match self {
+            Snapshot::Text(text) => text,
+            Snapshot::Rope(rope) => rope.to_string(),
+            Snapshot::Bytes(bytes) => as_chars(&bytes),
+        }
+    }
+}
+
+enum Command {
+    /// The entry's content is filled in from the snapshot.
+    Write(PathBuf, JournalEntry, Snapshot),
+    Remove(PathBuf),
+    /// Acknowledges once the commands before it are done.
+    Flush(Sender<()>),
+}
+
+/// Writes recovery snapshots on a background thread so typing never waits on the disk.
+///
+/// Snapshots are named after the session that wrote them, which holds a lock on
+/// `<session>.lock` for as long as it runs; snapshots whose session lock is gone or
+/// free were left by a session that ended without cleaning up.
+pub struct Journal {
+    dir: Option<PathBuf>,
+    sender: Sender<Command>,
+    /// The process id plus start time, so a reused pid never claims old snapshots.
+    session: String,
+    /// Held until the journal is dropped; `None` disables the journal.
+    _lock: Option<File>,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel::<Command>();
+        std::thread::spawn(move || {
+            for command in receiver {
+                match command {
+                    Command::Write(file, mut entry, snapshot) => {
+                        entry.content = snapshot.into_content();
+                        if let Ok(content) = serde_json::to_string(&entry) {
+                            // Write then rename so a crash mid-write never leaves a torn snapshot
+                            let tmp = file.with_extension("tmp");
+                            if std::fs::write(&tmp, content).is_ok() {
+                                let _ = std::fs::rename(&tmp, &file);
+                            }
+                        }
+                    }
+                    Command::Remove(file) => {
+                        let _ = std::fs::remove_file(file);
+                    }
+                    Command::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+
+        let session = format!("{}-{}", std::process::id(), now_nanos());
+        // Without the lock another instance would take our snapshots for orphans
+        let (dir, lock) = journal_dir()
+            .filter(|dir| std::fs::create_dir_all(dir).is_ok())
+            .and_then(|dir| lock_session(&dir, &session).map(|lock| (dir, lock)))
+            .unzip();
+        Self {
+            dir,
+            sender,
+            session,
+            _lock: lock,
+        }
+    }
+
+    fn file_for(&self, document_id: u64) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}-{}.json", self.session, document_id)))
+    }
+
+    pub fn write(
+        &self,
+        document_id: u64,
+        path: Option<PathBuf>,
+        title: String,
+        format: FileFormat,
+        snapshot: Snapshot,
+    ) {
+        if let Some(file) = self.file_for(document_id) {
+            let entry = JournalEntry {
+                path,
+                title,
+                content: String::new(),
+                hex: matches!(snapshot, Snapshot::Bytes(_)),
+                format,
+                timestamp: now_secs(),
+            };
+            let _ = self.sender.send(Command::Write(file, entry, snapshot));
+        }
+    }
+
+    pub fn remove(&self, document_id: u64) {
+        if let Some(file) = self.file_for(document_id) {
+            let _ = self.sender.send(Command::Remove(file));
+        }
+    }
+
+    /// Waits for queued writes and removals to reach the disk; called on exit.
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        if self.sender.send(Command::Flush(done)).is_ok() {
+            let _ = wait.recv_timeout(Duration::from_secs(2));
+        }
+    }
+
+    /// Snapshots left by sessions that are no longer running.
+    pub fn orphaned(&self) -> Vec<RecoveredBuffer> {
+        let Some(dir) = &self.dir else {
+            return Vec::new();
+        };
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+
+        let files: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+        for lock in files.iter().filter(|file| has_extension(file, "lock")) {
+            if session_of(lock) != Some(self.session.as_str()) && !is_locked(lock) {
+                let _ = std::fs::remove_file(lock);
+            }
+        }
+
+        let mut buffers: Vec<RecoveredBuffer> = files
+            .into_iter()
+            .filter(|file| has_extension(file, "json"))
+            .filter(|file| {
+                owner_session(file).is_some_and(|owner| {
+                    owner != self.session && !dir.join(format!("{}.lock", owner)).exists()
+                })
+            })
+            .filter_map(|file| {
+                let content = std::fs::read_to_string(&file).ok()?;
+                let entry = serde_json::from_str(&content).ok()?;
+                Some(RecoveredBuffer { file, entry })
+            })
+            .collect();
+        buffers.sort_by_key(|b| b.entry.timestamp);
+        buffers
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.dir {
+            let _ = std::fs::remove_file(dir.join(format!("{}.lock", self.session)));
+        }
+    }
+}
+
+pub fn discard(buffer: &RecoveredBuffer) {
+    let _ = std::fs::remove_file(&buffer.file);
+}
+
+fn journal_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("dnotepadx").join("recovery"))
+}
+
+/// Creates and locks the lock file of `session`.
+fn lock_session(dir: &Path, session: &str) -> Option<File> {
+    let file = File::create(dir.join(format!("{}.lock", session))).ok()?;
+    file.lock().ok()?;
+    Some(file)
+}
+
+/// Whether a running session holds `lock`. Errors other than a missing file count as
+/// held, so snapshots are never offered while their session might still be writing them.
+fn is_locked(lock: &Path) -> bool {
+    match File::open(lock) {
+        Ok(file) => file.try_lock().is_err(),
+        Err(e) => e.kind() != io::ErrorKind::NotFound,
+    }
+}
+
+fn has_extension(file: &Path, extension: &str) -> bool {
+    file.extension().is_some_and(|ext| ext == extension)
+}
+
+fn session_of(lock: &Path) -> Option<&str> {
+    lock.file_stem()?.to_str()
+}
+
+/// The session in a snapshot's name, `<session>-<document>.json`.
+fn owner_session(file: &Path) -> Option<&str> {
+    Some(file.file_stem()?.to_str()?.rsplit_once('-')?.0)
+}
+
+fn now_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos())
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
@@ -0,0 +1,577 @@
+use crate::document::Document;
+use crate::undo::{byte_index, Edit};
+use regex::{Regex, RegexBuilder};
+use regex_syntax::hir::{Class, Hir, HirKind, Look};
+use ropey::Rope;
+use std::ops::Range;
+
+/// Roughly how much of a rope `find_all_rope` copies out at a time.
+const BLOCK_BYTES: usize = 1 << 20;
+
+/// Toggles shown in the Find & Replace window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub regex: bool,
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub in_selection: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            regex: false,
+            case_sensitive: true,
+            whole_word: false,
+            in_selection: false,
+        }
+    }
+}
+
+/// A compiled search. Plain-text searches are compiled to an escaped regex so
+/// every option goes through the same matcher.
+pub struct SearchQuery {
+    regex: Regex,
+    expand_captures: bool,
+    /// Whether every match lies within a line and nothing depends on where the whole
+    /// text starts or ends, so the text can be searched a block of lines at a time.
+    within_lines: bool,
+}
+
+impl SearchQuery {
+    pub fn new(pattern: &str, options: &SearchOptions) -> Result<Self, String> {
+        let source = regex_source(pattern, options)?;
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(!options.case_sensitive)
+            .multi_line(true)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let within_lines = regex_syntax::ParserBuilder::new()
+            .multi_line(true)
+            .build()
+            .parse(&source)
+            .is_ok_and(|hir| {
+                let looks = hir.properties().look_set();
+                !looks.contains(Look::Start) && !looks.contains(Look::End) && !matches_newline(&hir)
+            });
+        Ok(Self {
+            regex,
+            expand_captures: options.regex,
+            within_lines,
+        })
+    }
+
+    /// Byte ranges of all non-empty matches inside `scope` (the whole text when `None`).
+    pub fn find_all(&self, text: &str, scope: Option<Range<usize>>) -> Vec<Range<usize>> {
+        let scope = scope.unwrap_or(0..text.len());
+        let mut matches = Vec::new();
+        let mut start = scope.start;
+        while start <= scope.end {
+            let Some(m) = self.regex.find_at(text, start) else {
+                break;
+            };
+            if m.end() > scope.end {
+                break;
+            }
+            if m.is_empty() {
+                // Skip zero-width matches such as `^` or `a*`, they can't be selected or replaced usefully
+                start = next_char_boundary(text, m.end());
+                continue;
+            }
+            matches.push(m.range());
+            start = m.end();
+        }
+        matches
+    }
+
+    /// Like `find_all`, for the text of a rope. Patterns that stay within a line are
+    /// searched a block of whole lines at a time rather than in a copy of the entire text;
+    /// block edges then look to the regex just like the line breaks they are.
+    pub fn find_all_rope(&self, rope: &Rope, scope: Option<Range<usize>>) -> Vec<Range<usize>> {
+        if !self.within_lines {
+            return self.find_all(&rope.to_string(), scope);
+        }
+        let scope = scope.unwrap_or(0..rope.len_bytes());
+        let mut base = rope.line_to_byte(rope.byte_to_line(scope.start));
+        let (mut chunks, chunk_start, _, _) = rope.chunks_at_byte(base);
+        let mut block = String::with_capacity(BLOCK_BYTES);
+        if let Some(first) = chunks.next() {
+            block.push_str(&first[base - chunk_start..]);
+        }
+
+        let mut matches = Vec::new();
+        loop {
+            let more = chunks.next();
+            if let Some(chunk) = more {
+                block.push_str(chunk);
+                if block.len() < BLOCK_BYTES {
+                    continue;
+                }
+            }
+            let cut = match more {
+                Some(_) => match block.rfind('\n') {
+                    Some(i) => i + 1,
+                    None => continue,
+                },
+                None => block.len(),
+            };
+            let local = scope.start.saturating_sub(base)..(scope.end - base).min(cut);
+            let found = self.find_all(&block[..cut], Some(local));
+            matches.extend(found.into_iter().map(|m| m.start + base..m.end + base));
+            block.drain(..cut);
+            base += cut;
+            if more.is_none() || base >= scope.end {
+                return matches;
+            }
+        }
+    }
+
+    /// The text that replaces the match at `range`. In regex mode `$1`, `${name}`
+    /// and `$0` refer to capture groups; otherwise the replacement is literal.
+    pub fn replacement(&self, text: &str, range: Range<usize>, replace: &str) -> String {
+        if !self.expand_captures {
+            return replace.to_string();
+        }
+        match self.regex.captures_at(text, range.start) {
+            Some(caps) if caps.get(0).map(|m| m.range()) == Some(range) => {
+                let mut expanded = String::new();
+                caps.expand(replace, &mut expanded);
+                expanded
+            }
+            _ => replace.to_string(),
+        }
+    }
+
+    /// Edits for Replace All: every one of `matches` in `text` replaced with `replace`,
+    /// in order for `Document::edit`. Also returns how many characters the text grows by.
+    pub fn replace_all_edits(&self, text: &str, matches: &[Range<usize>], replace: &str) -> (Vec<Edit>, isize) {
+        let mut edits = Vec::with_capacity(matches.len());
+        let mut char_pos = 0;
+        let mut last_byte = 0;
+        let mut shift: isize = 0;
+        for range in matches {
+            char_pos += text[last_byte..range.start].chars().count();
+            last_byte = range.start;
+            let removed = &text[range.clone()];
+            let replacement = self.replacement(text, range.clone(), replace);
+            // Each edit is applied after the previous ones, so shift by what they changed
+            let at = (char_pos as isize + shift) as usize;
+            shift += replacement.chars().count() as isize - removed.chars().count() as isize;
+            edits.push(Edit::new(at, removed, replacement));
+        }
+        (edits, shift)
+    }
+}
+
+/// The search as a regex over raw bytes, for files that aren't held as text.
+pub fn byte_regex(pattern: &str, options: &SearchOptions) -> Result<regex::bytes::Regex, String> {
+    regex::bytes::RegexBuilder::new(&regex_source(pattern, options)?)
+        .case_insensitive(!options.case_sensitive)
+        .multi_line(true)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// A byte pattern for the hex editor, like `4D 5A ?? 00` or `"PNG" 0D 0A`: hex pairs,
+/// `??` for any byte and quoted text for its UTF-8 bytes, with or without spaces between them.
+pub fn byte_pattern(pattern: &str) -> Result<regex::bytes::Regex, String> {
+    const PREFIX: &str = "(?s-u)";
+    let invalid = || "Enter bytes in hex, e.g. 4D 5A ?? 00 \"text\"".to_string();
+    let mut source = PREFIX.to_string();
+    let push = |source: &mut String, byte: u8| source.push_str(&format!("\\x{:02X}", byte));
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '"' => {
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '"' {
+                        closed = true;
+                        break;
+                    }
+                    c.encode_utf8(&mut [0; 4]).bytes().for_each(|byte| push(&mut source, byte));
+                }
+                if !closed {
+                    return Err(invalid());
+                }
+            }
+            '?' if chars.next() == Some('?') => source.push('.'),
+            c => {
+                let high = c.to_digit(16).ok_or_else(invalid)?;
+                let low = chars.next().and_then(|c| c.to_digit(16)).ok_or_else(invalid)?;
+                push(&mut source, (high << 4 | low) as u8);
+            }
+        }
+    }
+    if source == PREFIX {
+        return Err("Nothing to search for".to_string());
+    }
+    regex::bytes::Regex::new(&source).map_err(|e| e.to_string())
+}
+
+fn regex_source(pattern: &str, options: &SearchOptions) -> Result<String, String> {
+    if pattern.is_empty() {
+        return Err("Nothing to search for".to_string());
+    }
+    let source = if options.regex {
+        pattern.to_string()
+    } else {
+        regex::escape(pattern)
+    };
+    if options.whole_word {
+        Ok(format!(r"\b(?:{})\b", source))
+    } else {
+        Ok(source)
+    }
+}
+
+/// Whether `hir` can match a line break anywhere.
+fn matches_newline(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => false,
+        HirKind::Literal(literal) => literal.0.contains(&b'\n'),
+        HirKind::Class(Class::Unicode(class)) => class.ranges().iter().any(|r| r.start() <= '\n' && '\n' <= r.end()),
+        HirKind::Class(Class::Bytes(class)) => class.ranges().iter().any(|r| r.start() <= b'\n' && b'\n' <= r.end()),
+        HirKind::Repetition(repetition) => matches_newline(&repetition.sub),
+        HirKind::Capture(capture) => matches_newline(&capture.sub),
+        HirKind::Concat(hirs) | HirKind::Alternation(hirs) => hirs.iter().any(matches_newline),
+    }
+}
+
+fn next_char_boundary(text: &str, index: usize) -> usize {
+    let mut next = index + 1;
+    while next < text.len() && !text.is_char_boundary(next) {
+        next += 1;
+    }
+    next
+}
+
+/// Position of the match equal to `selection` among `matches` for a "3 of 17" counter, 1-based.
+pub fn match_ordinal(matches: &[Range<usize>], selection: &Range<usize>) -> Option<usize> {
+    matches
+        .binary_search_by_key(&selection.start, |m| m.start)
+        .ok()
+        .filter(|&i| matches[i] == *selection)
+        .map(|i| i + 1)
+}
+
+/// Find & Replace window state plus the cached matches for the active document.
+#[derive(Default)]
+pub struct SearchState {
+    pub find_text: String,
+    pub replace_text: String,
+    pub options: SearchOptions,
+    /// Character range of the selection captured when "In selection" was turned on,
+    /// together with the id of the document it belongs to.
+    pub scope: Option<(u64, Range<usize>)>,
+    query: Option<SearchQuery>,
+    error: Option<String>,
+    matches: Vec<Range<usize>>,
+    computed_for: Option<SearchKey>,
+}
+
+/// What the cached matches were computed for.
+#[derive(PartialEq)]
+struct SearchKey {
+    document_id: u64,
+    revision: u64,
+    pattern: String,
+    options: SearchOptions,
+    scope: Option<Range<usize>>,
+}
+
+impl SearchState {
+    pub fn query(&self) -> Option<&SearchQuery> {
+        self.query.as_ref()
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Byte ranges of the matches in the document passed to the last `refresh`.
+    pub fn matches(&self) -> &[Range<usize>] {
+        &self.matches
+    }
+
+    /// Character range the search is restricted to in document `document_id`, if any.
+    pub fn scope_for(&self, document_id: u64) -> Option<Range<usize>> {
+        match &self.scope {
+            Some((id, range)) if self.options.in_selection && *id == document_id => {
+                Some(range.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// `refresh`, `refresh_rope` or `refresh_bytes`, whichever fits how `doc` is edited.
+    pub fn refresh_document(&mut self, doc: &Document) {
+        match (&doc.hex, &doc.large) {
+            (Some(hex), _) => self.refresh_bytes(doc.id(), doc.revision(), &hex.bytes),
+            (None, Some(rope)) => self.refresh_rope(doc.id(), doc.revision(), rope),
+            (None, None) => self.refresh(doc.id(), doc.revision(), &doc.content),
+        }
+    }
+
+    /// Recomputes the matches when the text, pattern or options changed since last time.
+    pub fn refresh(&mut self, document_id: u64, revision: u64, text: &str) {
+        self.refresh_with(document_id, revision, |query, scope| {
+            let byte_scope = scope.map(|r| byte_index(text, r.start)..byte_index(text, r.end));
+            query.find_all(text, byte_scope)
+        });
+    }
+
+    /// Like `refresh`, for a document in large-file mode.
+    pub fn refresh_rope(&mut self, document_id: u64, revision: u64, rope: &Rope) {
+        self.refresh_with(document_id, revision, |query, scope| {
+            let byte = |char: usize| rope.char_to_byte(char.min(rope.len_chars()));
+            query.find_all_rope(rope, scope.map(|r| byte(r.start)..byte(r.end)))
+        });
+    }
+
+    /// Runs `find` with the query and the character scope when the cached matches are stale.
+    fn refresh_with(
+        &mut self,
+        document_id: u64,
+        revision: u64,
+        find: impl FnOnce(&SearchQuery, Option<Range<usize>>) -> Vec<Range<usize>>,
+    ) {
+        let scope = self.scope_for(document_id);
+        let key = SearchKey {
+            document_id,
+            revision,
+            pattern: self.find_text.clone(),
+            options: self.options.clone(),
+            scope: scope.clone(),
+        };
+        if self.computed_for.as_ref() == Some(&key) {
+            return;
+        }
+
+        self.matches.clear();
+        self.error = None;
+        self.query = None;
+        if !self.find_text.is_empty() {
+            match SearchQuery::new(&self.find_text, &self.options) {
+                Ok(query) => {
+                    self.matches = find(&query, scope);
+                    self.query = Some(query);
+                }
+                Err(e) => self.error = Some(e),
+            }
+        }
+        self.computed_for = Some(key);
+    }
+
+    /// Like `refresh`, for the bytes of a document in the hex editor: the find text
+    /// is a `byte_pattern` and the matches are byte offsets.
+    pub fn refresh_bytes(&mut self, document_id: u64, revision: u64, bytes: &[u8]) {
+        let key = SearchKey {
+            document_id,
+            revision,
+            pattern: self.find_text.clone(),
+            options: self.options.clone(),
+            scope: None,
+        };
+        if self.computed_for.as_ref() == Some(&key) {
+            return;
+        }
+
+        self.matches.clear();
+        self.error = None;
+        self.query = None;
+        if !self.find_text.is_empty() {
+            match byte_pattern(&self.find_text) {
+                Ok(regex) => self.matches = regex.find_iter(bytes).map(|m| m.range()).collect(),
+                Err(e) => self.error = Some(e),
+            }
+        }
+        self.computed_for = Some(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pattern: &str, options: SearchOptions) -> SearchQuery {
+        SearchQuery::new(pattern, &options).unwrap()
+    }
+
+    fn regex() -> SearchOptions {
+        SearchOptions {
+            regex: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn find_all_in_text_and_scope() {
+        let text = "one two one two one";
+        let query = query("one", SearchOptions::default());
+        assert_eq!(query.find_all(text, None), vec![0..3, 8..11, 16..19]);
+        assert_eq!(query.find_all(text, Some(4..12)), vec![8..11]);
+        // A match running past the end of the scope is left out
+        assert_eq!(query.find_all(text, Some(0..10)), vec![0..3]);
+    }
+
+    #[test]
+    fn find_all_skips_empty_matches() {
+        assert_eq!(query("a*", regex()).find_all("baab", None), vec![1..3]);
+        assert!(query("^", regex()).find_all("one\ntwo", None).is_empty());
+        assert_eq!(query("é*", regex()).find_all("aéé", None), vec![1..5]);
+    }
+
+    #[test]
+    fn plain_text_is_not_a_pattern() {
+        let query = query("a.c", SearchOptions::default());
+        assert_eq!(query.find_all("abc a.c", None), vec![4..7]);
+        assert_eq!(query.replacement("abc a.c", 4..7, "$0"), "$0");
+    }
+
+    #[test]
+    fn regex_replacement_expands_captures() {
+        let text = "user@host";
+        let query = query(r"(\w+)@(?P<domain>\w+)", regex());
+        assert_eq!(query.find_all(text, None), vec![0..9]);
+        assert_eq!(query.replacement(text, 0..9, "$1 at ${domain}"), "user at host");
+        assert_eq!(query.replacement(text, 0..9, "[$0]"), "[user@host]");
+    }
+
+    #[test]
+    fn whole_word_and_case() {
+        let text = "Cat concat cat category";
+        let whole_word = SearchOptions {
+            whole_word: true,
+            ..Default::default()
+        };
+        assert_eq!(query("cat", whole_word.clone()).find_all(text, None), vec![11..14]);
+        let any_case = SearchOptions {
+            case_sensitive: false,
+            ..whole_word
+        };
+        assert_eq!(query("cat", any_case.clone()).find_all(text, None), vec![0..3, 11..14]);
+        assert_eq!(
+            query("CAT", SearchOptions { whole_word: false, ..any_case }).find_all(text, None),
+            vec![0..3, 7..10, 11..14, 15..18]
+        );
+    }
+
+    #[test]
+    fn empty_or_invalid_patterns_are_errors() {
+        assert!(SearchQuery::new("", &SearchOptions::default()).is_err());
+        assert!(SearchQuery::new("(", &regex()).is_err());
+        assert!(SearchQuery::new("(", &SearchOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn byte_patterns() {
+        let bytes = b"MZ\x90\x00PE\x00\x00\x89PNG\r\n";
+        let pattern = byte_pattern("4D 5A ?? 00").unwrap();
+        assert_eq!(pattern.find(bytes).map(|m| m.range()), Some(0..4));
+        let pattern = byte_pattern("89\"PNG\"0d0A").unwrap();
+        assert_eq!(pattern.find(bytes).map(|m| m.range()), Some(8..14));
+        // `??` matches any byte, line breaks included
+        assert_eq!(byte_pattern("?? 50").unwrap().find(b"\n\x50").map(|m| m.range()), Some(0..2));
+        assert!(byte_pattern("").is_err());
+        assert!(byte_pattern("4").is_err());
+        assert!(byte_pattern("zz").is_err());
+        assert!(byte_pattern("\"open").is_err());
+    }
+
+    #[test]
+    fn byte_regex_searches_bytes_that_are_not_text() {
+        let bytes = b"\xff\xfeKEY=1\x00key=2";
+        let options = SearchOptions {
+            case_sensitive: false,
+            ..Default::default()
+        };
+        let regex = byte_regex("key=", &options).unwrap();
+        let found: Vec<_> = regex.find_iter(bytes).map(|m| m.range()).collect();
+        assert_eq!(found, vec![2..6, 8..12]);
+    }
+
+    #[test]
+    fn replace_all_edits_apply_in_order() {
+        let mut text = "a=1, bé=22, c=333".to_string();
+        let swap = query(r"(\w+)=(\d+)", regex());
+        let matches = swap.find_all(&text, None);
+        let (edits, shift) = swap.replace_all_edits(&text, &matches, "$2:$1");
+        crate::undo::apply_all(&mut text, &edits);
+        assert_eq!(text, "1:a, 22:bé, 333:c");
+        assert_eq!(shift, 0);
+
+        let mut text = "xéx".to_string();
+        let widen = query("x", SearchOptions::default());
+        let matches = widen.find_all(&text, None);
+        let (edits, shift) = widen.replace_all_edits(&text, &matches, "yy");
+        crate::undo::apply_all(&mut text, &edits);
+        assert_eq!(text, "yyéyy");
+        assert_eq!(shift, 2);
+    }
+
+    #[test]
+    fn ordinal_of_selected_match() {
+        let matches = [0..3, 8..11, 16..19];
+        assert_eq!(match_ordinal(&matches, &(8..11)), Some(2));
+        assert_eq!(match_ordinal(&matches, &(8..10)), None);
+        assert_eq!(match_ordinal(&matches, &(4..7)), None);
+        assert_eq!(match_ordinal(&[], &(0..1)), None);
+    }
+
+    #[test]
+    fn patterns_that_stay_within_lines() {
+        for pattern in ["foo", r"^\w+ é$", r"\bbar\b", "[a-z]+", "a.b"] {
+            assert!(query(pattern, regex()).within_lines, "{}", pattern);
+        }
+        for pattern in [r"a\sb", "a\nb", "[^x]", "(?s)a.b", r"\Afoo", r"foo\z"] {
+            assert!(!query(pattern, regex()).within_lines, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn rope_search_matches_the_whole_text() {
+        // Several blocks long, with multi-byte characters around the block edges
+        let mut text = String::new();
+        let mut line = 0;
+        while text.len() < BLOCK_BYTES * 5 / 2 {
+            text.push_str(&format!("word{} é foo bar{}\n", line, line % 7));
+            line += 1;
+        }
+        text.push_str("last foo");
+        let rope = Rope::from_str(&text);
+        let middle = text.len() / 3..text.len() * 2 / 3;
+        let middle = next_char_boundary(&text, middle.start)..next_char_boundary(&text, middle.end);
+
+        for pattern in ["foo", r"^word1\d*", r"bar3$", r"\bword77\b", r"bar6\nword", r"\Aword0", "foo\\z"] {
+            let query = query(pattern, regex());
+            assert_eq!(query.find_all_rope(&rope, None), query.find_all(&text, None), "{}", pattern);
+            assert_eq!(
+                query.find_all_rope(&rope, Some(middle.clone())),
+                query.find_all(&text, Some(middle.clone())),
+                "{}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn refresh_limits_to_the_scope_in_characters() {
+        let mut state = SearchState {
+            find_text: "a".to_string(),
+            options: SearchOptions {
+                in_selection: true,
+                ..Default::default()
+            },
+            scope: Some((7, 1..4)),
+            ..Default::default()
+        };
+        let text = "éaéaéa";
+        state.refresh(7, 1, text);
+        assert_eq!(state.matches(), &[2..3, 5..6]);
+        // The scope belongs to another document
+        state.refresh(8, 1, text);
+        assert_eq!(state.matches(), &[2..3, 5..6, 8..9]);
+    }
+}
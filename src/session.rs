@@ -0,0 +1,75 @@
+use crate::document::{Document, Documents, Selection};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Key of the session in eframe's storage.
+const STORAGE_KEY: &str = "session";
+
+/// The tabs and window of the last run, reopened on the next start.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub documents: Vec<SessionDocument>,
+    /// Index of the active tab in `documents`.
+    pub active: usize,
+    pub maximized: bool,
+}
+
+/// One tab of a `Session`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionDocument {
+    /// Untitled documents are only kept when they have unsaved text.
+    pub path: Option<PathBuf>,
+    /// The `Document::snapshot` of a document with unsaved edits.
+    pub unsaved: Option<String>,
+    /// Whether the document was open in the hex editor.
+    pub hex: bool,
+    pub selection: Selection,
+    /// Offset of the text editor's scroll area, in points.
+    pub scroll: [f32; 2],
+    /// First byte shown by the read-only viewer.
+    pub viewer_top: usize,
+}
+
+impl Session {
+    /// Captures the open documents. The unsaved text of large-file mode documents is
+    /// only copied with `large_text`, since that can take a while.
+    pub fn capture(documents: &Documents, maximized: bool, large_text: bool) -> Self {
+        let mut session = Session {
+            maximized,
+            ..Default::default()
+        };
+        for (index, doc) in documents.iter().enumerate() {
+            let unsaved = Self::keeps_unsaved(doc, large_text).then(|| doc.snapshot().into_owned());
+            if doc.path.is_none() && unsaved.is_none() {
+                continue;
+            }
+            if index == documents.active_index() {
+                session.active = session.documents.len();
+            }
+            session.documents.push(SessionDocument {
+                path: doc.path.clone(),
+                unsaved,
+                hex: doc.is_hex(),
+                selection: doc.cursor,
+                scroll: doc.scroll_offset.into(),
+                viewer_top: doc.mapped.as_ref().map_or(0, |file| file.top),
+            });
+        }
+        session
+    }
+
+    /// Whether `capture` keeps the unsaved text of `doc`.
+    pub fn keeps_unsaved(doc: &Document, large_text: bool) -> bool {
+        doc.is_modified && (large_text || !doc.is_large())
+    }
+
+    pub fn load(storage: &dyn eframe::Storage) -> Option<Self> {
+        eframe::get_value(storage, STORAGE_KEY)
+    }
+
+    pub fn store(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, STORAGE_KEY, self);
+    }
+}
@@ -1,18 +1,46 @@
+use crate::file_operations::BackupPolicy;
+use crate::keybindings::Keybindings;
+use crate::text_format::{Indentation, LineEnding};
 use crate::theme::Theme;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     pub theme: Theme,
     pub font_family: String,
     pub font_size: f32,
+    /// Font files loaded by hand, offered next to the installed fonts.
+    pub font_files: Vec<PathBuf>,
     pub word_wrap: bool,
+    pub show_line_numbers: bool,
+    pub relative_line_numbers: bool,
     pub auto_save: bool,
+    pub auto_save_interval_secs: u64,
+    pub auto_save_idle_secs: u64,
+    pub backup_policy: BackupPolicy,
+    /// Line ending of files created in the editor; opened files keep their own.
+    pub default_line_ending: LineEnding,
     pub tab_size: usize,
+    /// Indent new files with spaces rather than tabs; opened files keep their own style.
+    pub insert_spaces: bool,
+    /// Enter keeps the indentation of the line before.
+    pub auto_indent: bool,
+    /// Enter indents a level more after an opening bracket, or a `:` in Python and YAML.
+    pub smart_indent: bool,
+    pub undo_depth: usize,
+    /// Files of at least this many megabytes open in large-file mode.
+    pub large_file_threshold_mb: u64,
+    /// Files of at least this many megabytes open in the read-only viewer.
+    pub viewer_threshold_mb: u64,
+    /// Reopen the documents and window of the last run on startup.
+    pub restore_session: bool,
+    /// Window size used when there is no session to restore it from.
     pub window_width: f32,
     pub window_height: f32,
     pub recent_files: Vec<PathBuf>,
+    pub keybindings: Keybindings,
 }
 
 impl Default for Settings {
@@ -21,12 +49,27 @@ impl Default for Settings {
             theme: Theme::default(),
             font_family: "Monospace".to_string(),
             font_size: 14.0,
+            font_files: Vec::new(),
             word_wrap: true,
+            show_line_numbers: true,
+            relative_line_numbers: false,
             auto_save: false,
+            auto_save_interval_secs: 30,
+            auto_save_idle_secs: 2,
+            backup_policy: BackupPolicy::None,
+            default_line_ending: LineEnding::native(),
             tab_size: 4,
+            insert_spaces: true,
+            auto_indent: true,
+            smart_indent: true,
+            undo_depth: 1000,
+            large_file_threshold_mb: 8,
+            viewer_threshold_mb: 256,
+            restore_session: true,
             window_width: 1200.0,
             window_height: 800.0,
             recent_files: Vec::new(),
+            keybindings: Keybindings::default(),
         }
     }
 }
@@ -56,6 +99,14 @@ impl Settings {
         }
     }
 
+    /// How new documents are indented.
+    pub fn indentation(&self) -> Indentation {
+        Indentation {
+            use_tabs: !self.insert_spaces,
+            width: self.tab_size.max(1),
+        }
+    }
+
     pub fn add_recent_file(&mut self, path: PathBuf) {
         // Remove if already exists
         self.recent_files.retain(|p| p != &path);
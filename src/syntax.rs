@@ -0,0 +1,375 @@
+use std::ops::Range;
+use std::path::Path;
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
+
+pub const PLAIN_TEXT: &str = "Plain Text";
+
+/// Buffers larger than this are shown without highlighting, parsing them would stall typing.
+const MAX_HIGHLIGHT_BYTES: usize = 4 * 1024 * 1024;
+
+/// Token categories that get their own color in `Theme`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Comment,
+    String,
+    Number,
+    Constant,
+    Keyword,
+    Type,
+    Function,
+}
+
+impl TokenKind {
+    pub const ALL: [TokenKind; 7] = [
+        TokenKind::Comment,
+        TokenKind::String,
+        TokenKind::Number,
+        TokenKind::Constant,
+        TokenKind::Keyword,
+        TokenKind::Type,
+        TokenKind::Function,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TokenKind::Comment => "Comments",
+            TokenKind::String => "Strings",
+            TokenKind::Number => "Numbers",
+            TokenKind::Constant => "Constants",
+            TokenKind::Keyword => "Keywords",
+            TokenKind::Type => "Types",
+            TokenKind::Function => "Functions",
+        }
+    }
+}
+
+/// A byte range of the text to paint in the color of `kind`.
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub range: Range<usize>,
+    pub kind: TokenKind,
+}
+
+/// Scope prefixes mapped to token kinds, most specific first. `None` keeps the plain text color.
+const SCOPE_KINDS: &[(&str, Option<TokenKind>)] = &[
+    ("comment", Some(TokenKind::Comment)),
+    ("string", Some(TokenKind::String)),
+    ("markup.raw", Some(TokenKind::String)),
+    ("constant.numeric", Some(TokenKind::Number)),
+    ("constant", Some(TokenKind::Constant)),
+    ("support.constant", Some(TokenKind::Constant)),
+    ("keyword.operator", None),
+    ("keyword", Some(TokenKind::Keyword)),
+    ("storage", Some(TokenKind::Keyword)),
+    ("variable.language", Some(TokenKind::Keyword)),
+    ("entity.name.tag", Some(TokenKind::Keyword)),
+    ("entity.name.section", Some(TokenKind::Keyword)),
+    ("markup.heading", Some(TokenKind::Keyword)),
+    ("entity.name.function", Some(TokenKind::Function)),
+    ("support.function", Some(TokenKind::Function)),
+    ("variable.function", Some(TokenKind::Function)),
+    ("markup.underline.link", Some(TokenKind::Function)),
+    ("entity.other.attribute-name", Some(TokenKind::Type)),
+    ("entity.other.inherited-class", Some(TokenKind::Type)),
+    ("entity.name", Some(TokenKind::Type)),
+    ("support.type", Some(TokenKind::Type)),
+    ("support.class", Some(TokenKind::Type)),
+];
+
+/// Parses buffers with the bundled Sublime Text grammars. The parser state at the
+/// start of every line is kept, so after an edit only the lines from the change
+/// onwards are parsed again, and parsing stops early once it is back in step with
+/// the previous result.
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    scope_kinds: Vec<(Scope, Option<TokenKind>)>,
+    cache: Option<Cache>,
+}
+
+struct Cache {
+    language: String,
+    text: String,
+    lines: Vec<Line>,
+}
+
+struct Line {
+    start: usize,
+    /// Parser state before the first character of the line.
+    state: ParseState,
+    stack: ScopeStack,
+    tokens: Vec<Token>,
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Highlighter {
+    pub fn new() -> Self {
+        let scope_kinds = SCOPE_KINDS
+            .iter()
+            .filter_map(|(name, kind)| Scope::new(name).ok().map(|scope| (scope, *kind)))
+            .collect();
+        Self {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            scope_kinds,
+            cache: None,
+        }
+    }
+
+    /// Names of the languages that can be picked by hand, sorted.
+    pub fn languages(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .syntaxes
+            .syntaxes()
+            .iter()
+            .filter(|s| !s.hidden)
+            .map(|s| s.name.as_str())
+            .collect();
+        names.sort_by_key(|name| name.to_lowercase());
+        names.dedup();
+        names
+    }
+
+    /// Picks a language from the file extension, falling back to the first line (e.g. a shebang).
+    pub fn detect(&self, path: Option<&Path>, text: &str) -> &str {
+        let by_extension = path
+            .and_then(|p| p.extension())
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.syntaxes.find_syntax_by_extension(ext));
+        let by_first_line = || {
+            let first_line = text.lines().next().unwrap_or("");
+            self.syntaxes.find_syntax_by_first_line(first_line)
+        };
+        by_extension
+            .or_else(by_first_line)
+            .map_or(PLAIN_TEXT, |s| s.name.as_str())
+    }
+
+    /// Tokens of `text` in `language`, sorted and non-overlapping.
+    pub fn highlight(&mut self, language: &str, text: &str) -> Vec<Token> {
+        if language == PLAIN_TEXT || text.len() > MAX_HIGHLIGHT_BYTES {
+            return Vec::new();
+        }
+        let Some(syntax) = self.syntaxes.find_syntax_by_name(language) else {
+            return Vec::new();
+        };
+
+        let cache = match self.cache.take() {
+            Some(cache) if cache.language == language => {
+                if cache.text == text {
+                    self.cache = Some(cache);
+                    return self.flatten();
+                }
+                Some(cache)
+            }
+            _ => None,
+        };
+        let lines = self.parse(syntax, cache, text);
+        self.cache = Some(Cache {
+            language: language.to_string(),
+            text: text.to_string(),
+            lines,
+        });
+        self.flatten()
+    }
+
+    fn flatten(&self) -> Vec<Token> {
+        self.cache
+            .iter()
+            .flat_map(|cache| &cache.lines)
+            .flat_map(|line| line.tokens.iter().cloned())
+            .collect()
+    }
+
+    /// Parses `text`, reusing the lines of `previous` before and after the edited region.
+    fn parse(&self, syntax: &SyntaxReference, previous: Option<Cache>, text: &str) -> Vec<Line> {
+        let (mut lines, mut tail, prefix, suffix, old_len) = match previous {
+            Some(Cache { text: old, mut lines, .. }) => {
+                let prefix = common_prefix(&old, text);
+                let suffix = common_suffix(&old, text).min(old.len().min(text.len()) - prefix);
+                // The line containing the first difference is parsed again
+                let keep = lines.partition_point(|l| l.start <= prefix).saturating_sub(1);
+                let tail = lines.split_off(keep);
+                (lines, tail, prefix, suffix, old.len())
+            }
+            None => (Vec::new(), Vec::new(), 0, 0, 0),
+        };
+
+        let (mut state, mut stack, mut start) = match tail.first() {
+            Some(line) => (line.state.clone(), line.stack.clone(), line.start),
+            None => (ParseState::new(syntax), ScopeStack::new(), 0),
+        };
+        // Old lines that start inside the unchanged suffix, so they may be reused after the edit
+        let reusable_from = tail.partition_point(|l| l.start < old_len - suffix);
+        let mut reusable = tail.drain(..).skip(reusable_from).peekable();
+
+        while start < text.len() {
+            if start >= prefix && start >= text.len() - suffix {
+                let old_start = start + old_len - text.len();
+                while reusable.peek().is_some_and(|l| l.start < old_start) {
+                    reusable.next();
+                }
+                if reusable
+                    .peek()
+                    .is_some_and(|l| l.start == old_start && l.state == state && l.stack == stack)
+                {
+                    // Back in step with the previous parse: the rest only moves by the length change
+                    let shift = |offset: usize| offset + text.len() - old_len;
+                    lines.extend(reusable.map(|mut line| {
+                        line.start = shift(line.start);
+                        for token in &mut line.tokens {
+                            token.range = shift(token.range.start)..shift(token.range.end);
+                        }
+                        line
+                    }));
+                    return lines;
+                }
+            }
+
+            let end = text[start..].find('\n').map_or(text.len(), |i| start + i + 1);
+            let mut line = Line {
+                start,
+                state: state.clone(),
+                stack: stack.clone(),
+                tokens: Vec::new(),
+            };
+            let Ok(ops) = state.parse_line(&text[start..end], &self.syntaxes) else {
+                // A broken grammar leaves the rest of the buffer plain
+                lines.push(line);
+                return lines;
+            };
+            let mut pos = 0;
+            for (offset, op) in ops {
+                self.push_token(&mut line.tokens, &stack, start + pos..start + offset);
+                pos = offset;
+                if stack.apply(&op).is_err() {
+                    break;
+                }
+            }
+            self.push_token(&mut line.tokens, &stack, start + pos..end);
+            lines.push(line);
+            start = end;
+        }
+        lines
+    }
+
+    fn push_token(&self, tokens: &mut Vec<Token>, stack: &ScopeStack, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let Some(kind) = self.kind_of(stack) else {
+            return;
+        };
+        match tokens.last_mut() {
+            Some(last) if last.kind == kind && last.range.end == range.start => last.range.end = range.end,
+            _ => tokens.push(Token { range, kind }),
+        }
+    }
+
+    /// The kind of the innermost scope that has one.
+    fn kind_of(&self, stack: &ScopeStack) -> Option<TokenKind> {
+        stack.as_slice().iter().rev().find_map(|scope| {
+            self.scope_kinds
+                .iter()
+                .find(|(prefix, _)| prefix.is_prefix_of(*scope))
+                .map(|(_, kind)| *kind)
+        })?
+    }
+}
+
+fn common_prefix(a: &str, b: &str) -> usize {
+    a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count()
+}
+
+fn common_suffix(a: &str, b: &str) -> usize {
+    a.bytes().rev().zip(b.bytes().rev()).take_while(|(x, y)| x == y).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(tokens: &[Token]) -> Vec<(Range<usize>, TokenKind)> {
+        tokens.iter().map(|t| (t.range.clone(), t.kind)).collect()
+    }
+
+    fn rust_lines(count: usize) -> String {
+        (0..count).map(|i| format!("let x{} = \"s\"; // n\n", i)).collect()
+    }
+
+    /// Highlights `before`, then `after` with the same highlighter, and checks the
+    /// result against a parse of `after` from scratch.
+    fn assert_incremental(before: &str, after: &str) {
+        let mut highlighter = Highlighter::new();
+        highlighter.highlight("Rust", before);
+        let incremental = highlighter.highlight("Rust", after);
+        let full = Highlighter::new().highlight("Rust", after);
+        assert_eq!(spans(&incremental), spans(&full));
+    }
+
+    /// Replaces the tokens of the last cached line with a marker a reparse would not produce.
+    fn mark_last_line(highlighter: &mut Highlighter) -> usize {
+        let line = highlighter.cache.as_mut().unwrap().lines.last_mut().unwrap();
+        line.tokens = vec![Token {
+            range: line.start..line.start + 1,
+            kind: TokenKind::Function,
+        }];
+        line.start
+    }
+
+    #[test]
+    fn edits_match_a_full_parse() {
+        let text = rust_lines(50);
+        let middle = text.find("x25").unwrap();
+        assert_incremental(&text, &format!("{}yy{}", &text[..middle], &text[middle..]));
+        assert_incremental(&text, &text[text.find("let x1 ").unwrap()..]);
+        assert_incremental(&text, &format!("fn main() {{}}\n{}", text));
+        assert_incremental(&text, &format!("{}/* open", text));
+        assert_incremental(&text, &format!("{}/*{}", &text[..middle], &text[middle..]));
+        assert_incremental(&text, "");
+    }
+
+    #[test]
+    fn reparse_stops_once_the_state_converges() {
+        let text = rust_lines(200);
+        let mut highlighter = Highlighter::new();
+        highlighter.highlight("Rust", &text);
+        let marker = mark_last_line(&mut highlighter);
+
+        // One byte longer on the second line; the lines after it are moved, not parsed
+        let edited = text.replacen("x1 ", "x1a ", 1);
+        let tokens = highlighter.highlight("Rust", &edited);
+        let last = tokens.last().unwrap();
+        assert_eq!(last.range, marker + 1..marker + 2);
+        assert_eq!(last.kind, TokenKind::Function);
+    }
+
+    #[test]
+    fn unclosed_comment_reparses_to_the_end() {
+        let text = rust_lines(200);
+        let mut highlighter = Highlighter::new();
+        highlighter.highlight("Rust", &text);
+        let marker = mark_last_line(&mut highlighter);
+
+        let edited = text.replacen("let x1 ", "/* let x1 ", 1);
+        let tokens = highlighter.highlight("Rust", &edited);
+        let last = tokens.last().unwrap();
+        assert_eq!(last.range.end, edited.len());
+        assert_eq!(last.kind, TokenKind::Comment);
+        assert!(!tokens.iter().any(|t| t.range.start == marker + 3 && t.kind == TokenKind::Function));
+    }
+
+    #[test]
+    fn unchanged_text_and_languages_are_cached_separately() {
+        let text = rust_lines(3);
+        let mut highlighter = Highlighter::new();
+        let first = highlighter.highlight("Rust", &text);
+        assert_eq!(spans(&highlighter.highlight("Rust", &text)), spans(&first));
+        assert!(highlighter.highlight(PLAIN_TEXT, &text).is_empty());
+        let python = highlighter.highlight("Python", "# note\n");
+        assert_eq!(spans(&python), vec![(0..7, TokenKind::Comment)]);
+    }
+}
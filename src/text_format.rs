@@ -0,0 +1,590 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The character set of a file, plus whether it starts with a byte order mark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "StoredEncoding", try_from = "StoredEncoding")]
+pub struct Encoding {
+    charset: &'static encoding_rs::Encoding,
+    pub bom: bool,
+}
+
+/// How an `Encoding` is written to the recovery journal: by the charset's name.
+#[derive(Serialize, Deserialize)]
+struct StoredEncoding {
+    charset: String,
+    bom: bool,
+}
+
+impl From<Encoding> for StoredEncoding {
+    fn from(encoding: Encoding) -> Self {
+        Self {
+            charset: encoding.charset.name().to_string(),
+            bom: encoding.bom,
+        }
+    }
+}
+
+impl TryFrom<StoredEncoding> for Encoding {
+    type Error = String;
+
+    fn try_from(stored: StoredEncoding) -> Result<Self, String> {
+        Encoding::for_label(&stored.charset, stored.bom)
+            .ok_or_else(|| format!("unknown charset {}", stored.charset))
+    }
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Self::utf8()
+    }
+}
+
+/// Legacy character sets offered in menus, with the scripts they are used for.
+const LEGACY: &[(&str, &str)] = &[
+    ("windows-1252", "Western"),
+    ("ISO-8859-15", "Western"),
+    ("windows-1250", "Central European"),
+    ("ISO-8859-2", "Central European"),
+    ("windows-1257", "Baltic"),
+    ("ISO-8859-4", "Baltic"),
+    ("windows-1251", "Cyrillic"),
+    ("KOI8-R", "Cyrillic"),
+    ("KOI8-U", "Cyrillic"),
+    ("ISO-8859-5", "Cyrillic"),
+    ("windows-1253", "Greek"),
+    ("ISO-8859-7", "Greek"),
+    ("windows-1254", "Turkish"),
+    ("windows-1255", "Hebrew"),
+    ("ISO-8859-8", "Hebrew"),
+    ("windows-1256", "Arabic"),
+    ("ISO-8859-6", "Arabic"),
+    ("windows-874", "Thai"),
+    ("windows-1258", "Vietnamese"),
+    ("Shift_JIS", "Japanese"),
+    ("EUC-JP", "Japanese"),
+    ("ISO-2022-JP", "Japanese"),
+    ("GBK", "Simplified Chinese"),
+    ("gb18030", "Simplified Chinese"),
+    ("Big5", "Traditional Chinese"),
+    ("EUC-KR", "Korean"),
+];
+
+impl Encoding {
+    pub fn utf8() -> Self {
+        Self {
+            charset: encoding_rs::UTF_8,
+            bom: false,
+        }
+    }
+
+    /// The encoding a label like `latin1` or `UTF-16LE` stands for.
+    pub fn for_label(label: &str, bom: bool) -> Option<Self> {
+        encoding_rs::Encoding::for_label(label.as_bytes()).map(|charset| Self { charset, bom })
+    }
+
+    /// Every encoding that can be picked by hand, Unicode ones first.
+    pub fn all() -> Vec<Encoding> {
+        let unicode = [
+            (encoding_rs::UTF_8, false),
+            (encoding_rs::UTF_8, true),
+            (encoding_rs::UTF_16LE, true),
+            (encoding_rs::UTF_16BE, true),
+        ];
+        let legacy = LEGACY
+            .iter()
+            .filter_map(|(name, _)| encoding_rs::Encoding::for_label(name.as_bytes()))
+            .map(|charset| (charset, false));
+        unicode
+            .into_iter()
+            .chain(legacy)
+            .map(|(charset, bom)| Encoding { charset, bom })
+            .collect()
+    }
+
+    pub fn label(self) -> String {
+        let name = self.charset.name();
+        match (name, self.bom) {
+            ("UTF-8", false) => "UTF-8".to_string(),
+            ("UTF-8", true) => "UTF-8 with BOM".to_string(),
+            ("UTF-16LE", true) => "UTF-16 LE".to_string(),
+            ("UTF-16BE", true) => "UTF-16 BE".to_string(),
+            ("UTF-16LE", false) => "UTF-16 LE without BOM".to_string(),
+            ("UTF-16BE", false) => "UTF-16 BE without BOM".to_string(),
+            _ => match LEGACY.iter().find(|(legacy, _)| *legacy == name) {
+                Some((_, script)) => format!("{} ({})", script, name),
+                None => name.to_string(),
+            },
+        }
+    }
+
+    /// Decodes `bytes`, recognizing the encoding from its byte order mark, or else
+    /// guessing it from the bytes themselves.
+    fn decode(bytes: &[u8]) -> Result<(String, Encoding), String> {
+        if let Some((charset, bom_length)) = encoding_rs::Encoding::for_bom(bytes) {
+            let encoding = Encoding { charset, bom: true };
+            return Ok((encoding.decode_without_bom(&bytes[bom_length..]), encoding));
+        }
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            if !text.contains('\0') {
+                return Ok((text.to_string(), Encoding::utf8()));
+            }
+        }
+        if let Some(charset) = utf16_without_bom(bytes) {
+            let encoding = Encoding { charset, bom: false };
+            return Ok((encoding.decode_without_bom(bytes), encoding));
+        }
+        if bytes.contains(&0) {
+            return Err("the file looks like binary data, not text".to_string());
+        }
+
+        let mut detector = chardetng::EncodingDetector::new();
+        detector.feed(bytes, true);
+        let encoding = Encoding {
+            charset: detector.guess(None, true),
+            bom: false,
+        };
+        Ok((encoding.decode_without_bom(bytes), encoding))
+    }
+
+    /// Decodes `bytes` as this encoding whatever they look like, skipping a matching
+    /// byte order mark. Malformed sequences become U+FFFD.
+    fn decode_forced(self, bytes: &[u8]) -> String {
+        match encoding_rs::Encoding::for_bom(bytes) {
+            Some((charset, bom_length)) if charset == self.charset => {
+                self.decode_without_bom(&bytes[bom_length..])
+            }
+            _ => self.decode_without_bom(bytes),
+        }
+    }
+
+    fn decode_without_bom(self, bytes: &[u8]) -> String {
+        self.charset.decode_without_bom_handling(bytes).0.into_owned()
+    }
+
+    /// Fails with a message naming the first character the encoding has no bytes for.
+    fn encode(self, text: &str) -> Result<Vec<u8>, String> {
+        let bom: &[u8] = match (self.bom, self.charset.name()) {
+            (false, _) => &[],
+            (true, "UTF-16LE") => &[0xFF, 0xFE],
+            (true, "UTF-16BE") => &[0xFE, 0xFF],
+            (true, _) => &[0xEF, 0xBB, 0xBF],
+        };
+        // encoding_rs only writes the encodings of the web, which leaves out UTF-16
+        if self.charset == encoding_rs::UTF_16LE {
+            return Ok(bom.iter().copied().chain(text.encode_utf16().flat_map(u16::to_le_bytes)).collect());
+        }
+        if self.charset == encoding_rs::UTF_16BE {
+            return Ok(bom.iter().copied().chain(text.encode_utf16().flat_map(u16::to_be_bytes)).collect());
+        }
+        if self.charset == encoding_rs::UTF_8 {
+            return Ok([bom, text.as_bytes()].concat());
+        }
+
+        let mut encoder = self.charset.new_encoder();
+        let capacity = encoder
+            .max_buffer_length_from_utf8_without_replacement(text.len())
+            .ok_or("the file is too large to convert")?;
+        let mut bytes = Vec::with_capacity(capacity);
+        match encoder.encode_from_utf8_to_vec_without_replacement(text, &mut bytes, true) {
+            (encoding_rs::EncoderResult::Unmappable(c), _) => {
+                Err(format!("\"{}\" (U+{:04X}) can't be written in {}", c, c as u32, self.label()))
+            }
+            _ => Ok(bytes),
+        }
+    }
+}
+
+/// Recognizes UTF-16 without a byte order mark from the zero high bytes of Latin text.
+fn utf16_without_bom(bytes: &[u8]) -> Option<&'static encoding_rs::Encoding> {
+    if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let pairs = bytes.len() / 2;
+    let zeros_at = |parity: usize| bytes.iter().skip(parity).step_by(2).filter(|&&b| b == 0).count();
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    if odd * 10 >= pairs * 4 && even * 10 < pairs {
+        Some(encoding_rs::UTF_16LE)
+    } else if even * 10 >= pairs * 4 && odd * 10 < pairs {
+        Some(encoding_rs::UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Line break style written to disk. Documents always hold `\n` in memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl LineEnding {
+    pub const ALL: [LineEnding; 3] = [LineEnding::Lf, LineEnding::Crlf, LineEnding::Cr];
+
+    /// The usual line ending of the platform the editor runs on.
+    pub fn native() -> Self {
+        if cfg!(windows) {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+            LineEnding::Cr => "CR",
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /// The most common line break in `text`, if it has any, and whether other kinds occur too.
+    fn detect(text: &str) -> (Option<LineEnding>, bool) {
+        let bytes = text.as_bytes();
+        let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+        for (i, &byte) in bytes.iter().enumerate() {
+            match byte {
+                b'\n' if i > 0 && bytes[i - 1] == b'\r' => crlf += 1,
+                b'\n' => lf += 1,
+                b'\r' if bytes.get(i + 1) != Some(&b'\n') => cr += 1,
+                _ => {}
+            }
+        }
+        let found = [(crlf, LineEnding::Crlf), (cr, LineEnding::Cr), (lf, LineEnding::Lf)]
+            .into_iter()
+            .filter(|(count, _)| *count > 0);
+        let mixed = found.clone().count() > 1;
+        (found.max_by_key(|(count, _)| *count).map(|(_, ending)| ending), mixed)
+    }
+}
+
+/// How a document's text is stored on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileFormat {
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+}
+
+/// The text of a file with `\n` line breaks, plus the format needed to write it back.
+pub struct Decoded {
+    pub text: String,
+    pub format: FileFormat,
+    /// Set when the file used more than one kind of line break; `format` has the most common one.
+    pub mixed_line_endings: bool,
+}
+
+impl FileFormat {
+    pub fn decode(bytes: &[u8]) -> Result<Decoded, String> {
+        let (text, encoding) = Encoding::decode(bytes)?;
+        Ok(Self::normalize(text, encoding))
+    }
+
+    /// Like `decode`, but reads the bytes as `encoding` instead of recognizing it.
+    pub fn decode_as(bytes: &[u8], encoding: Encoding) -> Decoded {
+        let text = encoding.decode_forced(bytes);
+        let bom = encoding_rs::Encoding::for_bom(bytes).is_some_and(|(charset, _)| charset == encoding.charset);
+        Self::normalize(text, Encoding { bom, ..encoding })
+    }
+
+    fn normalize(text: String, encoding: Encoding) -> Decoded {
+        let (line_ending, mixed_line_endings) = LineEnding::detect(&text);
+        let text = if text.contains('\r') {
+            text.replace("\r\n", "\n").replace('\r', "\n")
+        } else {
+            text
+        };
+        Decoded {
+            text,
+            format: FileFormat {
+                encoding,
+                line_ending: line_ending.unwrap_or_default(),
+            },
+            mixed_line_endings,
+        }
+    }
+
+    /// The bytes to write for `text`, or why it can't be written in this format.
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, String> {
+        match self.line_ending {
+            LineEnding::Lf => self.encoding.encode(text),
+            ending => self.encoding.encode(&text.replace('\n', ending.as_str())),
+        }
+    }
+}
+
+/// Whether a document is indented with tabs or spaces, and how wide a level is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Indentation {
+    pub use_tabs: bool,
+    pub width: usize,
+}
+
+impl Indentation {
+    pub const WIDTHS: [usize; 4] = [2, 3, 4, 8];
+
+    pub fn spaces(width: usize) -> Self {
+        Self {
+            use_tabs: false,
+            width: width.max(1),
+        }
+    }
+
+    /// One level of indentation.
+    pub fn unit(&self) -> String {
+        if self.use_tabs {
+            "\t".to_string()
+        } else {
+            " ".repeat(self.width)
+        }
+    }
+
+    pub fn label(&self) -> String {
+        if self.use_tabs {
+            format!("Tabs: {}", self.width)
+        } else {
+            format!("Spaces: {}", self.width)
+        }
+    }
+
+    /// Guesses the indentation of `text` from its leading whitespace, using
+    /// `fallback` for whatever the text doesn't tell.
+    pub fn detect(text: &str, fallback: Indentation) -> Indentation {
+        let mut tab_lines = 0;
+        let mut space_lines = 0;
+        let mut steps: HashMap<usize, usize> = HashMap::new();
+        let mut previous = 0;
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            if line.starts_with('\t') {
+                tab_lines += 1;
+                continue;
+            }
+            let indent = line.len() - line.trim_start_matches(' ').len();
+            if indent > 0 {
+                space_lines += 1;
+            }
+            let step = indent.abs_diff(previous);
+            if (2..=8).contains(&step) {
+                *steps.entry(step).or_default() += 1;
+            }
+            previous = indent;
+        }
+
+        if tab_lines == 0 && space_lines == 0 {
+            return fallback;
+        }
+        if tab_lines > space_lines {
+            return Indentation {
+                use_tabs: true,
+                width: fallback.width,
+            };
+        }
+        let width = steps
+            .into_iter()
+            .max_by_key(|&(step, count)| (count, std::cmp::Reverse(step)))
+            .map_or(fallback.width, |(step, _)| step);
+        Indentation::spaces(width)
+    }
+
+    /// Rewrites the leading whitespace of every line in this style, keeping its visual width.
+    pub fn reindent(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            let body = line.trim_start_matches([' ', '\t']);
+            let leading = &line[..line.len() - body.len()];
+            let columns = leading.chars().fold(0, |column, c| match c {
+                '\t' => (column / self.width + 1) * self.width,
+                _ => column + 1,
+            });
+            if self.use_tabs {
+                result.extend(std::iter::repeat_n('\t', columns / self.width));
+                result.extend(std::iter::repeat_n(' ', columns % self.width));
+            } else {
+                result.extend(std::iter::repeat_n(' ', columns));
+            }
+            result.push_str(body);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoding(label: &str, bom: bool) -> Encoding {
+        Encoding::for_label(label, bom).unwrap()
+    }
+
+    fn format(encoding: Encoding, line_ending: LineEnding) -> FileFormat {
+        FileFormat { encoding, line_ending }
+    }
+
+    /// Encodes `text` in `format` and reads it back as the same encoding.
+    fn round_trip(text: &str, format: FileFormat) {
+        let bytes = format.encode(text).unwrap();
+        let decoded = FileFormat::decode_as(&bytes, format.encoding);
+        assert_eq!(decoded.text, text, "{}", format.encoding.label());
+        assert_eq!(decoded.format, format, "{}", format.encoding.label());
+    }
+
+    #[test]
+    fn every_encoding_round_trips_ascii() {
+        for encoding in Encoding::all() {
+            for line_ending in LineEnding::ALL {
+                round_trip("Line one\nLine two\n", format(encoding, line_ending));
+            }
+        }
+    }
+
+    #[test]
+    fn encodings_round_trip_their_scripts() {
+        let samples = [
+            ("UTF-8", "naïve 日本語 😀"),
+            ("UTF-16LE", "naïve 日本語 😀"),
+            ("UTF-16BE", "naïve 日本語 😀"),
+            ("windows-1252", "café déjà €"),
+            ("ISO-8859-2", "zażółć"),
+            ("windows-1251", "привет"),
+            ("KOI8-R", "привет"),
+            ("windows-1253", "Ελληνικά"),
+            ("Shift_JIS", "日本語のテキスト"),
+            ("EUC-JP", "日本語のテキスト"),
+            ("GBK", "中文文本"),
+            ("Big5", "中文文本"),
+            ("EUC-KR", "한국어"),
+        ];
+        for (label, text) in samples {
+            round_trip(text, format(encoding(label, false), LineEnding::Lf));
+        }
+    }
+
+    #[test]
+    fn byte_order_marks_are_recognized_and_written_back() {
+        for (label, bom) in [
+            ("UTF-8", &[0xEF, 0xBB, 0xBF][..]),
+            ("UTF-16LE", &[0xFF, 0xFE][..]),
+            ("UTF-16BE", &[0xFE, 0xFF][..]),
+        ] {
+            let format = format(encoding(label, true), LineEnding::Lf);
+            let bytes = format.encode("hi\n").unwrap();
+            assert!(bytes.starts_with(bom), "{}", label);
+            let decoded = FileFormat::decode(&bytes).unwrap();
+            assert_eq!(decoded.text, "hi\n");
+            assert_eq!(decoded.format, format);
+        }
+    }
+
+    #[test]
+    fn utf16_is_written_by_hand() {
+        let le = encoding("UTF-16LE", true).encode("A😀").unwrap();
+        assert_eq!(le, [0xFF, 0xFE, 0x41, 0x00, 0x3D, 0xD8, 0x00, 0xDE]);
+        let be = encoding("UTF-16BE", false).encode("A😀").unwrap();
+        assert_eq!(be, [0x00, 0x41, 0xD8, 0x3D, 0xDE, 0x00]);
+    }
+
+    #[test]
+    fn utf16_without_bom_is_recognized() {
+        for label in ["UTF-16LE", "UTF-16BE"] {
+            let plain = encoding(label, false);
+            let bytes = plain.encode("plain old text\n").unwrap();
+            let decoded = FileFormat::decode(&bytes).unwrap();
+            assert_eq!(decoded.text, "plain old text\n");
+            assert_eq!(decoded.format.encoding, plain);
+        }
+        assert_eq!(utf16_without_bom(b"a\0b\0c"), None);
+        assert_eq!(utf16_without_bom(b"ab"), None);
+    }
+
+    #[test]
+    fn nul_bytes_mean_binary() {
+        assert!(FileFormat::decode(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0").is_err());
+        assert!(FileFormat::decode(b"a\0b").is_err());
+        // Valid UTF-8 with a NUL, but an odd length rules out UTF-16
+        assert!(FileFormat::decode("tab\0le".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn legacy_encodings_are_guessed() {
+        let cases = [
+            ("windows-1252", "Le café est très chaud à l'été, déjà prêt pour la fête."),
+            ("Shift_JIS", "日本語のテキストファイルを開きます。これはテストです。"),
+            ("windows-1251", "Это обычный текстовый файл на русском языке."),
+        ];
+        for (label, text) in cases {
+            let expected = encoding(label, false);
+            let bytes = expected.encode(text).unwrap();
+            let decoded = FileFormat::decode(&bytes).unwrap();
+            assert_eq!(decoded.format.encoding, expected, "{}", label);
+            assert_eq!(decoded.text, text);
+        }
+    }
+
+    #[test]
+    fn unmappable_characters_are_named() {
+        let error = encoding("windows-1252", false).encode("a日").unwrap_err();
+        assert!(error.contains("U+65E5"), "{}", error);
+    }
+
+    #[test]
+    fn forced_decoding_keeps_only_a_matching_bom() {
+        let utf8 = encoding("UTF-8", false);
+        let decoded = FileFormat::decode_as(b"\xEF\xBB\xBFhi", utf8);
+        assert_eq!(decoded.text, "hi");
+        assert!(decoded.format.encoding.bom);
+
+        let latin = encoding("windows-1252", false);
+        let decoded = FileFormat::decode_as(b"caf\xC3\xA9", latin);
+        assert_eq!(decoded.text, "cafÃ©");
+        assert!(!decoded.format.encoding.bom);
+    }
+
+    #[test]
+    fn format_round_trips_through_json() {
+        let format = format(encoding("Shift_JIS", false), LineEnding::Crlf);
+        let json = serde_json::to_string(&format).unwrap();
+        assert_eq!(serde_json::from_str::<FileFormat>(&json).unwrap(), format);
+        assert_eq!(serde_json::from_str::<FileFormat>("{}").unwrap(), FileFormat::default());
+    }
+
+    #[test]
+    fn line_endings_are_detected() {
+        assert_eq!(LineEnding::detect("a\nb\n"), (Some(LineEnding::Lf), false));
+        assert_eq!(LineEnding::detect("a\r\nb\r\n"), (Some(LineEnding::Crlf), false));
+        assert_eq!(LineEnding::detect("a\rb\r"), (Some(LineEnding::Cr), false));
+        assert_eq!(LineEnding::detect("a\r\nb\nc\r\n"), (Some(LineEnding::Crlf), true));
+        assert_eq!(LineEnding::detect("a\rb\rc\n"), (Some(LineEnding::Cr), true));
+        assert_eq!(LineEnding::detect("no break"), (None, false));
+    }
+
+    #[test]
+    fn line_endings_are_normalized_and_written_back() {
+        for ending in LineEnding::ALL {
+            let bytes = format!("one{0}two{0}", ending.as_str());
+            let decoded = FileFormat::decode(bytes.as_bytes()).unwrap();
+            assert_eq!(decoded.text, "one\ntwo\n");
+            assert_eq!(decoded.format.line_ending, ending);
+            assert!(!decoded.mixed_line_endings);
+            assert_eq!(decoded.format.encode(&decoded.text).unwrap(), bytes.as_bytes());
+        }
+
+        let decoded = FileFormat::decode(b"a\r\nb\rc\nd\r\n").unwrap();
+        assert_eq!(decoded.text, "a\nb\nc\nd\n");
+        assert_eq!(decoded.format.line_ending, LineEnding::Crlf);
+        assert!(decoded.mixed_line_endings);
+        // No line break at all falls back to LF
+        assert_eq!(FileFormat::decode(b"one line").unwrap().format.line_ending, LineEnding::Lf);
+    }
+}
@@ -1,139 +1,327 @@
-use eframe::egui::{Color32, FontFamily, FontId};
+use crate::syntax::TokenKind;
+use eframe::egui::Color32;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Theme {
-    pub background_color: [u8; 3],
-    pub text_color: [u8; 3],
-    pub line_number_color: [u8; 3],
-    pub selection_color: [u8; 3],
-    pub cursor_color: [u8; 3],
-    pub border_color: [u8; 3],
-    pub menu_bg_color: [u8; 3],
-    pub button_color: [u8; 3],
+    pub background_color: ThemeColor,
+    pub text_color: ThemeColor,
+    pub line_number_color: ThemeColor,
+    pub selection_color: ThemeColor,
+    pub cursor_color: ThemeColor,
+    pub border_color: ThemeColor,
+    pub menu_bg_color: ThemeColor,
+    pub button_color: ThemeColor,
+    pub comment_color: ThemeColor,
+    pub string_color: ThemeColor,
+    pub number_color: ThemeColor,
+    pub constant_color: ThemeColor,
+    pub keyword_color: ThemeColor,
+    pub type_color: ThemeColor,
+    pub function_color: ThemeColor,
+    /// Text of menus, dialogs and the status bar; `None` picks light or dark to stand
+    /// out against `menu_bg_color`.
+    pub ui_text_color: Option<ThemeColor>,
 }
 
 impl Default for Theme {
     fn default() -> Self {
         // Renaissance-inspired color palette with warm, elegant tones
         Self {
-            background_color: [245, 240, 230], // Warm parchment
-            text_color: [40, 30, 20],          // Dark brown
-            line_number_color: [120, 100, 80], // Muted bronze
-            selection_color: [180, 150, 120],  // Warm tan
-            cursor_color: [80, 50, 30],        // Dark wood
-            border_color: [160, 130, 100],     // Antique gold
-            menu_bg_color: [235, 225, 210],    // Light parchment
-            button_color: [200, 170, 140],     // Aged paper
+            background_color: ThemeColor::rgb(245, 240, 230), // Warm parchment
+            text_color: ThemeColor::rgb(40, 30, 20),          // Dark brown
+            line_number_color: ThemeColor::rgb(120, 100, 80), // Muted bronze
+            selection_color: ThemeColor::rgb(180, 150, 120),  // Warm tan
+            cursor_color: ThemeColor::rgb(80, 50, 30),        // Dark wood
+            border_color: ThemeColor::rgb(160, 130, 100),     // Antique gold
+            menu_bg_color: ThemeColor::rgb(235, 225, 210),    // Light parchment
+            button_color: ThemeColor::rgb(200, 170, 140),     // Aged paper
+            comment_color: ThemeColor::rgb(140, 125, 105),    // Faded ink
+            string_color: ThemeColor::rgb(70, 110, 50),       // Verdigris
+            number_color: ThemeColor::rgb(170, 85, 30),       // Burnt sienna
+            constant_color: ThemeColor::rgb(160, 110, 20),    // Gold ochre
+            keyword_color: ThemeColor::rgb(140, 40, 30),      // Vermilion
+            type_color: ThemeColor::rgb(40, 80, 130),         // Lapis lazuli
+            function_color: ThemeColor::rgb(110, 55, 110),    // Tyrian purple
+            ui_text_color: None,
         }
     }
 }
 
 impl Theme {
+    /// The themes that come with DNotepadX, with their menu labels.
+    pub fn built_in() -> [(&'static str, Theme); 4] {
+        [
+            ("📜 Classic Renaissance", Theme::default()),
+            ("🌙 Dark Renaissance", Theme::dark_renaissance()),
+            ("👑 Royal Blue", Theme::royal_blue()),
+            ("🌲 Forest Green", Theme::forest_green()),
+        ]
+    }
+
     pub fn dark_renaissance() -> Self {
         Self {
-            background_color: [35, 30, 25],    // Dark wood
-            text_color: [220, 210, 190],       // Cream
-            line_number_color: [140, 120, 100], // Muted gold
-            selection_color: [80, 60, 40],     // Dark bronze
-            cursor_color: [200, 180, 150],     // Light gold
-            border_color: [100, 80, 60],       // Bronze
-            menu_bg_color: [45, 40, 35],       // Darker wood
-            button_color: [70, 60, 50],        // Dark bronze
+            background_color: ThemeColor::rgb(35, 30, 25),    // Dark wood
+            text_color: ThemeColor::rgb(220, 210, 190),       // Cream
+            line_number_color: ThemeColor::rgb(140, 120, 100), // Muted gold
+            selection_color: ThemeColor::rgb(80, 60, 40),     // Dark bronze
+            cursor_color: ThemeColor::rgb(200, 180, 150),     // Light gold
+            border_color: ThemeColor::rgb(100, 80, 60),       // Bronze
+            menu_bg_color: ThemeColor::rgb(45, 40, 35),       // Darker wood
+            button_color: ThemeColor::rgb(70, 60, 50),        // Dark bronze
+            comment_color: ThemeColor::rgb(125, 110, 95),     // Smoked oak
+            string_color: ThemeColor::rgb(160, 190, 120),     // Sage
+            number_color: ThemeColor::rgb(230, 170, 110),     // Amber
+            constant_color: ThemeColor::rgb(230, 200, 120),   // Gilt
+            keyword_color: ThemeColor::rgb(220, 130, 90),     // Copper
+            type_color: ThemeColor::rgb(130, 170, 210),       // Faded azure
+            function_color: ThemeColor::rgb(205, 165, 215),   // Lavender
+            ui_text_color: None,
         }
     }
 
     pub fn royal_blue() -> Self {
         Self {
-            background_color: [240, 245, 250], // Light blue-white
-            text_color: [20, 30, 60],          // Dark navy
-            line_number_color: [80, 100, 140], // Royal blue
-            selection_color: [150, 170, 200],  // Light royal blue
-            cursor_color: [40, 60, 120],       // Deep blue
-            border_color: [120, 140, 180],     // Medium blue
-            menu_bg_color: [230, 235, 245],    // Very light blue
-            button_color: [180, 200, 230],     // Soft blue
+            background_color: ThemeColor::rgb(240, 245, 250), // Light blue-white
+            text_color: ThemeColor::rgb(20, 30, 60),          // Dark navy
+            line_number_color: ThemeColor::rgb(80, 100, 140), // Royal blue
+            selection_color: ThemeColor::rgb(150, 170, 200),  // Light royal blue
+            cursor_color: ThemeColor::rgb(40, 60, 120),       // Deep blue
+            border_color: ThemeColor::rgb(120, 140, 180),     // Medium blue
+            menu_bg_color: ThemeColor::rgb(230, 235, 245),    // Very light blue
+            button_color: ThemeColor::rgb(180, 200, 230),     // Soft blue
+            comment_color: ThemeColor::rgb(110, 125, 150),    // Slate
+            string_color: ThemeColor::rgb(150, 40, 70),       // Crimson
+            number_color: ThemeColor::rgb(170, 90, 30),       // Bronze
+            constant_color: ThemeColor::rgb(160, 110, 20),    // Gold
+            keyword_color: ThemeColor::rgb(30, 60, 160),      // Royal blue
+            type_color: ThemeColor::rgb(20, 110, 140),        // Teal
+            function_color: ThemeColor::rgb(100, 50, 150),    // Amethyst
+            ui_text_color: None,
         }
     }
 
     pub fn forest_green() -> Self {
         Self {
-            background_color: [240, 245, 240], // Light green-white
-            text_color: [20, 40, 20],          // Dark forest green
-            line_number_color: [80, 120, 80],  // Forest green
-            selection_color: [150, 180, 150],  // Light green
-            cursor_color: [40, 80, 40],        // Deep green
-            border_color: [120, 160, 120],     // Medium green
-            menu_bg_color: [235, 245, 235],    // Very light green
-            button_color: [180, 210, 180],     // Soft green
+            background_color: ThemeColor::rgb(240, 245, 240), // Light green-white
+            text_color: ThemeColor::rgb(20, 40, 20),          // Dark forest green
+            line_number_color: ThemeColor::rgb(80, 120, 80),  // Forest green
+            selection_color: ThemeColor::rgb(150, 180, 150),  // Light green
+            cursor_color: ThemeColor::rgb(40, 80, 40),        // Deep green
+            border_color: ThemeColor::rgb(120, 160, 120),     // Medium green
+            menu_bg_color: ThemeColor::rgb(235, 245, 235),    // Very light green
+            button_color: ThemeColor::rgb(180, 210, 180),     // Soft green
+            comment_color: ThemeColor::rgb(120, 140, 120),    // Lichen
+            string_color: ThemeColor::rgb(140, 90, 30),       // Bark
+            number_color: ThemeColor::rgb(160, 70, 40),       // Rust
+            constant_color: ThemeColor::rgb(150, 120, 30),    // Moss gold
+            keyword_color: ThemeColor::rgb(30, 100, 40),      // Deep forest
+            type_color: ThemeColor::rgb(40, 100, 110),        // Pine
+            function_color: ThemeColor::rgb(120, 60, 90),     // Mulberry
+            ui_text_color: None,
         }
     }
 
-    pub fn to_color32(&self, color: [u8; 3]) -> Color32 {
-        Color32::from_rgb(color[0], color[1], color[2])
-    }
-
     pub fn background(&self) -> Color32 {
-        self.to_color32(self.background_color)
+        self.background_color.color32()
     }
 
     pub fn text(&self) -> Color32 {
-        self.to_color32(self.text_color)
+        self.text_color.color32()
+    }
+
+    pub fn line_number(&self) -> Color32 {
+        self.line_number_color.color32()
     }
 
     pub fn selection(&self) -> Color32 {
-        self.to_color32(self.selection_color)
+        self.selection_color.color32()
     }
 
     pub fn cursor(&self) -> Color32 {
-        self.to_color32(self.cursor_color)
+        self.cursor_color.color32()
     }
 
     pub fn border(&self) -> Color32 {
-        self.to_color32(self.border_color)
+        self.border_color.color32()
     }
 
     pub fn menu_bg(&self) -> Color32 {
-        self.to_color32(self.menu_bg_color)
+        self.menu_bg_color.color32()
     }
 
     pub fn button(&self) -> Color32 {
-        self.to_color32(self.button_color)
+        self.button_color.color32()
+    }
+
+    pub fn syntax(&self, kind: TokenKind) -> Color32 {
+        self.syntax_color(kind).color32()
+    }
+
+    fn syntax_color(&self, kind: TokenKind) -> ThemeColor {
+        match kind {
+            TokenKind::Comment => self.comment_color,
+            TokenKind::String => self.string_color,
+            TokenKind::Number => self.number_color,
+            TokenKind::Constant => self.constant_color,
+            TokenKind::Keyword => self.keyword_color,
+            TokenKind::Type => self.type_color,
+            TokenKind::Function => self.function_color,
+        }
     }
 
-    // Get a contrasting text color for better readability in settings
+    /// Text color of menus, dialogs and the settings window.
     pub fn settings_text(&self) -> Color32 {
-        // Use a lighter color that contrasts well with the menu background
-        match self.menu_bg_color {
-            // For light backgrounds, use a medium-dark color
-            [r, g, b] if r > 200 && g > 200 && b > 200 => Color32::from_rgb(60, 60, 60),
-            // For dark backgrounds, use a light color
-            _ => Color32::from_rgb(220, 220, 220),
+        self.ui_text().color32()
+    }
+
+    /// `ui_text_color`, or the automatic pick when it is `None`.
+    pub fn ui_text(&self) -> ThemeColor {
+        self.ui_text_color.unwrap_or_else(|| {
+            let menu_bg = self.menu_bg_color;
+            [DARK_UI_TEXT, LIGHT_UI_TEXT]
+                .into_iter()
+                .max_by(|a, b| a.contrast(menu_bg).total_cmp(&b.contrast(menu_bg)))
+                .unwrap_or(DARK_UI_TEXT)
+        })
+    }
+
+    /// Every color but the UI text, labelled for the theme editor.
+    pub fn colors_mut(&mut self) -> [(&'static str, &mut ThemeColor); 15] {
+        [
+            ("Background", &mut self.background_color),
+            ("Text", &mut self.text_color),
+            ("Line numbers", &mut self.line_number_color),
+            ("Selection", &mut self.selection_color),
+            ("Cursor", &mut self.cursor_color),
+            ("Border", &mut self.border_color),
+            ("Menu background", &mut self.menu_bg_color),
+            ("Buttons", &mut self.button_color),
+            ("Comments", &mut self.comment_color),
+            ("Strings", &mut self.string_color),
+            ("Numbers", &mut self.number_color),
+            ("Constants", &mut self.constant_color),
+            ("Keywords", &mut self.keyword_color),
+            ("Types", &mut self.type_color),
+            ("Functions", &mut self.function_color),
+        ]
+    }
+
+    /// Text and background pairs whose contrast ratio is below what WCAG 2 level AA
+    /// asks of body text, with the ratio. Translucent colors count as drawn over what
+    /// is beneath them.
+    pub fn contrast_warnings(&self) -> Vec<(String, f32)> {
+        let background = self.background_color;
+        let selection = self.selection_color.over(background);
+        let menu_bg = self.menu_bg_color;
+        let button = self.button_color.over(menu_bg);
+        let ui_text = self.ui_text();
+        let mut pairs = vec![
+            ("Text on background".to_string(), self.text_color, background),
+            ("Text on selection".to_string(), self.text_color, selection),
+            ("UI text on menu background".to_string(), ui_text, menu_bg),
+            ("UI text on buttons".to_string(), ui_text, button),
+        ];
+        for kind in TokenKind::ALL {
+            pairs.push((format!("{} on background", kind.label()), self.syntax_color(kind), background));
         }
+        pairs
+            .into_iter()
+            .map(|(label, text, background)| (label, text.over(background).contrast(background)))
+            .filter(|&(_, ratio)| ratio < AA_CONTRAST)
+            .collect()
     }
 }
 
-pub fn get_available_fonts() -> Vec<String> {
-    vec![
-        "Monospace".to_string(),
-        "Proportional".to_string(),
-        "Consolas".to_string(),
-        "Courier New".to_string(),
-        "Liberation Mono".to_string(),
-        "DejaVu Sans Mono".to_string(),
-        "Ubuntu Mono".to_string(),
-        "Source Code Pro".to_string(),
-        "Fira Code".to_string(),
-        "JetBrains Mono".to_string(),
-        "Cascadia Code".to_string(),
-        "Inconsolata".to_string(),
-    ]
+/// WCAG 2 level AA's minimum contrast ratio for body text.
+pub const AA_CONTRAST: f32 = 4.5;
+
+/// The UI text colors picked from when a theme doesn't set one.
+const DARK_UI_TEXT: ThemeColor = ThemeColor::rgb(60, 60, 60);
+const LIGHT_UI_TEXT: ThemeColor = ThemeColor::rgb(220, 220, 220);
+
+/// An sRGB color with straight alpha. Opaque colors are stored as `[r, g, b]`, the
+/// way all colors were before themes had alpha, and others as `[r, g, b, a]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<u8>", into = "Vec<u8>")]
+pub struct ThemeColor(pub [u8; 4]);
+
+impl ThemeColor {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self([r, g, b, 255])
+    }
+
+    pub fn color32(self) -> Color32 {
+        let [r, g, b, a] = self.0;
+        Color32::from_rgba_unmultiplied(r, g, b, a)
+    }
+
+    pub fn from_color32(color: Color32) -> Self {
+        Self(color.to_srgba_unmultiplied())
+    }
+
+    /// Parses `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`; the `#` may be left out.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim().trim_start_matches('#');
+        let digits: Vec<u8> = hex.chars().map(|c| c.to_digit(16).map(|d| d as u8)).collect::<Option<_>>()?;
+        let components: Vec<u8> = match digits.len() {
+            3 | 4 => digits.iter().map(|d| d * 17).collect(),
+            6 | 8 => digits.chunks(2).map(|pair| pair[0] * 16 + pair[1]).collect(),
+            _ => return None,
+        };
+        Self::try_from(components).ok()
+    }
+
+    /// Whether light text stands out more against this color than dark text.
+    pub fn is_dark(self) -> bool {
+        self.contrast(ThemeColor::rgb(255, 255, 255)) > self.contrast(ThemeColor::rgb(0, 0, 0))
+    }
+
+    /// The opaque color this gives drawn over `below`.
+    pub fn over(self, below: ThemeColor) -> ThemeColor {
+        let alpha = self.0[3] as f32 / 255.0;
+        let mix = |i: usize| (self.0[i] as f32 * alpha + below.0[i] as f32 * (1.0 - alpha)).round() as u8;
+        ThemeColor::rgb(mix(0), mix(1), mix(2))
+    }
+
+    /// WCAG 2 relative luminance, from 0 for black to 1 for white.
+    fn luminance(self) -> f32 {
+        let linear = |c: u8| {
+            let c = c as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * linear(self.0[0]) + 0.7152 * linear(self.0[1]) + 0.0722 * linear(self.0[2])
+    }
+
+    /// WCAG 2 contrast ratio between two opaque colors, from 1 to 21.
+    pub fn contrast(self, other: ThemeColor) -> f32 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+}
+
+impl TryFrom<Vec<u8>> for ThemeColor {
+    type Error = String;
+
+    fn try_from(components: Vec<u8>) -> Result<Self, Self::Error> {
+        match components[..] {
+            [r, g, b] => Ok(Self::rgb(r, g, b)),
+            [r, g, b, a] => Ok(Self([r, g, b, a])),
+            _ => Err(format!("expected 3 or 4 color components, found {}", components.len())),
+        }
+    }
 }
 
-pub fn create_font_id(font_name: &str, size: f32) -> FontId {
-    match font_name {
-        "Monospace" => FontId::new(size, FontFamily::Monospace),
-        "Proportional" => FontId::new(size, FontFamily::Proportional),
-        _ => FontId::new(size, FontFamily::Monospace), // Default to monospace for all custom fonts
+impl From<ThemeColor> for Vec<u8> {
+    fn from(color: ThemeColor) -> Self {
+        match color.0 {
+            [r, g, b, 255] => vec![r, g, b],
+            rgba => rgba.to_vec(),
+        }
     }
 }
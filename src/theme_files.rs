@@ -0,0 +1,654 @@
+use crate::syntax::TokenKind;
+use crate::theme::{Theme, ThemeColor};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde_json::{Map, Value};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A theme saved in the themes directory, listed in `View > Themes`.
+pub struct ThemeFile {
+    /// The file name without its extension.
+    pub name: String,
+    pub theme: Theme,
+}
+
+/// `<config dir>/dnotepadx/themes`, where every `.json` or `.toml` file is a theme.
+pub fn themes_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("dnotepadx").join("themes"))
+}
+
+/// The themes in `themes_dir`, sorted by name. Files that aren't themes are skipped.
+pub fn load_all() -> Vec<ThemeFile> {
+    let Some(entries) = themes_dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut themes: Vec<ThemeFile> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let name = path.file_stem()?.to_string_lossy().into_owned();
+            let theme = read(&path).ok()?;
+            Some(ThemeFile { name, theme })
+        })
+        .collect();
+    themes.sort_by_key(|theme| theme.name.to_lowercase());
+    themes
+}
+
+/// Reads a theme in DNotepadX's own format: the `Theme` fields in JSON or TOML.
+pub fn read(path: &Path) -> io::Result<Theme> {
+    let text = std::fs::read_to_string(path)?;
+    let value = match extension(path).as_str() {
+        "json" => serde_json::from_str(&text).map_err(invalid)?,
+        "toml" => toml_to_json(text.parse::<toml_edit::DocumentMut>().map_err(invalid)?.as_item()),
+        _ => return Err(invalid("not a .json or .toml file")),
+    };
+    theme_from_value(value)
+}
+
+/// Writes `theme` as JSON, the format `read` takes.
+pub fn write(path: &Path, theme: &Theme) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(theme).map_err(invalid)?;
+    std::fs::write(path, json)
+}
+
+/// Reads a color scheme made for another editor — a VS Code color theme (JSON with
+/// comments), a TextMate `.tmTheme` or a base16 YAML scheme — or a shared DNotepadX
+/// theme, and returns its name and the theme it maps to.
+pub fn import(path: &Path) -> io::Result<(String, Theme)> {
+    let text = std::fs::read_to_string(path)?;
+    let stem = path.file_stem().map_or_else(|| "Imported".to_string(), |s| s.to_string_lossy().into_owned());
+    let (name, theme) = match extension(path).as_str() {
+        "tmtheme" => text_mate(&parse_plist(&text)?),
+        "yaml" | "yml" => base16(&text)?,
+        "toml" => (None, read(path)?),
+        _ => {
+            let value: Value = serde_json::from_str(&strip_jsonc(&text)).map_err(invalid)?;
+            if value.get("background_color").is_some() || value.get("text_color").is_some() {
+                (None, theme_from_value(value)?)
+            } else {
+                vs_code(&value)
+            }
+        }
+    };
+    Ok((name.filter(|name| !name.trim().is_empty()).unwrap_or(stem), theme))
+}
+
+/// A path in the themes directory for a theme called `name` that doesn't overwrite another one.
+pub fn new_theme_path(name: &str) -> Option<PathBuf> {
+    let dir = themes_dir()?;
+    let name: String = name
+        .chars()
+        .map(|c| if "/\\:*?\"<>|".contains(c) || c.is_control() { '-' } else { c })
+        .collect();
+    (1..)
+        .map(|n| match n {
+            1 => dir.join(format!("{}.json", name)),
+            n => dir.join(format!("{} {}.json", name, n)),
+        })
+        .find(|path| !path.exists())
+}
+
+fn extension(path: &Path) -> String {
+    path.extension().map_or_else(String::new, |e| e.to_string_lossy().to_lowercase())
+}
+
+fn invalid(error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+fn theme_from_value(value: Value) -> io::Result<Theme> {
+    if !value.is_object() {
+        return Err(invalid("a theme must be a table of colors"));
+    }
+    serde_json::from_value(value).map_err(invalid)
+}
+
+fn toml_to_json(item: &toml_edit::Item) -> Value {
+    match item {
+        toml_edit::Item::Value(value) => toml_value_to_json(value),
+        toml_edit::Item::Table(table) => {
+            Value::Object(table.iter().map(|(key, item)| (key.to_string(), toml_to_json(item))).collect())
+        }
+        toml_edit::Item::ArrayOfTables(tables) => Value::Array(
+            tables
+                .iter()
+                .map(|table| toml_to_json(&toml_edit::Item::Table(table.clone())))
+                .collect(),
+        ),
+        toml_edit::Item::None => Value::Null,
+    }
+}
+
+fn toml_value_to_json(value: &toml_edit::Value) -> Value {
+    match value {
+        toml_edit::Value::String(s) => Value::String(s.value().clone()),
+        toml_edit::Value::Integer(i) => Value::from(*i.value()),
+        toml_edit::Value::Float(f) => Value::from(*f.value()),
+        toml_edit::Value::Boolean(b) => Value::Bool(*b.value()),
+        toml_edit::Value::Datetime(d) => Value::String(d.value().to_string()),
+        toml_edit::Value::Array(array) => Value::Array(array.iter().map(toml_value_to_json).collect()),
+        toml_edit::Value::InlineTable(table) => Value::Object(
+            table
+                .iter()
+                .map(|(key, value)| (key.to_string(), toml_value_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// What an imported scheme says about the parts of a `Theme`; `None` where it says nothing.
+#[derive(Default)]
+struct Palette {
+    background: Option<ThemeColor>,
+    text: Option<ThemeColor>,
+    line_number: Option<ThemeColor>,
+    selection: Option<ThemeColor>,
+    cursor: Option<ThemeColor>,
+    border: Option<ThemeColor>,
+    menu_bg: Option<ThemeColor>,
+    button: Option<ThemeColor>,
+    ui_text: Option<ThemeColor>,
+    /// Colors by `TokenKind::ALL` order.
+    tokens: [Option<ThemeColor>; 7],
+}
+
+impl Palette {
+    /// What the scheme leaves open comes from the built-in theme that is as light or
+    /// as dark as its background.
+    fn into_theme(self) -> Theme {
+        let dark = self.background.is_some_and(ThemeColor::is_dark);
+        let mut theme = if dark { Theme::dark_renaissance() } else { Theme::default() };
+        let menu_bg = self.menu_bg.or(self.background);
+        let fields = [
+            (&mut theme.background_color, self.background),
+            (&mut theme.text_color, self.text),
+            (&mut theme.line_number_color, self.line_number),
+            (&mut theme.selection_color, self.selection),
+            (&mut theme.cursor_color, self.cursor.or(self.text)),
+            (&mut theme.border_color, self.border),
+            (&mut theme.menu_bg_color, menu_bg),
+            (&mut theme.button_color, self.button),
+        ];
+        for (field, color) in fields {
+            if let Some(color) = color {
+                *field = color;
+            }
+        }
+        for (kind, color) in TokenKind::ALL.into_iter().zip(self.tokens) {
+            let field = match kind {
+                TokenKind::Comment => &mut theme.comment_color,
+                TokenKind::String => &mut theme.string_color,
+                TokenKind::Number => &mut theme.number_color,
+                TokenKind::Constant => &mut theme.constant_color,
+                TokenKind::Keyword => &mut theme.keyword_color,
+                TokenKind::Type => &mut theme.type_color,
+                TokenKind::Function => &mut theme.function_color,
+            };
+            if let Some(color) = color {
+                *field = color;
+            }
+        }
+        theme.ui_text_color = self.ui_text;
+        theme
+    }
+
+    /// Token colors from TextMate scope rules, the `settings` of a `.tmTheme` or the
+    /// `tokenColors` of a VS Code theme.
+    fn add_token_rules(&mut self, rules: &[Value]) {
+        for (i, kind) in TokenKind::ALL.into_iter().enumerate() {
+            let color = token_scopes(kind)
+                .iter()
+                .find_map(|scope| rule_color(rules, scope));
+            if color.is_some() {
+                self.tokens[i] = color;
+            }
+        }
+    }
+}
+
+/// Scopes TextMate grammars give each kind of token, the most telling first.
+fn token_scopes(kind: TokenKind) -> &'static [&'static str] {
+    match kind {
+        TokenKind::Comment => &["comment.line.double-slash"],
+        TokenKind::String => &["string.quoted.double"],
+        TokenKind::Number => &["constant.numeric.integer"],
+        TokenKind::Constant => &["constant.language", "variable.other.constant"],
+        TokenKind::Keyword => &["keyword.control", "storage.type"],
+        TokenKind::Type => &["entity.name.type", "support.type", "entity.name.class"],
+        TokenKind::Function => &["entity.name.function", "support.function"],
+    }
+}
+
+/// The foreground the most specific rule gives `scope`; among equally specific
+/// rules the last one wins, as in TextMate.
+fn rule_color(rules: &[Value], scope: &str) -> Option<ThemeColor> {
+    let mut best: Option<(usize, ThemeColor)> = None;
+    for rule in rules {
+        let Some(color) = rule.pointer("/settings/foreground").and_then(Value::as_str).and_then(ThemeColor::from_hex) else {
+            continue;
+        };
+        let selectors: Vec<&str> = match rule.get("scope") {
+            Some(Value::String(scopes)) => scopes.split(',').collect(),
+            Some(Value::Array(scopes)) => scopes.iter().filter_map(Value::as_str).collect(),
+            _ => continue,
+        };
+        for selector in selectors {
+            // Exclusions are dropped and only the innermost scope of a path is compared
+            let selector = selector.split(" - ").next().unwrap_or_default();
+            let Some(last) = selector.split_whitespace().last() else {
+                continue;
+            };
+            let matches = scope == last || scope.strip_prefix(last).is_some_and(|rest| rest.starts_with('.'));
+            let depth = last.split('.').count();
+            if matches && best.is_none_or(|(best_depth, _)| depth >= best_depth) {
+                best = Some((depth, color));
+            }
+        }
+    }
+    best.map(|(_, color)| color)
+}
+
+/// Maps a VS Code color theme: workbench `colors` and TextMate `tokenColors`.
+fn vs_code(value: &Value) -> (Option<String>, Theme) {
+    let color = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| value.get("colors")?.get(key)?.as_str().and_then(ThemeColor::from_hex))
+    };
+    let mut palette = Palette {
+        background: color(&["editor.background"]),
+        text: color(&["editor.foreground", "foreground"]),
+        line_number: color(&["editorLineNumber.foreground"]),
+        selection: color(&["editor.selectionBackground"]),
+        cursor: color(&["editorCursor.foreground"]),
+        border: color(&["editorGroup.border", "panel.border", "contrastBorder", "focusBorder"]),
+        menu_bg: color(&["menu.background", "titleBar.activeBackground", "sideBar.background"]),
+        button: color(&["button.secondaryBackground", "dropdown.background", "input.background"]),
+        ui_text: color(&["menu.foreground", "foreground", "sideBar.foreground"]),
+        ..Default::default()
+    };
+    if let Some(Value::Array(rules)) = value.get("tokenColors") {
+        // A rule without a scope carries the defaults, like in a .tmTheme
+        if let Some(defaults) = rules.iter().find(|rule| rule.get("scope").is_none()) {
+            let setting = |key: &str| defaults.get("settings")?.get(key)?.as_str().and_then(ThemeColor::from_hex);
+            palette.background = palette.background.or_else(|| setting("background"));
+            palette.text = palette.text.or_else(|| setting("foreground"));
+        }
+        palette.add_token_rules(rules);
+    }
+    let name = value.get("name").and_then(Value::as_str).map(str::to_string);
+    (name, palette.into_theme())
+}
+
+/// Maps a TextMate `.tmTheme`, read with `parse_plist`.
+fn text_mate(plist: &Value) -> (Option<String>, Theme) {
+    let rules = match plist.get("settings") {
+        Some(Value::Array(rules)) => rules.as_slice(),
+        _ => &[],
+    };
+    let defaults = rules.iter().find(|rule| rule.get("scope").is_none());
+    let setting = |key: &str| defaults?.get("settings")?.get(key)?.as_str().and_then(ThemeColor::from_hex);
+    let mut palette = Palette {
+        background: setting("background"),
+        text: setting("foreground"),
+        line_number: setting("gutterForeground"),
+        selection: setting("selection"),
+        cursor: setting("caret"),
+        border: setting("selectionBorder"),
+        menu_bg: setting("gutter"),
+        ..Default::default()
+    };
+    palette.add_token_rules(rules);
+    let name = plist.get("name").and_then(Value::as_str).map(str::to_string);
+    (name, palette.into_theme())
+}
+
+/// Maps a base16 scheme, which lists sixteen colors `base00` to `base0F` either at the
+/// top level (the original format) or under `palette:` (tinted-theming's).
+fn base16(text: &str) -> io::Result<(Option<String>, Theme)> {
+    let mut name = None;
+    let mut base = [None; 16];
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        // Values may be quoted and followed by a comment
+        let value = value.split(" #").next().unwrap_or_default().trim().trim_matches(['"', '\'']);
+        match key.trim() {
+            "scheme" | "name" => name = Some(value.to_string()),
+            key => {
+                let index = key.strip_prefix("base").and_then(|digits| usize::from_str_radix(digits, 16).ok());
+                if let Some(index) = index.filter(|&i| i < 16 && key.len() == 6) {
+                    base[index] = ThemeColor::from_hex(value);
+                }
+            }
+        }
+    }
+    if base.iter().any(Option::is_none) {
+        return Err(invalid("a base16 scheme needs the colors base00 to base0F"));
+    }
+    // The roles the base16 styling guidelines give each color
+    let palette = Palette {
+        background: base[0x0],
+        menu_bg: base[0x1],
+        selection: base[0x2],
+        button: base[0x2],
+        line_number: base[0x3],
+        border: base[0x3],
+        text: base[0x5],
+        cursor: base[0x5],
+        ui_text: base[0x5],
+        tokens: [base[0x3], base[0xB], base[0x9], base[0x9], base[0xE], base[0xA], base[0xD]],
+    };
+    Ok((name, palette.into_theme()))
+}
+
+/// Removes the comments and trailing commas VS Code allows in its JSON files.
+fn strip_jsonc(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            (']' | '}', _) => {
+                let kept = out.trim_end().len();
+                if out[..kept].ends_with(',') {
+                    out.truncate(kept - 1);
+                }
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Reads an XML property list into JSON values: dicts become objects, arrays arrays,
+/// and strings, numbers and dates strings.
+fn parse_plist(text: &str) -> io::Result<Value> {
+    let mut reader = Reader::from_str(text);
+    reader.config_mut().trim_text(true);
+    // Containers being filled, each dict with the key whose value comes next
+    let mut stack: Vec<(Value, Option<String>)> = Vec::new();
+    let mut root = None;
+    let mut text = String::new();
+    let mut add = |stack: &mut Vec<(Value, Option<String>)>, value: Value| match stack.last_mut() {
+        Some((Value::Object(map), key)) => {
+            if let Some(key) = key.take() {
+                map.insert(key, value);
+            }
+        }
+        Some((Value::Array(items), _)) => items.push(value),
+        _ => root = Some(value),
+    };
+    loop {
+        match reader.read_event().map_err(invalid)? {
+            Event::Start(element) => match element.name().as_ref() {
+                b"dict" => stack.push((Value::Object(Map::new()), None)),
+                b"array" => stack.push((Value::Array(Vec::new()), None)),
+                _ => text.clear(),
+            },
+            Event::Empty(element) => {
+                let value = match element.name().as_ref() {
+                    b"true" => Value::Bool(true),
+                    b"false" => Value::Bool(false),
+                    b"dict" => Value::Object(Map::new()),
+                    b"array" => Value::Array(Vec::new()),
+                    b"string" => Value::String(String::new()),
+                    _ => continue,
+                };
+                add(&mut stack, value);
+            }
+            Event::Text(content) => text.push_str(&content.unescape().map_err(invalid)?),
+            Event::CData(content) => text.push_str(&String::from_utf8_lossy(&content)),
+            Event::End(element) => match element.name().as_ref() {
+                b"dict" | b"array" => {
+                    if let Some((value, _)) = stack.pop() {
+                        add(&mut stack, value);
+                    }
+                }
+                b"key" => {
+                    if let Some((_, key)) = stack.last_mut() {
+                        *key = Some(std::mem::take(&mut text));
+                    }
+                }
+                b"string" | b"integer" | b"real" | b"date" | b"data" => {
+                    add(&mut stack, Value::String(std::mem::take(&mut text)));
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    root.ok_or_else(|| invalid("not a property list"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn gray(level: u8) -> ThemeColor {
+        ThemeColor::rgb(level, level, level)
+    }
+
+    /// A base16 scheme where `baseN` is the gray `N * 0x11`, with `indent` before each color.
+    fn base16_scheme(header: &str, indent: &str) -> String {
+        let colors: String = (0..16u8)
+            .map(|i| format!("{indent}base{i:02X}: \"{0:02x}{0:02x}{0:02x}\" # base{i:02X}\n", i * 0x11))
+            .collect();
+        format!("{header}{colors}")
+    }
+
+    #[test]
+    fn strip_jsonc_removes_comments_and_trailing_commas_outside_strings() {
+        let text = r#"{
+            // a line comment
+            "url": "http://example.com/a", /* a block comment */
+            "glob": "src/**/*.rs",
+            "list": [1, 2, ],
+            "text": "[1,] {a,}",
+            "quote": "say \"hi\", // still text",
+            "nested": { "a": 1, },
+        }"#;
+        let value: Value = serde_json::from_str(&strip_jsonc(text)).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "url": "http://example.com/a",
+                "glob": "src/**/*.rs",
+                "list": [1, 2],
+                "text": "[1,] {a,}",
+                "quote": "say \"hi\", // still text",
+                "nested": { "a": 1 },
+            })
+        );
+    }
+
+    #[test]
+    fn parse_plist_nests_dicts_and_arrays() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <plist version="1.0">
+            <dict>
+                <key>name</key><string>Tom &amp; Jerry</string>
+                <key>settings</key>
+                <array>
+                    <dict>
+                        <key>settings</key>
+                        <dict><key>background</key><string>#102030</string></dict>
+                    </dict>
+                    <dict>
+                        <key>scope</key><string>comment</string>
+                        <key>settings</key><dict/>
+                    </dict>
+                </array>
+                <key>size</key><integer>12</integer>
+                <key>semantic</key><true/>
+                <key>empty</key><string/>
+            </dict>
+            </plist>"#;
+        assert_eq!(
+            parse_plist(text).unwrap(),
+            json!({
+                "name": "Tom & Jerry",
+                "settings": [
+                    { "settings": { "background": "#102030" } },
+                    { "scope": "comment", "settings": {} },
+                ],
+                "size": "12",
+                "semantic": true,
+                "empty": "",
+            })
+        );
+        assert!(parse_plist("<plist></plist>").is_err());
+    }
+
+    #[test]
+    fn base16_reads_top_level_colors() {
+        let (name, theme) = base16(&base16_scheme("scheme: \"Grays\"\nauthor: someone\n", "")).unwrap();
+        assert_eq!(name.as_deref(), Some("Grays"));
+        assert_eq!(theme.background_color, gray(0x00));
+        assert_eq!(theme.menu_bg_color, gray(0x11));
+        assert_eq!(theme.text_color, gray(0x55));
+        assert_eq!(theme.ui_text_color, Some(gray(0x55)));
+        assert_eq!(theme.comment_color, gray(0x33));
+        assert_eq!(theme.keyword_color, gray(0xEE));
+        assert_eq!(theme.function_color, gray(0xDD));
+    }
+
+    #[test]
+    fn base16_reads_colors_under_palette() {
+        let (name, theme) = base16(&base16_scheme("system: \"base16\"\nname: 'Grays'\npalette:\n", "  ")).unwrap();
+        assert_eq!(name.as_deref(), Some("Grays"));
+        assert_eq!(theme.background_color, gray(0x00));
+        assert_eq!(theme.string_color, gray(0xBB));
+    }
+
+    #[test]
+    fn base16_needs_all_sixteen_colors() {
+        let scheme = base16_scheme("", "").replace("base0F", "base0G");
+        assert!(base16(&scheme).is_err());
+    }
+
+    #[test]
+    fn rule_color_prefers_the_most_specific_then_the_last_rule() {
+        let rule = |scope: Value, color: &str| json!({ "scope": scope, "settings": { "foreground": color } });
+        let rules = [
+            rule(json!("keyword"), "#010101"),
+            rule(json!("keyword.control"), "#020202"),
+            rule(json!("keyword.control.flow.js"), "#030303"),
+            rule(json!("string, constant"), "#040404"),
+            rule(json!(["string"]), "#050505"),
+            rule(json!("source.js comment - comment.block"), "#060606"),
+            rule(json!("key"), "#070707"),
+            json!({ "scope": "support", "settings": { "fontStyle": "italic" } }),
+        ];
+        assert_eq!(rule_color(&rules, "keyword.control.flow"), Some(gray(0x02)));
+        assert_eq!(rule_color(&rules, "keyword.operator"), Some(gray(0x01)));
+        assert_eq!(rule_color(&rules, "string.quoted.double"), Some(gray(0x05)));
+        assert_eq!(rule_color(&rules, "constant.numeric"), Some(gray(0x04)));
+        assert_eq!(rule_color(&rules, "comment.line"), Some(gray(0x06)));
+        assert_eq!(rule_color(&rules, "keywords"), None);
+        assert_eq!(rule_color(&rules, "support.function"), None);
+    }
+
+    #[test]
+    fn vs_code_maps_workbench_and_token_colors() {
+        let value = json!({
+            "name": "Night",
+            "colors": {
+                "editor.background": "#101010",
+                "editor.foreground": "#e0e0e0",
+                "editorLineNumber.foreground": "#808080",
+            },
+            "tokenColors": [
+                { "settings": { "foreground": "#ffffff" } },
+                { "scope": "keyword", "settings": { "foreground": "#c0c0c0" } },
+                { "scope": ["comment"], "settings": { "foreground": "#707070" } },
+            ],
+        });
+        let (name, theme) = vs_code(&value);
+        assert_eq!(name.as_deref(), Some("Night"));
+        assert_eq!(theme.background_color, gray(0x10));
+        assert_eq!(theme.text_color, gray(0xE0));
+        assert_eq!(theme.cursor_color, gray(0xE0));
+        assert_eq!(theme.line_number_color, gray(0x80));
+        assert_eq!(theme.menu_bg_color, gray(0x10));
+        assert_eq!(theme.keyword_color, gray(0xC0));
+        assert_eq!(theme.comment_color, gray(0x70));
+        // What the theme leaves open comes from the dark built-in theme
+        assert_eq!(theme.string_color, Theme::dark_renaissance().string_color);
+    }
+
+    #[test]
+    fn vs_code_falls_back_to_the_unscoped_token_rule() {
+        let value = json!({
+            "tokenColors": [{ "settings": { "background": "#f0f0f0", "foreground": "#202020" } }],
+        });
+        let (name, theme) = vs_code(&value);
+        assert_eq!(name, None);
+        assert_eq!(theme.background_color, gray(0xF0));
+        assert_eq!(theme.text_color, gray(0x20));
+        assert_eq!(theme.string_color, Theme::default().string_color);
+    }
+
+    #[test]
+    fn text_mate_maps_the_default_settings_and_scopes() {
+        let plist = json!({
+            "name": "Paper",
+            "settings": [
+                {
+                    "settings": {
+                        "background": "#fafafa",
+                        "foreground": "#303030",
+                        "caret": "#000000",
+                        "gutterForeground": "#909090",
+                    }
+                },
+                { "scope": "storage.type", "settings": { "foreground": "#010101" } },
+                { "scope": "keyword.control", "settings": { "foreground": "#020202" } },
+                { "scope": "entity.name", "settings": { "foreground": "#030303" } },
+                { "scope": "entity.name.function", "settings": { "foreground": "#040404" } },
+            ],
+        });
+        let (name, theme) = text_mate(&plist);
+        assert_eq!(name.as_deref(), Some("Paper"));
+        assert_eq!(theme.background_color, gray(0xFA));
+        assert_eq!(theme.text_color, gray(0x30));
+        assert_eq!(theme.cursor_color, gray(0x00));
+        assert_eq!(theme.line_number_color, gray(0x90));
+        // keyword.control is tried before storage.type
+        assert_eq!(theme.keyword_color, gray(0x02));
+        assert_eq!(theme.function_color, gray(0x04));
+        assert_eq!(theme.type_color, gray(0x03));
+        assert_eq!(theme.comment_color, Theme::default().comment_color);
+    }
+}
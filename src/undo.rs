@@ -0,0 +1,594 @@
+use crate::document::Selection;
+use eframe::egui::TextBuffer;
+use ropey::{Rope, RopeBuilder};
+use std::ops::Range;
+
+/// Typing pauses longer than this start a new undo step.
+const GROUP_TIMEOUT_SECS: f64 = 1.0;
+
+/// Replacement of `removed` by `inserted` at character index `at`.
+/// Edits of one transaction are recorded in the order they were applied.
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub at: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Edit {
+    pub fn new(at: usize, removed: impl Into<String>, inserted: impl Into<String>) -> Self {
+        Self {
+            at,
+            removed: removed.into(),
+            inserted: inserted.into(),
+        }
+    }
+
+    pub fn apply(&self, text: &mut (impl TextStorage + ?Sized)) {
+        text.replace_chars(self.at..self.at + self.removed.chars().count(), &self.inserted);
+    }
+
+    fn revert(&self, text: &mut (impl TextStorage + ?Sized)) {
+        text.replace_chars(self.at..self.at + self.inserted.chars().count(), &self.removed);
+    }
+}
+
+/// Applies `edits` in order. Runs of edits that move forward through the text, like
+/// the ones Replace All makes, are done in one pass instead of one at a time.
+pub fn apply_all(text: &mut (impl TextStorage + ?Sized), edits: &[Edit]) {
+    let mut shift = 0isize;
+    let mut replacements = Vec::with_capacity(edits.len());
+    for (i, edit) in edits.iter().enumerate() {
+        if i > 0 && edit.at < edits[i - 1].at + edits[i - 1].inserted.chars().count() {
+            edits.iter().for_each(|edit| edit.apply(text));
+            return;
+        }
+        let removed = edit.removed.chars().count();
+        // `at` counts the changes of the edits before; the pass works on the original text
+        let start = (edit.at as isize - shift) as usize;
+        replacements.push((start..start + removed, edit.inserted.as_str()));
+        shift += edit.inserted.chars().count() as isize - removed as isize;
+    }
+    text.replace_many(replacements);
+}
+
+/// Undoes `edits`, which were applied in order, last one first.
+fn revert_all(text: &mut (impl TextStorage + ?Sized), edits: &[Edit]) {
+    let mut replacements = Vec::with_capacity(edits.len());
+    for (i, edit) in edits.iter().enumerate() {
+        let inserted = edit.inserted.chars().count();
+        if i + 1 < edits.len() && edits[i + 1].at < edit.at + inserted {
+            edits.iter().rev().for_each(|edit| edit.revert(text));
+            return;
+        }
+        // Later edits lie behind this one, so its position holds in the final text
+        replacements.push((edit.at..edit.at + inserted, edit.removed.as_str()));
+    }
+    text.replace_many(replacements);
+}
+
+/// Text a document can be stored in.
+pub trait TextStorage {
+    fn replace_chars(&mut self, char_range: Range<usize>, replacement: &str);
+
+    /// Replaces sorted, non-overlapping character ranges of the current text.
+    fn replace_many(&mut self, replacements: Vec<(Range<usize>, &str)>) {
+        for (char_range, replacement) in replacements.into_iter().rev() {
+            self.replace_chars(char_range, replacement);
+        }
+    }
+}
+
+impl TextStorage for String {
+    fn replace_chars(&mut self, char_range: Range<usize>, replacement: &str) {
+        let start = byte_index(self, char_range.start);
+        let end = start + byte_index(&self[start..], char_range.end - char_range.start);
+        self.replace_range(start..end, replacement);
+    }
+
+    /// Builds the new text in a single copy rather than shifting the tail for every replacement.
+    fn replace_many(&mut self, replacements: Vec<(Range<usize>, &str)>) {
+        if replacements.len() < 2 {
+            for (char_range, replacement) in replacements {
+                self.replace_chars(char_range, replacement);
+            }
+            return;
+        }
+        let len = self.len();
+        let mut result = String::with_capacity(len);
+        let mut offsets = self
+            .char_indices()
+            .map(|(byte, _)| byte)
+            .chain(std::iter::once(len))
+            .enumerate()
+            .peekable();
+        // Character indices only grow, so the text is walked once
+        let mut byte_at = |index: usize| {
+            while let Some(&(char_index, byte)) = offsets.peek() {
+                if char_index == index {
+                    return byte;
+                }
+                offsets.next();
+            }
+            len
+        };
+        let mut byte_pos = 0;
+        for (char_range, replacement) in replacements {
+            let start = byte_at(char_range.start);
+            let end = byte_at(char_range.end);
+            result.push_str(&self[byte_pos..start]);
+            result.push_str(replacement);
+            byte_pos = end;
+        }
+        result.push_str(&self[byte_pos..]);
+        *self = result;
+    }
+}
+
+impl TextStorage for Rope {
+    fn replace_chars(&mut self, char_range: Range<usize>, replacement: &str) {
+        self.remove(char_range.clone());
+        self.insert(char_range.start, replacement);
+    }
+
+    /// Edits in place when there are few replacements, otherwise copies the text once into a new rope.
+    fn replace_many(&mut self, replacements: Vec<(Range<usize>, &str)>) {
+        if replacements.len() < 1000 {
+            for (char_range, replacement) in replacements.into_iter().rev() {
+                self.replace_chars(char_range, replacement);
+            }
+            return;
+        }
+        let mut builder = RopeBuilder::new();
+        let mut pos = 0;
+        for (char_range, replacement) in replacements {
+            self.slice(pos..char_range.start).chunks().for_each(|chunk| builder.append(chunk));
+            builder.append(replacement);
+            pos = char_range.end;
+        }
+        self.slice(pos..).chunks().for_each(|chunk| builder.append(chunk));
+        *self = builder.finish();
+    }
+}
+
+/// The bytes of a document in the hex editor, where character `n` of an edit stands for byte `n`.
+impl TextStorage for Vec<u8> {
+    fn replace_chars(&mut self, char_range: Range<usize>, replacement: &str) {
+        self.splice(char_range, replacement.chars().map(|c| c as u8));
+    }
+}
+
+/// How an edit was made, used to decide whether it can be merged with the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    /// A single typed character.
+    Typing,
+    /// A single character removed with Backspace or Delete.
+    Deleting,
+    /// Anything else: paste, cut, replace, indentation changes...
+    Other,
+}
+
+impl EditKind {
+    pub fn classify(edits: &[Edit]) -> Self {
+        match edits {
+            [edit] if edit.removed.is_empty() && edit.inserted.chars().count() == 1 => {
+                EditKind::Typing
+            }
+            [edit] if edit.inserted.is_empty() && edit.removed.chars().count() == 1 => {
+                EditKind::Deleting
+            }
+            _ => EditKind::Other,
+        }
+    }
+}
+
+struct Transaction {
+    id: u64,
+    edits: Vec<Edit>,
+    before: Selection,
+    after: Selection,
+    kind: EditKind,
+    time: f64,
+}
+
+impl Transaction {
+    /// Tries to fold a follow-up single-character edit into this transaction.
+    fn merge(&mut self, edit: &Edit, kind: EditKind, time: f64) -> bool {
+        if kind != self.kind || kind == EditKind::Other || time - self.time > GROUP_TIMEOUT_SECS {
+            return false;
+        }
+        let Some(last) = self.edits.last_mut() else {
+            return false;
+        };
+        match kind {
+            EditKind::Typing => {
+                let end = last.at + last.inserted.chars().count();
+                // Start a new step at word boundaries
+                let starts_word = edit.inserted.starts_with(char::is_whitespace)
+                    && !last.inserted.ends_with(char::is_whitespace);
+                if edit.at != end || starts_word {
+                    return false;
+                }
+                last.inserted.push_str(&edit.inserted);
+            }
+            EditKind::Deleting => {
+                if edit.at + 1 == last.at {
+                    // Backspace
+                    last.at = edit.at;
+                    last.removed.insert_str(0, &edit.removed);
+                } else if edit.at == last.at {
+                    // Delete
+                    last.removed.push_str(&edit.removed);
+                } else {
+                    return false;
+                }
+            }
+            EditKind::Other => return false,
+        }
+        self.time = time;
+        true
+    }
+}
+
+/// Editor-owned undo/redo history of a document.
+pub struct UndoHistory {
+    undo: Vec<Transaction>,
+    redo: Vec<Transaction>,
+    max_depth: usize,
+    next_id: u64,
+    sealed: bool,
+}
+
+impl UndoHistory {
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            max_depth: max_depth.max(1),
+            next_id: 1,
+            sealed: false,
+        }
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth.max(1);
+        self.trim();
+    }
+
+    /// Identifies the current state of the text, used to tell whether
+    /// undoing brought the document back to its saved contents.
+    pub fn state_id(&self) -> u64 {
+        self.undo.last().map_or(0, |t| t.id)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Prevents the next edit from being merged into the current undo step,
+    /// e.g. after the caret was moved or the document was saved.
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    /// Records already-applied `edits` as one undo step, merging it into the
+    /// previous step when both are part of the same run of typing or deleting.
+    pub fn record(
+        &mut self,
+        edits: Vec<Edit>,
+        kind: EditKind,
+        before: Selection,
+        after: Selection,
+        time: f64,
+    ) {
+        if edits.is_empty() {
+            return;
+        }
+        self.redo.clear();
+
+        if !self.sealed {
+            if let Some(last) = self.undo.last_mut() {
+                if last.merge(&edits[0], kind, time) {
+                    last.after = after;
+                    return;
+                }
+            }
+        }
+
+        self.sealed = false;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.undo.push(Transaction {
+            id,
+            edits,
+            before,
+            after,
+            kind,
+            time,
+        });
+        self.trim();
+    }
+
+    /// Reverts the last step on `text`, returning the selection to restore.
+    pub fn undo(&mut self, text: &mut (impl TextStorage + ?Sized)) -> Option<Selection> {
+        let transaction = self.undo.pop()?;
+        revert_all(text, &transaction.edits);
+        let selection = transaction.before;
+        self.redo.push(transaction);
+        self.sealed = true;
+        Some(selection)
+    }
+
+    /// Re-applies the last undone step on `text`, returning the selection to restore.
+    pub fn redo(&mut self, text: &mut (impl TextStorage + ?Sized)) -> Option<Selection> {
+        let transaction = self.redo.pop()?;
+        apply_all(text, &transaction.edits);
+        let selection = transaction.after;
+        self.undo.push(transaction);
+        self.sealed = true;
+        Some(selection)
+    }
+
+    /// Forgets every step, for when the text was replaced too wholesale to keep a copy.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.sealed = false;
+    }
+
+    fn trim(&mut self) {
+        if self.undo.len() > self.max_depth {
+            let excess = self.undo.len() - self.max_depth;
+            self.undo.drain(..excess);
+        }
+    }
+}
+
+/// Wraps a document's text while it is handed to `egui::TextEdit`, so that
+/// every mutation the widget makes is captured for the undo history.
+pub struct RecordingBuffer<'a> {
+    text: &'a mut String,
+    pub edits: Vec<Edit>,
+}
+
+impl<'a> RecordingBuffer<'a> {
+    pub fn new(text: &'a mut String) -> Self {
+        Self {
+            text,
+            edits: Vec::new(),
+        }
+    }
+}
+
+impl TextBuffer for RecordingBuffer<'_> {
+    fn is_mutable(&self) -> bool {
+        true
+    }
+
+    fn as_str(&self) -> &str {
+        self.text.as_str()
+    }
+
+    fn insert_text(&mut self, text: &str, char_index: usize) -> usize {
+        if text.is_empty() {
+            return 0;
+        }
+        let inserted = TextBuffer::insert_text(self.text, text, char_index);
+        self.edits.push(Edit::new(char_index, "", text));
+        inserted
+    }
+
+    fn delete_char_range(&mut self, char_range: Range<usize>) {
+        if char_range.is_empty() {
+            return;
+        }
+        let removed = TextBuffer::char_range(self.text, char_range.clone()).to_owned();
+        TextBuffer::delete_char_range(self.text, char_range.clone());
+        self.edits.push(Edit::new(char_range.start, removed, ""));
+    }
+
+    fn clear(&mut self) {
+        let len = self.text.chars().count();
+        self.delete_char_range(0..len);
+    }
+}
+
+pub fn byte_index(text: &str, char_index: usize) -> usize {
+    text.char_indices()
+        .nth(char_index)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies `edit` to `text` and records it the way the editor does.
+    fn record(history: &mut UndoHistory, text: &mut String, edit: Edit, time: f64) {
+        let kind = EditKind::classify(std::slice::from_ref(&edit));
+        let before = Selection::caret(edit.at + edit.removed.chars().count());
+        let after = Selection::caret(edit.at + edit.inserted.chars().count());
+        edit.apply(text);
+        history.record(vec![edit], kind, before, after, time);
+    }
+
+    fn type_text(history: &mut UndoHistory, text: &mut String, at: usize, typed: &str, time: f64) {
+        for (i, c) in typed.chars().enumerate() {
+            record(history, text, Edit::new(at + i, "", c.to_string()), time + i as f64 * 0.1);
+        }
+    }
+
+    #[test]
+    fn typing_is_grouped_by_word() {
+        let mut history = UndoHistory::new(100);
+        let mut text = String::new();
+        type_text(&mut history, &mut text, 0, "one two", 0.0);
+        assert_eq!(text, "one two");
+        history.undo(&mut text);
+        assert_eq!(text, "one");
+        history.undo(&mut text);
+        assert_eq!(text, "");
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn pauses_and_seals_start_new_steps() {
+        let mut history = UndoHistory::new(100);
+        let mut text = String::new();
+        type_text(&mut history, &mut text, 0, "ab", 0.0);
+        type_text(&mut history, &mut text, 2, "cd", 5.0);
+        history.seal();
+        type_text(&mut history, &mut text, 4, "ef", 5.5);
+        history.undo(&mut text);
+        assert_eq!(text, "abcd");
+        history.undo(&mut text);
+        assert_eq!(text, "ab");
+    }
+
+    #[test]
+    fn backspace_and_delete_are_grouped() {
+        let mut history = UndoHistory::new(100);
+        let mut text = "abcdef".to_string();
+        for (i, at) in [3, 2, 1].into_iter().enumerate() {
+            let removed = text.chars().nth(at).unwrap().to_string();
+            record(&mut history, &mut text, Edit::new(at, removed, ""), i as f64 * 0.1);
+        }
+        assert_eq!(text, "aef");
+        history.seal();
+        for i in 0..2 {
+            let removed = text.chars().nth(1).unwrap().to_string();
+            record(&mut history, &mut text, Edit::new(1, removed, ""), 1.0 + i as f64 * 0.1);
+        }
+        assert_eq!(text, "a");
+        history.undo(&mut text);
+        assert_eq!(text, "aef");
+        history.undo(&mut text);
+        assert_eq!(text, "abcdef");
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn undo_redo_round_trip() {
+        let mut history = UndoHistory::new(100);
+        let mut text = "héllo world".to_string();
+        let saved = history.state_id();
+        let edits = vec![Edit::new(0, "héllo", "bye"), Edit::new(4, "world", "wörld!")];
+        apply_all(&mut text, &edits);
+        let (before, after) = (Selection::caret(0), Selection::caret(10));
+        history.record(edits, EditKind::Other, before, after, 0.0);
+        assert_eq!(text, "bye wörld!");
+        assert_ne!(history.state_id(), saved);
+
+        assert_eq!(history.undo(&mut text), Some(before));
+        assert_eq!(text, "héllo world");
+        assert_eq!(history.state_id(), saved);
+        assert_eq!(history.redo(&mut text), Some(after));
+        assert_eq!(text, "bye wörld!");
+        assert_eq!(history.redo(&mut text), None);
+
+        // A new edit after an undo drops the redo steps
+        history.undo(&mut text);
+        type_text(&mut history, &mut text, 0, "x", 1.0);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn history_is_trimmed_to_max_depth() {
+        let mut history = UndoHistory::new(3);
+        let mut text = String::new();
+        for i in 0..5 {
+            history.seal();
+            type_text(&mut history, &mut text, i, "x", i as f64);
+        }
+        let mut undone = 0;
+        while history.undo(&mut text).is_some() {
+            undone += 1;
+        }
+        assert_eq!((undone, text.as_str()), (3, "xx"));
+
+        history.set_max_depth(1);
+        assert!(history.redo(&mut text).is_some());
+        history.set_max_depth(0);
+        assert!(history.can_undo());
+    }
+
+    /// Edits both passes of `apply_all` and `revert_all` go through: the single pass for
+    /// runs that move forward, one at a time when a later edit lands in an earlier one.
+    fn edit_runs() -> [Vec<Edit>; 2] {
+        [
+            vec![Edit::new(0, "ab", "x"), Edit::new(2, "d", "yyy"), Edit::new(6, "f", "")],
+            vec![Edit::new(2, "cd", "123"), Edit::new(3, "2", "")],
+        ]
+    }
+
+    #[test]
+    fn apply_and_revert_on_every_storage() {
+        let expected = ["xcyyye", "ab13ef"];
+        for (edits, expected) in edit_runs().iter().zip(expected) {
+            let mut text = "abcdef".to_string();
+            apply_all(&mut text, edits);
+            assert_eq!(text, expected);
+            revert_all(&mut text, edits);
+            assert_eq!(text, "abcdef");
+
+            let mut rope = Rope::from_str("abcdef");
+            apply_all(&mut rope, edits);
+            assert_eq!(rope.to_string(), expected);
+            revert_all(&mut rope, edits);
+            assert_eq!(rope.to_string(), "abcdef");
+
+            let mut bytes = b"abcdef".to_vec();
+            apply_all(&mut bytes, edits);
+            assert_eq!(bytes, expected.as_bytes());
+            revert_all(&mut bytes, edits);
+            assert_eq!(bytes, b"abcdef");
+        }
+    }
+
+    #[test]
+    fn many_replacements_on_multibyte_text() {
+        let original = "é-".repeat(1500);
+        let edits: Vec<Edit> = (0..1500).map(|i| Edit::new(i * 3, "é", "ab")).collect();
+        let expected = "ab-".repeat(1500);
+
+        let mut text = original.clone();
+        apply_all(&mut text, &edits);
+        assert_eq!(text, expected);
+        revert_all(&mut text, &edits);
+        assert_eq!(text, original);
+
+        // Enough replacements for the rope to be rebuilt rather than edited in place
+        let mut rope = Rope::from_str(&original);
+        apply_all(&mut rope, &edits);
+        assert_eq!(rope.to_string(), expected);
+        revert_all(&mut rope, &edits);
+        assert_eq!(rope.to_string(), original);
+    }
+
+    #[test]
+    fn recording_buffer_captures_widget_edits() {
+        let mut text = "héllo".to_string();
+        let mut buffer = RecordingBuffer::new(&mut text);
+        buffer.insert_text("!", 5);
+        buffer.delete_char_range(0..2);
+        buffer.insert_text("", 0);
+        let edits = buffer.edits;
+        assert_eq!(text, "llo!");
+        revert_all(&mut text, &edits);
+        assert_eq!(text, "héllo");
+    }
+
+    #[test]
+    fn char_to_byte_index() {
+        assert_eq!(byte_index("aé€b", 0), 0);
+        assert_eq!(byte_index("aé€b", 2), 3);
+        assert_eq!(byte_index("aé€b", 3), 6);
+        assert_eq!(byte_index("aé€b", 4), 7);
+        assert_eq!(byte_index("aé€b", 10), 7);
+    }
+}
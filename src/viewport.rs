@@ -0,0 +1,484 @@
+use crate::document::{Document, Selection};
+use crate::editorconfig::EditorConfig;
+use crate::gutter::{self, GutterStyle};
+use crate::indent::{self, IndentOptions, Lines};
+use crate::undo::Edit;
+use eframe::egui::{self, Align2, Color32, Event, EventFilter, FontId, Id, Key, Rect, Sense, Stroke, Ui};
+use ropey::Rope;
+use std::ops::Range;
+
+const SCROLLBAR_WIDTH: f32 = 10.0;
+
+pub struct ViewportStyle {
+    pub font_id: FontId,
+    pub text_color: Color32,
+    pub selection: Color32,
+    pub match_color: Color32,
+    pub scrollbar: Color32,
+    /// Color of the guide line at `.editorconfig`'s `max_line_length`.
+    pub ruler: Color32,
+    pub gutter: Option<GutterStyle>,
+}
+
+/// Where a document's viewport is scrolled to, counted in lines and columns:
+/// pixel offsets lose precision over millions of lines.
+#[derive(Debug, Clone, Copy, Default)]
+struct ScrollState {
+    top: usize,
+    left: usize,
+    /// Widest line seen so far, which sizes the horizontal scroll bar.
+    widest: usize,
+}
+
+/// Shows and edits a document in large-file mode. Only the lines in view are laid
+/// out, and every column is one glyph wide, so the cost of a frame doesn't depend on
+/// the size of the file. Lines aren't wrapped and there is no syntax highlighting.
+/// `matches` are the sorted byte ranges of search matches to mark.
+pub fn show(
+    ui: &mut Ui,
+    doc: &mut Document,
+    style: &ViewportStyle,
+    indent: &IndentOptions,
+    matches: &[Range<usize>],
+) {
+    let id = doc.editor_id();
+    let tab = doc.indentation.width.max(1);
+    let ruler = doc.editorconfig.as_ref().and_then(EditorConfig::max_line_length);
+    let time = ui.input(|i| i.time);
+    let row_height = ui.fonts(|f| f.row_height(&style.font_id));
+    let char_width = ui.fonts(|f| f.glyph_width(&style.font_id, '0'));
+    let line_count = doc.line_count();
+
+    let rect = ui.available_rect_before_wrap();
+    ui.allocate_rect(rect, Sense::hover());
+    let gutter_width = match style.gutter {
+        Some(_) => gutter::width(ui, &style.font_id, line_count),
+        None => 0.0,
+    };
+    let gutter_rect = Rect::from_min_max(rect.min, egui::pos2(rect.min.x + gutter_width, rect.max.y));
+    let text_rect = Rect::from_min_max(
+        egui::pos2(gutter_rect.max.x, rect.min.y),
+        egui::pos2(rect.max.x - SCROLLBAR_WIDTH, rect.max.y - SCROLLBAR_WIDTH),
+    );
+    let rows = ((text_rect.height() / row_height) as usize).max(1);
+    let cols = ((text_rect.width() / char_width) as usize).max(1);
+
+    let response = ui.interact(text_rect, id, Sense::click_and_drag());
+    let state_id = id.with("scroll");
+    let mut state: ScrollState = ui.data(|d| d.get_temp(state_id)).unwrap_or_default();
+    let Some(rope) = doc.large.as_mut() else {
+        return;
+    };
+
+    let mut cursor = doc.cursor;
+    let mut edits = Vec::new();
+    let mut follow_cursor = false;
+
+    if response.hovered() {
+        let delta = ui.input(|i| i.raw_scroll_delta);
+        state.top = scroll_by(state.top, delta.y / row_height);
+        state.left = scroll_by(state.left, delta.x / char_width);
+    }
+
+    if let Some(pos) = response.interact_pointer_pos() {
+        let row = ((pos.y - text_rect.top()) / row_height).floor() as isize;
+        let line = (state.top as isize + row).clamp(0, line_count as isize - 1) as usize;
+        let column = state.left as f32 + (pos.x - text_rect.left()) / char_width;
+        let index = index_at_column(rope, line, column, tab);
+        let (pressed, shift) = ui.input(|i| (i.pointer.any_pressed(), i.modifiers.shift));
+        if response.double_clicked() {
+            cursor = word_at(rope, index);
+        } else if pressed && !shift {
+            cursor = Selection::caret(index);
+        } else {
+            cursor.head = index;
+        }
+        follow_cursor = true;
+        response.request_focus();
+    }
+
+    if response.has_focus() {
+        ui.memory_mut(|m| {
+            let filter = EventFilter {
+                tab: true,
+                horizontal_arrows: true,
+                vertical_arrows: true,
+                escape: false,
+            };
+            m.set_focus_lock_filter(id, filter);
+        });
+        for event in ui.input(|i| i.events.clone()) {
+            let cursor_before = cursor;
+            match event {
+                Event::Text(text) => replace_selection(rope, &mut cursor, &text, &mut edits),
+                Event::Paste(text) => {
+                    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+                    replace_selection(rope, &mut cursor, &text, &mut edits);
+                }
+                Event::Copy if !cursor.is_empty() => {
+                    ui.ctx().copy_text(rope.slice(cursor.start()..cursor.end()).to_string());
+                }
+                Event::Cut if !cursor.is_empty() => {
+                    ui.ctx().copy_text(rope.slice(cursor.start()..cursor.end()).to_string());
+                    replace_selection(rope, &mut cursor, "", &mut edits);
+                }
+                Event::Key {
+                    key,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } => {
+                    let (len, line_count) = (rope.len_chars(), rope.len_lines());
+                    let head = cursor.head;
+                    let line = rope.char_to_line(head);
+                    let column = head - rope.line_to_char(line);
+                    let at_line = |line: usize| {
+                        let line = line.min(line_count - 1);
+                        rope.line_to_char(line) + column.min(line_length(rope, line))
+                    };
+                    let moved = match key {
+                        Key::ArrowLeft if modifiers.command => Some(word_boundary(rope, head, false)),
+                        Key::ArrowRight if modifiers.command => Some(word_boundary(rope, head, true)),
+                        Key::ArrowLeft if !cursor.is_empty() && !modifiers.shift => Some(cursor.start()),
+                        Key::ArrowRight if !cursor.is_empty() && !modifiers.shift => Some(cursor.end()),
+                        Key::ArrowLeft => Some(head.saturating_sub(1)),
+                        Key::ArrowRight => Some((head + 1).min(len)),
+                        Key::ArrowUp => Some(if line == 0 { 0 } else { at_line(line - 1) }),
+                        Key::ArrowDown if line + 1 == line_count => Some(len),
+                        Key::ArrowDown => Some(at_line(line + 1)),
+                        Key::PageUp => Some(at_line(line.saturating_sub(rows))),
+                        Key::PageDown => Some(at_line(line + rows)),
+                        Key::Home if modifiers.command => Some(0),
+                        Key::End if modifiers.command => Some(len),
+                        Key::Home => Some(rope.line_to_char(line)),
+                        Key::End => Some(rope.line_to_char(line) + line_length(rope, line)),
+                        Key::A if modifiers.command => {
+                            cursor = Selection { anchor: 0, head: len };
+                            None
+                        }
+                        _ => None,
+                    };
+                    if let Some(index) = moved {
+                        if modifiers.shift {
+                            cursor.head = index;
+                        } else {
+                            cursor = Selection::caret(index);
+                        }
+                    } else if !modifiers.command {
+                        match key {
+                            Key::Enter if indent.auto_indent => {
+                                let (text, start) = lines_around(rope, cursor);
+                                let lines = Lines { text: &text, start };
+                                let (changes, after) = indent::newline(&lines, cursor, indent);
+                                apply(rope, &mut cursor, changes, after, &mut edits);
+                            }
+                            Key::Enter => replace_selection(rope, &mut cursor, "\n", &mut edits),
+                            Key::Tab => {
+                                let (text, start) = lines_around(rope, cursor);
+                                let lines = Lines { text: &text, start };
+                                let (changes, after) = indent::tab(&lines, cursor, indent.indentation, modifiers.shift);
+                                apply(rope, &mut cursor, changes, after, &mut edits);
+                            }
+                            Key::Backspace => {
+                                if cursor.is_empty() && head > 0 {
+                                    cursor.anchor = head - 1;
+                                }
+                                replace_selection(rope, &mut cursor, "", &mut edits);
+                            }
+                            Key::Delete => {
+                                if cursor.is_empty() && head < len {
+                                    cursor.anchor = head + 1;
+                                }
+                                replace_selection(rope, &mut cursor, "", &mut edits);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+            follow_cursor |= cursor != cursor_before;
+        }
+    }
+
+    let line_count = rope.len_lines();
+    let max_top = line_count.saturating_sub(rows);
+    if std::mem::take(&mut doc.reveal_cursor) {
+        state.top = rope.char_to_line(cursor.head).saturating_sub(rows / 2);
+        follow_cursor = true;
+        response.request_focus();
+    }
+    if follow_cursor {
+        let line = rope.char_to_line(cursor.head);
+        if line < state.top {
+            state.top = line;
+        } else if line >= state.top + rows {
+            state.top = line + 1 - rows;
+        }
+        let column = column_of(rope, cursor.head, tab);
+        if column < state.left {
+            state.left = column;
+        } else if column >= state.left + cols {
+            state.left = column + 1 - cols;
+        }
+    }
+    state.top = state.top.min(max_top);
+
+    let painter = ui.painter_at(rect);
+    let bottom = (state.top + rows + 1).min(line_count);
+    let current_line = rope.char_to_line(cursor.head);
+    if let Some(gutter_style) = &style.gutter {
+        painter.rect_filled(gutter_rect, 0.0, gutter_style.background);
+    }
+    if let Some(column) = ruler.filter(|&column| column >= state.left) {
+        let x = text_rect.left() + (column - state.left) as f32 * char_width;
+        painter.vline(x, text_rect.y_range(), Stroke::new(1.0, style.ruler));
+    }
+
+    // Search matches in view, converted to character ranges
+    let visible_bytes = rope.line_to_byte(state.top)..rope.line_to_byte(bottom);
+    let first = matches.partition_point(|m| m.end < visible_bytes.start);
+    let marks: Vec<Range<usize>> = matches[first..]
+        .iter()
+        .take_while(|m| m.start <= visible_bytes.end)
+        .filter(|m| m.end <= rope.len_bytes())
+        .map(|m| rope.byte_to_char(m.start)..rope.byte_to_char(m.end))
+        .collect();
+
+    for line in state.top..bottom {
+        let y = text_rect.top() + (line - state.top) as f32 * row_height;
+        if let Some(gutter_style) = &style.gutter {
+            gutter::paint_number(&painter, gutter_rect, y, line, current_line, gutter_style);
+        }
+
+        let line_start = rope.line_to_char(line);
+        let line_end = line_start + line_length(rope, line);
+        let highlight = |range: &Range<usize>, color: Color32| {
+            if range.end <= line_start || range.start > line_end || range.is_empty() {
+                return;
+            }
+            let start = column_of(rope, range.start.max(line_start), tab);
+            // A range running on to the next line covers the line break too
+            let end = column_of(rope, range.end.min(line_end), tab) + usize::from(range.end > line_end);
+            let x = |column: usize| text_rect.left() + (column as f32 - state.left as f32) * char_width;
+            let band = Rect::from_x_y_ranges(x(start)..=x(end), y..=y + row_height);
+            painter.rect_filled(band.intersect(text_rect), 0.0, color);
+        };
+        for mark in &marks {
+            highlight(mark, style.match_color);
+        }
+        highlight(&(cursor.start()..cursor.end()), style.selection);
+
+        let (text, width) = visible_text(rope, line, state.left, cols + 1, tab);
+        state.widest = state.widest.max(width);
+        painter.text(
+            egui::pos2(text_rect.left(), y),
+            Align2::LEFT_TOP,
+            text,
+            style.font_id.clone(),
+            style.text_color,
+        );
+    }
+
+    if response.has_focus() && (state.top..bottom).contains(&current_line) {
+        let column = column_of(rope, cursor.head, tab);
+        if column >= state.left {
+            let x = text_rect.left() + (column - state.left) as f32 * char_width;
+            let y = text_rect.top() + (current_line - state.top) as f32 * row_height;
+            painter.vline(x, y..=y + row_height, Stroke::new(2.0, style.text_color));
+        }
+    }
+
+    let vertical = Rect::from_min_max(
+        egui::pos2(text_rect.right(), rect.top()),
+        egui::pos2(rect.right(), text_rect.bottom()),
+    );
+    let horizontal = Rect::from_min_max(
+        egui::pos2(text_rect.left(), text_rect.bottom()),
+        egui::pos2(text_rect.right(), rect.bottom()),
+    );
+    state.top = scrollbar(ui, id.with("vertical"), vertical, true, state.top, rows, line_count, style.scrollbar);
+    state.left = scrollbar(ui, id.with("horizontal"), horizontal, false, state.left, cols, state.widest, style.scrollbar);
+    ui.data_mut(|d| d.insert_temp(state_id, state));
+
+    doc.sync_cursor = false;
+    if !edits.is_empty() {
+        doc.record_edits(edits, cursor, time);
+    } else if cursor != doc.cursor {
+        // Moving the caret ends the current run of typing
+        doc.history.seal();
+        doc.cursor = cursor;
+    }
+}
+
+/// Moves a scroll position by `amount` lines or columns.
+fn scroll_by(position: usize, amount: f32) -> usize {
+    position.saturating_add_signed(-wheel_steps(amount))
+}
+
+/// Whole lines or columns for a scroll wheel movement of `amount`, at least one when there is any movement.
+pub fn wheel_steps(amount: f32) -> isize {
+    match amount.round() {
+        0.0 if amount != 0.0 => amount.signum() as isize,
+        steps => steps as isize,
+    }
+}
+
+/// Replaces the selection with `text`, recording the change in `edits`.
+fn replace_selection(rope: &mut Rope, cursor: &mut Selection, text: &str, edits: &mut Vec<Edit>) {
+    let range = cursor.start()..cursor.end();
+    if range.is_empty() && text.is_empty() {
+        return;
+    }
+    let removed = rope.slice(range.clone()).to_string();
+    rope.remove(range.clone());
+    rope.insert(range.start, text);
+    edits.push(Edit::new(range.start, removed, text));
+    *cursor = Selection::caret(range.start + text.chars().count());
+}
+
+/// Applies `changes` worked out elsewhere, recording them in `edits`.
+fn apply(rope: &mut Rope, cursor: &mut Selection, changes: Vec<Edit>, after: Selection, edits: &mut Vec<Edit>) {
+    for edit in &changes {
+        edit.apply(rope);
+    }
+    edits.extend(changes);
+    *cursor = after;
+}
+
+/// The whole lines `selection` touches and the index of their first character.
+fn lines_around(rope: &Rope, selection: Selection) -> (String, usize) {
+    let first = rope.char_to_line(selection.start());
+    let last = rope.char_to_line(selection.end());
+    let start = rope.line_to_char(first);
+    let end = rope.line_to_char(last) + line_length(rope, last);
+    (rope.slice(start..end).to_string(), start)
+}
+
+/// Number of characters on `line`, not counting its line break.
+fn line_length(rope: &Rope, line: usize) -> usize {
+    let slice = rope.line(line);
+    let len = slice.len_chars();
+    if len > 0 && slice.char(len - 1) == '\n' {
+        len - 1
+    } else {
+        len
+    }
+}
+
+pub fn advance(column: usize, c: char, tab: usize) -> usize {
+    match c {
+        '\t' => (column / tab + 1) * tab,
+        _ => column + 1,
+    }
+}
+
+/// Screen column of character `index`, with tabs expanded.
+fn column_of(rope: &Rope, index: usize, tab: usize) -> usize {
+    let line_start = rope.line_to_char(rope.char_to_line(index));
+    rope.slice(line_start..index)
+        .chars()
+        .fold(0, |column, c| advance(column, c, tab))
+}
+
+/// The character nearest to the fractional screen `column` of `line`.
+fn index_at_column(rope: &Rope, line: usize, column: f32, tab: usize) -> usize {
+    let line_start = rope.line_to_char(line);
+    let mut current = 0;
+    for (i, c) in rope.line(line).chars().enumerate() {
+        if c == '\n' {
+            return line_start + i;
+        }
+        let next = advance(current, c, tab);
+        if column < (current + next) as f32 / 2.0 {
+            return line_start + i;
+        }
+        current = next;
+    }
+    line_start + line_length(rope, line)
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The word around `index`, selected on double click.
+fn word_at(rope: &Rope, index: usize) -> Selection {
+    Selection {
+        anchor: index - rope.chars_at(index).reversed().take_while(|&c| is_word(c)).count(),
+        head: index + rope.chars_at(index).take_while(|&c| is_word(c)).count(),
+    }
+}
+
+/// Where Ctrl+Left/Right moves to: the end of the run of word or other characters next to `index`.
+fn word_boundary(rope: &Rope, index: usize, forward: bool) -> usize {
+    if forward {
+        let mut chars = rope.chars_at(index).peekable();
+        let word = chars.peek().is_some_and(|&c| is_word(c));
+        index + chars.take_while(|&c| is_word(c) == word && c != '\n').count().max(1).min(rope.len_chars() - index)
+    } else {
+        let mut chars = rope.chars_at(index).reversed().peekable();
+        let word = chars.peek().is_some_and(|&c| is_word(c));
+        index - chars.take_while(|&c| is_word(c) == word && c != '\n').count().max(1).min(index)
+    }
+}
+
+/// Up to `width` screen columns of `line` starting at column `left`, with tabs
+/// expanded, plus how wide the line is as far as it was read.
+fn visible_text(rope: &Rope, line: usize, left: usize, width: usize, tab: usize) -> (String, usize) {
+    let right = left + width;
+    let mut text = String::new();
+    let mut column = 0;
+    for c in rope.line(line).chars() {
+        if c == '\n' || column >= right {
+            break;
+        }
+        let next = advance(column, c, tab);
+        if next > left {
+            match c {
+                '\t' => text.extend(std::iter::repeat_n(' ', next.min(right) - column.max(left))),
+                _ => text.push(c),
+            }
+        }
+        column = next;
+    }
+    (text, column)
+}
+
+/// A scroll bar over `track` for `total` lines or columns, `visible` of them shown
+/// from `position`. Returns the position after the user dragged or clicked it.
+#[allow(clippy::too_many_arguments)]
+pub fn scrollbar(
+    ui: &Ui,
+    id: Id,
+    track: Rect,
+    vertical: bool,
+    position: usize,
+    visible: usize,
+    total: usize,
+    color: Color32,
+) -> usize {
+    let max = total.saturating_sub(visible);
+    if max == 0 {
+        return 0;
+    }
+    let length = if vertical { track.height() } else { track.width() };
+    let handle = (length * visible as f32 / total as f32).max(20.0).min(length);
+    let response = ui.interact(track, id, Sense::click_and_drag());
+    let mut position = position.min(max);
+    if let Some(pointer) = response.interact_pointer_pos() {
+        let along = if vertical { pointer.y - track.top() } else { pointer.x - track.left() };
+        let fraction = ((along - handle / 2.0) / (length - handle).max(1.0)).clamp(0.0, 1.0);
+        position = (fraction as f64 * max as f64).round() as usize;
+    }
+
+    let start = (length - handle) * (position as f64 / max as f64) as f32;
+    let handle_rect = if vertical {
+        Rect::from_min_size(egui::pos2(track.left() + 2.0, track.top() + start), egui::vec2(track.width() - 4.0, handle))
+    } else {
+        Rect::from_min_size(egui::pos2(track.left() + start, track.top() + 2.0), egui::vec2(handle, track.height() - 4.0))
+    };
+    let alpha = if response.hovered() || response.dragged() { 1.0 } else { 0.6 };
+    ui.painter().rect_filled(handle_rect, 3.0, color.gamma_multiply(alpha));
+    position
+}
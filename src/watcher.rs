@@ -0,0 +1,62 @@
+use eframe::egui;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+/// Watches the directories of open files and reports which files changed. Directories
+/// are watched rather than the files themselves, because tools like git replace a
+/// file by renaming a new one over it.
+pub struct FileWatcher {
+    watcher: Option<RecommendedWatcher>,
+    receiver: Receiver<PathBuf>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl FileWatcher {
+    /// `ctx` is woken whenever something changes, so changes show up without other input.
+    pub fn new(ctx: egui::Context) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                return;
+            }
+            for path in event.paths {
+                let _ = sender.send(path);
+            }
+            ctx.request_repaint();
+        })
+        .ok();
+        Self {
+            watcher,
+            receiver,
+            dirs: HashSet::new(),
+        }
+    }
+
+    /// Watches the directories of `files`, dropping directories no longer needed.
+    pub fn watch<'a>(&mut self, files: impl Iterator<Item = &'a Path>) {
+        let Some(watcher) = &mut self.watcher else {
+            return;
+        };
+        let wanted: HashSet<PathBuf> = files
+            .filter_map(Path::parent)
+            .map(Path::to_path_buf)
+            .collect();
+        for dir in self.dirs.difference(&wanted) {
+            let _ = watcher.unwatch(dir);
+        }
+        for dir in wanted.difference(&self.dirs) {
+            let _ = watcher.watch(dir, RecursiveMode::NonRecursive);
+        }
+        self.dirs = wanted;
+    }
+
+    /// Paths that changed since the last call.
+    pub fn changed(&self) -> HashSet<PathBuf> {
+        self.receiver.try_iter().collect()
+    }
+}